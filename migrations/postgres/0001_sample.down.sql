@@ -0,0 +1 @@
+DROP TABLE IF EXISTS sample
\ No newline at end of file
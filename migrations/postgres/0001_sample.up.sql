@@ -0,0 +1,3 @@
+CREATE TABLE IF NOT EXISTS sample(
+                id BIGSERIAL PRIMARY KEY,
+                name text)
\ No newline at end of file
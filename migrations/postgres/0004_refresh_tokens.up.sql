@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS refresh_tokens(
+                id TEXT PRIMARY KEY,
+                family TEXT NOT NULL,
+                user_id BIGINT NOT NULL,
+                expires_at BIGINT NOT NULL,
+                used_at BIGINT);
+CREATE INDEX IF NOT EXISTS refresh_tokens_family ON refresh_tokens(family);
+CREATE INDEX IF NOT EXISTS refresh_tokens_expires_at ON refresh_tokens(expires_at)
\ No newline at end of file
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS user_roles(
+                user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                role TEXT NOT NULL,
+                PRIMARY KEY (user_id, role));
+CREATE TABLE IF NOT EXISTS role_permissions(
+                role TEXT NOT NULL,
+                permission TEXT NOT NULL,
+                PRIMARY KEY (role, permission));
+INSERT INTO role_permissions(role, permission) VALUES
+                ('user', 'sample:read'),
+                ('user', 'voting:vote'),
+                ('admin', 'sample:read'),
+                ('admin', 'sample:write'),
+                ('admin', 'voting:vote')
+                ON CONFLICT DO NOTHING
\ No newline at end of file
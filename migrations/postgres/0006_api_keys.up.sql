@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS api_keys(
+                id BIGSERIAL PRIMARY KEY,
+                name TEXT NOT NULL,
+                prefix TEXT NOT NULL,
+                key_hash TEXT NOT NULL UNIQUE,
+                scopes JSONB NOT NULL,
+                created_at BIGINT NOT NULL,
+                expires_at BIGINT)
\ No newline at end of file
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS user_identities(
+                provider TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                PRIMARY KEY (provider, subject))
\ No newline at end of file
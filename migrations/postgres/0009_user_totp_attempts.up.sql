@@ -0,0 +1 @@
+ALTER TABLE user_totp ADD COLUMN failed_attempts BIGINT NOT NULL DEFAULT 0
\ No newline at end of file
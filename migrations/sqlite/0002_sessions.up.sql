@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS sessions(
+                id TEXT PRIMARY KEY,
+                user_id BIGINT,
+                value TEXT NOT NULL,
+                expires_at BIGINT NOT NULL);
+CREATE INDEX IF NOT EXISTS sessions_user_id ON sessions(user_id);
+CREATE INDEX IF NOT EXISTS sessions_expires_at ON sessions(expires_at)
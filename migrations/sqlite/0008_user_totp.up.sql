@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS user_totp(
+                user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+                secret TEXT NOT NULL,
+                confirmed_at BIGINT,
+                last_step BIGINT);
+CREATE TABLE IF NOT EXISTS user_recovery_codes(
+                user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                code_hash TEXT NOT NULL,
+                PRIMARY KEY (user_id, code_hash))
\ No newline at end of file
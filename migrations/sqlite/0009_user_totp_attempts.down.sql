@@ -0,0 +1 @@
+ALTER TABLE user_totp DROP COLUMN failed_attempts
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::env;
+use std::net::IpAddr;
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -8,7 +9,17 @@ use axum::{
 };
 use tokio::sync::RwLock;
 
-use crate::{diagnostics, session_impl, util::config::TomlConfig};
+use crate::{
+    authz::Authenticator,
+    diagnostics,
+    metrics::Metrics,
+    migration, oidc_impl, session_impl, token_impl,
+    usecase::ApiKeyUsecase,
+    util::{
+        config::{MfaConfig, TomlConfig},
+        shutdown::Shutdown,
+    },
+};
 
 #[cfg(feature = "enable_websocket_pubsub_sample")]
 use crate::ws::pubsub::PubSubState;
@@ -32,15 +43,24 @@ mod db_impl {
 pub(crate) type DataBase = db_impl::DataBase;
 pub(crate) type DataBasePoolOptions = db_impl::PoolOptions;
 
-use bb8;
-use bb8_redis;
-
 pub(crate) type RedisPool = bb8::Pool<bb8_redis::RedisConnectionManager>;
 pub(crate) struct RedisConnection(
     pub bb8::PooledConnection<'static, bb8_redis::RedisConnectionManager>,
 );
 
 pub(crate) type SessionStoreImpl = session_impl::SessionStoreImpl;
+pub(crate) type SessionCookies = session_impl::SessionCookies;
+pub(crate) type SessionService = session_impl::SessionService;
+pub(crate) type JwtKeys = token_impl::JwtKeys;
+pub(crate) type TokenService = token_impl::TokenService;
+pub(crate) type OidcProviders = oidc_impl::OidcProviders;
+
+#[derive(Clone, Debug)]
+pub(crate) struct AdminToken(pub Option<Arc<str>>);
+
+/// `http.trusted_proxies`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TrustedProxies(pub Arc<[IpAddr]>);
 
 // https://docs.rs/axum/latest/axum/extract/struct.State.html
 #[derive(Clone, Debug)]
@@ -48,15 +68,28 @@ pub(crate) struct AppState {
     pub db_pool: Pool<DataBase>,
     pub redis_pool: RedisPool,
     pub session_store: SessionStoreImpl,
+    pub session_cookies: SessionCookies,
+    pub jwt_keys: JwtKeys,
+    pub oidc: OidcProviders,
+    pub mfa: MfaConfig,
     pub extentions: Arc<RwLock<Extensions>>,
+    pub shutdown: Shutdown,
+    pub metrics: Metrics,
+    pub admin_token: AdminToken,
+    pub trusted_proxies: TrustedProxies,
     #[cfg(feature = "enable_websocket_pubsub_sample")]
     pub pubsub: PubSubState,
 }
 
 impl AppState {
-    pub async fn new(config: &TomlConfig) -> Self {
+    /// `Err` when the jwt keys or the oidc providers can not be set up
+    pub async fn new(config: &TomlConfig) -> anyhow::Result<Self> {
+        // before anything is connected or created
+        let jwt_keys = JwtKeys::new(&config.jwt)?;
+        let oidc = OidcProviders::new(&config.oidc)?;
+
         #[cfg(feature = "use_sqlite")]
-        Self::sqlite_create_database(&config).await;
+        Self::sqlite_create_database(config).await;
 
         // for sqlx::query!
         env::set_var("DATABASE_URL", config.database.url.as_str());
@@ -65,27 +98,61 @@ impl AppState {
             .await
             .unwrap();
 
-        AppState {
-            db_pool: DataBasePoolOptions::new()
-                .max_connections(config.database.max_connection)
-                .connect(config.database.url.as_str())
-                .await
-                .expect("Unabled to Connect to Database"),
+        let db_pool = Self::connect_database(config).await;
+
+        Ok(AppState {
+            db_pool: db_pool.clone(),
 
             redis_pool: redis_pool.clone(),
 
-            session_store: SessionStoreImpl::new(redis_pool),
+            session_store: SessionStoreImpl::from_config(&config.session, db_pool, redis_pool),
+
+            session_cookies: SessionCookies::new(&config.session.cookie),
+
+            jwt_keys,
+
+            oidc,
+
+            mfa: config.mfa.clone(),
 
             extentions: Arc::new(RwLock::new(Extensions::default())),
 
+            shutdown: Shutdown::new(),
+
+            metrics: Metrics::new(),
+
+            admin_token: AdminToken(config.http.admin_token.as_deref().map(Arc::from)),
+            trusted_proxies: TrustedProxies(config.http.trusted_proxies.as_slice().into()),
+
             #[cfg(feature = "enable_websocket_pubsub_sample")]
             pubsub: PubSubState::new(),
-        }
+        })
+    }
+
+    pub async fn connect_database(config: &TomlConfig) -> Pool<DataBase> {
+        DataBasePoolOptions::new()
+            .max_connections(config.database.max_connection)
+            .connect(config.database.url.as_str())
+            .await
+            .expect("Unabled to Connect to Database")
     }
 
     pub async fn migrate_database(&self) -> diagnostics::Result<()> {
-        sqlx::migrate!("./migrations").run(&self.db_pool).await?;
-        Ok(())
+        migration::up(&self.db_pool).await
+    }
+
+    /// waits for checked out db connections to return and drops idle redis connections
+    pub async fn close(self) {
+        self.db_pool.close().await;
+        tracing::debug!("database pool closed");
+        let state = self.redis_pool.state();
+        drop(self.session_store);
+        drop(self.redis_pool);
+        tracing::debug!(
+            "redis pool released ({} connections, {} idle)",
+            state.connections,
+            state.idle_connections
+        );
     }
 
     #[cfg(feature = "use_sqlite")]
@@ -123,12 +190,66 @@ impl FromRef<AppState> for RedisPool {
     }
 }
 
+impl FromRef<AppState> for AdminToken {
+    fn from_ref(input: &AppState) -> Self {
+        input.admin_token.clone()
+    }
+}
+
+impl FromRef<AppState> for TrustedProxies {
+    fn from_ref(input: &AppState) -> Self {
+        input.trusted_proxies.clone()
+    }
+}
+
 impl FromRef<AppState> for SessionStoreImpl {
     fn from_ref(input: &AppState) -> Self {
         input.session_store.clone()
     }
 }
 
+impl FromRef<AppState> for SessionCookies {
+    fn from_ref(input: &AppState) -> Self {
+        input.session_cookies.clone()
+    }
+}
+
+impl FromRef<AppState> for SessionService {
+    fn from_ref(input: &AppState) -> Self {
+        SessionService::new(input.session_store.clone(), input.session_cookies.clone())
+    }
+}
+
+impl FromRef<AppState> for JwtKeys {
+    fn from_ref(input: &AppState) -> Self {
+        input.jwt_keys.clone()
+    }
+}
+
+impl FromRef<AppState> for OidcProviders {
+    fn from_ref(input: &AppState) -> Self {
+        input.oidc.clone()
+    }
+}
+
+impl FromRef<AppState> for MfaConfig {
+    fn from_ref(input: &AppState) -> Self {
+        input.mfa.clone()
+    }
+}
+
+impl FromRef<AppState> for Authenticator {
+    fn from_ref(input: &AppState) -> Self {
+        Authenticator::new(input.jwt_keys.clone(), ApiKeyUsecase::from_ref(input))
+    }
+}
+
+impl FromRef<AppState> for TokenService {
+    fn from_ref(input: &AppState) -> Self {
+        TokenService::new(input.jwt_keys.clone(), input.db_pool.clone())
+    }
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for RedisConnection
 where
@@ -0,0 +1,25 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// every key starts with it, so leaked keys are easy to spot
+pub(crate) const KEY_PREFIX: &str = "ak_";
+// characters of the key stored in the clear
+const DISPLAY_LEN: usize = KEY_PREFIX.len() + 8;
+
+/// a new key, 256 random bits
+pub(crate) fn generate() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    format!("{KEY_PREFIX}{}", hex::encode(bytes))
+}
+
+/// what is stored instead of the key. the keys are random enough that a plain digest
+/// can't be brute forced, unlike passwords they need no slow hash and can be looked up
+pub(crate) fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// start of the key that identifies it in listings
+pub(crate) fn display_prefix(key: &str) -> String {
+    key.chars().take(DISPLAY_LEN).collect()
+}
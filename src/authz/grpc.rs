@@ -0,0 +1,106 @@
+use std::{
+    convert::Infallible,
+    marker::PhantomData,
+    task::{Context, Poll},
+};
+
+use axum::http::{Request, Response};
+use futures::future::BoxFuture;
+use tonic::{body::BoxBody, server::NamedService, Status};
+use tower::{Layer, Service};
+
+use super::{Authenticator, Permission};
+use crate::{diagnostics::Error, util::request_id};
+
+/// layer for a grpc service whose methods need `P`. the caller sends an api key in the
+/// `x-api-key` metadata or an access token in `authorization`, the `Principal` is handed
+/// to the method in the request extensions. a layer and not a tonic interceptor because
+/// api keys are looked up in the database
+pub(crate) struct GrpcPermission<P> {
+    authenticator: Authenticator,
+    permission: PhantomData<fn() -> P>,
+}
+
+impl<P> GrpcPermission<P> {
+    pub(crate) fn new(authenticator: Authenticator) -> Self {
+        Self {
+            authenticator,
+            permission: PhantomData,
+        }
+    }
+}
+
+impl<P> Clone for GrpcPermission<P> {
+    fn clone(&self) -> Self {
+        Self::new(self.authenticator.clone())
+    }
+}
+
+impl<S, P> Layer<S> for GrpcPermission<P> {
+    type Service = GrpcPermissionService<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcPermissionService {
+            inner,
+            permission: self.clone(),
+        }
+    }
+}
+
+pub(crate) struct GrpcPermissionService<S, P> {
+    inner: S,
+    permission: GrpcPermission<P>,
+}
+
+impl<S: Clone, P> Clone for GrpcPermissionService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            permission: self.permission.clone(),
+        }
+    }
+}
+
+// `add_service` routes by the name of the wrapped service
+impl<S: NamedService, P> NamedService for GrpcPermissionService<S, P> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, P, ReqBody> Service<Request<ReqBody>> for GrpcPermissionService<S, P>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    P: Permission,
+    ReqBody: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // the clone is not ready, keep the one that is
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let authenticator = self.permission.authenticator.clone();
+        Box::pin(async move {
+            let principal = match authenticator.authenticate(req.headers()).await {
+                Some(Ok(principal)) if principal.has(P::NAME) => principal,
+                Some(Ok(_)) => return Ok(Status::from(Error::Forbidden).to_http()),
+                Some(Err(e)) => return Ok(Status::from(e).to_http()),
+                None => return Ok(Status::from(Error::Unauthorized).to_http()),
+            };
+            if let Some(user_id) = principal.user_id() {
+                request_id::record_user_id(user_id);
+            }
+            req.extensions_mut().insert(principal);
+            inner.call(req).await
+        })
+    }
+}
@@ -0,0 +1,59 @@
+pub(crate) mod api_key;
+mod grpc;
+pub(crate) mod principal;
+pub(crate) mod totp;
+
+use serde::{Deserialize, Serialize};
+
+pub(crate) use self::{
+    grpc::GrpcPermission,
+    principal::{Authenticator, Principal},
+};
+
+// given to every new user by `register`
+pub(crate) const DEFAULT_ROLE: &str = "user";
+
+/// roles of a user and the permissions they add up to, as stored in `user_roles` and
+/// `role_permissions`. copied into the session at login and into access tokens, a change
+/// takes effect with the next login or token refresh
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Grants {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+}
+
+impl Grants {
+    pub(crate) fn has(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+}
+
+/// a permission checked by `RequirePermission<P>` and `GrpcPermission<P>`
+pub(crate) trait Permission: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+macro_rules! permissions {
+    ($($name:ident => $value:literal,)*) => {
+        $(
+            pub(crate) struct $name;
+
+            impl Permission for $name {
+                const NAME: &'static str = $value;
+            }
+        )*
+
+        /// every permission there is, api key scopes are checked against it
+        pub(crate) const ALL_PERMISSIONS: &[&str] = &[$($value),*];
+    };
+}
+
+// names have to match `role_permissions.permission`
+permissions! {
+    SampleRead => "sample:read",
+    SampleWrite => "sample:write",
+    VotingVote => "voting:vote",
+}
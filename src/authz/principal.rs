@@ -0,0 +1,98 @@
+use hyper::HeaderMap;
+
+use super::Grants;
+use crate::{
+    app_state::JwtKeys,
+    define,
+    diagnostics::{self, Error},
+    entity::ApiKey,
+    token_impl::Claims,
+    usecase::ApiKeyUsecase,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Subject {
+    User(i64),
+    ApiKey(i64),
+}
+
+/// who is calling, a user or a machine client with an api key. handlers that don't care
+/// which check `grants` alike
+#[derive(Debug, Clone)]
+pub(crate) struct Principal {
+    pub subject: Subject,
+    pub grants: Grants,
+}
+
+impl Principal {
+    pub(crate) fn user(user_id: i64, grants: Grants) -> Self {
+        Self {
+            subject: Subject::User(user_id),
+            grants,
+        }
+    }
+
+    /// the scopes of the key are its permissions
+    pub(crate) fn api_key(api_key: &ApiKey) -> Self {
+        Self {
+            subject: Subject::ApiKey(api_key.id),
+            grants: Grants {
+                roles: Vec::new(),
+                permissions: api_key.scopes.clone(),
+            },
+        }
+    }
+
+    /// `Unauthorized` for claims without a user id
+    pub(crate) fn from_claims(claims: Claims) -> diagnostics::Result<Self> {
+        let user_id = claims.user_id().ok_or(Error::Unauthorized)?;
+        Ok(Self::user(user_id, claims.grants))
+    }
+
+    /// `None` for api keys
+    pub(crate) fn user_id(&self) -> Option<i64> {
+        match self.subject {
+            Subject::User(id) => Some(id),
+            Subject::ApiKey(_) => None,
+        }
+    }
+
+    pub(crate) fn has(&self, permission: &str) -> bool {
+        self.grants.has(permission)
+    }
+}
+
+/// checks the credentials a request carries in its headers, shared by the rest
+/// extractors and the grpc layer
+#[derive(Clone)]
+pub(crate) struct Authenticator {
+    keys: JwtKeys,
+    api_keys: ApiKeyUsecase,
+}
+
+impl Authenticator {
+    pub(crate) fn new(keys: JwtKeys, api_keys: ApiKeyUsecase) -> Self {
+        Self { keys, api_keys }
+    }
+
+    /// principal of the `x-api-key` header or else the bearer access token, `None` when
+    /// there is neither so rest callers can fall back to the session
+    pub(crate) async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+    ) -> Option<diagnostics::Result<Principal>> {
+        if let Some(key) = headers.get(define::API_KEY_HEADER) {
+            let Ok(key) = key.to_str() else {
+                return Some(Err(Error::Unauthorized));
+            };
+            return Some(
+                self.api_keys
+                    .authenticate(key)
+                    .await
+                    .map(|api_key| Principal::api_key(&api_key)),
+            );
+        }
+        let claims = self.keys.decode_bearer(headers)?;
+        Some(claims.and_then(Principal::from_claims))
+    }
+}
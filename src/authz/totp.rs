@@ -0,0 +1,85 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+// rfc 6238 defaults, the ones authenticator apps assume
+const DIGITS: u32 = 6;
+const PERIOD_SECS: u64 = 30;
+// steps accepted before and after the current one, for clocks that drift a little
+const SKEW_STEPS: u64 = 1;
+// handed out on confirmation, each one works once
+pub(crate) const RECOVERY_CODES: usize = 10;
+
+/// a new shared secret, 160 random bits in base32 like authenticator apps expect
+pub(crate) fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// `otpauth://` uri for authenticator apps, also what a qr code of the secret encodes
+pub(crate) fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let label = urlencoding::encode(&format!("{issuer}:{account}")).into_owned();
+    format!(
+        "otpauth://totp/{label}?secret={secret}&issuer={}&algorithm=SHA1&digits={DIGITS}&period={PERIOD_SECS}",
+        urlencoding::encode(issuer)
+    )
+}
+
+/// the time step `code` is valid for at `now` (unix seconds). callers keep the last
+/// step used so a code can't be replayed
+pub(crate) fn verify(secret: &str, code: &str, now: u64) -> Option<u64> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let current = now / PERIOD_SECS;
+    (current.saturating_sub(SKEW_STEPS)..=current + SKEW_STEPS)
+        .find(|step| hotp(&key, *step).as_bytes() == code.as_bytes())
+}
+
+/// rfc 4226 code of `key` for the counter `step`
+pub(crate) fn hotp(key: &[u8], step: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac takes keys of any size");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// fresh recovery codes, 80 random bits each, grouped for reading them off paper
+pub(crate) fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; 10];
+            rand::rngs::OsRng.fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..10], &code[10..])
+        })
+        .collect()
+}
+
+/// what is stored instead of a recovery code, random enough for a plain digest like
+/// api keys. case and dashes do not matter when typing it in
+pub(crate) fn hash_recovery_code(code: &str) -> String {
+    let normalized = code
+        .trim()
+        .chars()
+        .filter(|c| *c != '-')
+        .collect::<String>()
+        .to_lowercase();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
@@ -0,0 +1,84 @@
+use clap::{Args, Parser, Subcommand};
+
+use crate::util::config::TomlConfig;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "axum rest + grpc server")]
+pub(crate) struct Cli {
+    #[command(flatten)]
+    pub(crate) config: ConfigArgs,
+
+    /// defaults to `serve`
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct ConfigArgs {
+    /// base config file, `<name>_<profile>.toml` is merged on top of it
+    #[arg(short, long, global = true, default_value = "app_config.toml")]
+    pub(crate) config: String,
+
+    /// profile overlay to merge, e.g. `prod`
+    #[arg(short, long, global = true, env = crate::util::config::PROFILE_ENV)]
+    pub(crate) profile: Option<String>,
+}
+
+impl ConfigArgs {
+    pub(crate) fn load(&self) -> anyhow::Result<TomlConfig> {
+        let profile = self.profile.as_deref().filter(|p| !p.is_empty());
+        TomlConfig::load(&self.config, profile)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// run the http server
+    Serve(ServeArgs),
+
+    /// manage database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+
+    /// inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// print every registered rest and grpc route
+    Routes,
+}
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct ServeArgs {
+    /// serve rest only, without the grpc multiplexer
+    #[arg(long)]
+    pub(crate) no_grpc: bool,
+
+    /// skip migrations even if `database.with_migrations` is set
+    #[arg(long)]
+    pub(crate) no_migrate: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum MigrateAction {
+    /// apply pending migrations
+    Up,
+    /// list migrations and whether they are applied
+    Status,
+    /// revert applied migrations, the latest one unless --target is given
+    Revert {
+        /// revert every migration newer than this version
+        #[arg(long)]
+        target: Option<i64>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum ConfigAction {
+    /// parse and validate the configuration without connecting to anything
+    Check,
+}
@@ -1,3 +1,12 @@
 
-pub(crate) const CUSTOM_HEADER_IS_DIAGNOSTICS_ERROR: &'static str = "is_diagnostics_error";
-pub(crate) const SESSION_COOKIE: &'static str = "SESSIONID";
\ No newline at end of file
+pub(crate) const CUSTOM_HEADER_IS_DIAGNOSTICS_ERROR: &str = "is_diagnostics_error";
+pub(crate) const SESSION_COOKIE: &str = "SESSIONID";
+// csrf token of the session, readable by scripts so they can echo it in `CSRF_HEADER`
+pub(crate) const CSRF_COOKIE: &str = "XSRF-TOKEN";
+pub(crate) const CSRF_HEADER: &str = "x-csrf-token";
+pub(crate) const PROBLEM_JSON_CONTENT_TYPE: &str = "application/problem+json";
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+// machine clients, rest header and grpc metadata alike
+pub(crate) const API_KEY_HEADER: &str = "x-api-key";
+// crate name, also the tracing target prefix of everything in this crate
+pub(crate) const APP_NAME: &str = env!("CARGO_PKG_NAME");
@@ -0,0 +1,41 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    headers::{authorization::Bearer, Authorization},
+    http::request::Parts,
+    RequestPartsExt, TypedHeader,
+};
+
+use crate::{app_state::AdminToken, diagnostics};
+
+use super::Depends;
+
+/// caller presented `http.admin_token` as a bearer token
+pub(crate) struct Admin;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Depends<Admin>
+where
+    AdminToken: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = diagnostics::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AdminToken(Some(expected)) = AdminToken::from_ref(state) else {
+            return Err(diagnostics::Error::Unauthorized);
+        };
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
+            .map_err(|_| diagnostics::Error::Unauthorized)?;
+        if !constant_time_eq(bearer.token().as_bytes(), expected.as_bytes()) {
+            return Err(diagnostics::Error::Unauthorized);
+        }
+        Ok(Depends(Admin))
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+
+use crate::{app_state::JwtKeys, diagnostics, token_impl::Claims};
+
+use super::Depends;
+
+/// claims of the `Authorization: Bearer` access token
+#[async_trait]
+impl<S> FromRequestParts<S> for Depends<Claims>
+where
+    JwtKeys: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = diagnostics::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        bearer_claims(parts, state)
+            .unwrap_or(Err(diagnostics::Error::Unauthorized))
+            .map(Depends)
+    }
+}
+
+/// `None` when the request has no bearer token, so callers can fall back to the session
+pub(crate) fn bearer_claims<S>(parts: &Parts, state: &S) -> Option<diagnostics::Result<Claims>>
+where
+    JwtKeys: FromRef<S>,
+{
+    JwtKeys::from_ref(state).decode_bearer(&parts.headers)
+}
@@ -0,0 +1,58 @@
+use std::net::{IpAddr, SocketAddr};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use hyper::header;
+
+use crate::{app_state::TrustedProxies, diagnostics, session_impl::ClientInfo};
+
+use super::Depends;
+
+/// user agent and client address. the address is the peer of the connection, the proxy
+/// headers only count when that peer is one of `http.trusted_proxies`
+#[async_trait]
+impl<S> FromRequestParts<S> for Depends<ClientInfo>
+where
+    TrustedProxies: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = diagnostics::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let TrustedProxies(trusted) = TrustedProxies::from_ref(state);
+        let ip = match peer {
+            Some(peer) if trusted.contains(&peer) => Some(
+                // every proxy appends the address it got the request from, the client is
+                // the last one that is not a trusted proxy itself
+                header("x-forwarded-for")
+                    .and_then(|v| {
+                        v.rsplit(',')
+                            .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+                            .find(|ip| !trusted.contains(ip))
+                    })
+                    .or_else(|| header("x-real-ip").and_then(|v| v.parse().ok()))
+                    .unwrap_or(peer),
+            ),
+            peer => peer,
+        };
+        Ok(Depends(ClientInfo {
+            user_agent: header(header::USER_AGENT.as_str()).map(str::to_owned),
+            ip: ip.map(|ip| ip.to_string()),
+        }))
+    }
+}
@@ -1,3 +1,8 @@
+pub(crate) mod admin;
+pub(crate) mod claims;
+pub(crate) mod client;
+pub(crate) mod permission;
+pub(crate) mod principal;
 pub(crate) mod session;
 pub(crate) mod user;
 
@@ -0,0 +1,45 @@
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+
+use crate::{
+    app_state::SessionService,
+    authz::{Authenticator, Permission, Principal},
+    diagnostics,
+};
+
+use super::Depends;
+
+/// the caller is authenticated as for `Depends<Principal>` and holds `P`.
+/// `Unauthorized` without credentials, `Forbidden` without the permission
+pub(crate) struct RequirePermission<P> {
+    pub principal: Principal,
+    permission: PhantomData<fn() -> P>,
+}
+
+#[async_trait]
+impl<S, P> FromRequestParts<S> for RequirePermission<P>
+where
+    SessionService: FromRef<S>,
+    Authenticator: FromRef<S>,
+    S: Send + Sync,
+    P: Permission,
+{
+    type Rejection = diagnostics::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Depends(principal) = Depends::<Principal>::from_request_parts(parts, state).await?;
+        if !principal.has(P::NAME) {
+            tracing::debug!("{:?} lacks `{}`", principal.subject, P::NAME);
+            return Err(diagnostics::Error::Forbidden);
+        }
+        Ok(Self {
+            principal,
+            permission: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+
+use crate::{
+    app_state::SessionService,
+    authz::{Authenticator, Principal},
+    diagnostics, session_impl,
+    util::request_id,
+};
+
+use super::{session::load_session, Depends};
+
+/// the caller by `x-api-key`, bearer access token or session, tried in that order.
+/// `Unauthorized` when none of them is there or valid
+#[async_trait]
+impl<S> FromRequestParts<S> for Depends<Principal>
+where
+    SessionService: FromRef<S>,
+    Authenticator: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = diagnostics::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let principal = match Authenticator::from_ref(state)
+            .authenticate(&parts.headers)
+            .await
+        {
+            Some(principal) => principal?,
+            None => {
+                let session = load_session(parts, state).await?;
+                let user_id = session_impl::authenticated_user_id(&session)
+                    .ok_or(diagnostics::Error::Unauthorized)?;
+                Principal::user(user_id, session_impl::session_grants(&session))
+            }
+        };
+        if let Some(user_id) = principal.user_id() {
+            request_id::record_user_id(user_id);
+        }
+        Ok(Depends(principal))
+    }
+}
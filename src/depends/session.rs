@@ -1,51 +1,36 @@
-use async_session::SessionStore;
+use async_session::Session;
 use async_trait::async_trait;
 use axum::{
-    extract::{rejection::TypedHeaderRejectionReason, FromRef, FromRequestParts},
-    headers,
+    extract::{FromRef, FromRequestParts},
     http::request::Parts,
-    RequestPartsExt, TypedHeader,
 };
-use hyper::header;
 
-use crate::{app_state::SessionStoreImpl, define::SESSION_COOKIE, diagnostics};
+use crate::{app_state::SessionService, diagnostics};
 
 use super::Depends;
 
+/// the session of the request. to move it to a new id after a privilege change, return it
+/// as `RotateSession` from the handler
 #[async_trait]
-impl<S> FromRequestParts<S> for Depends<async_session::Session>
+impl<S> FromRequestParts<S> for Depends<Session>
 where
-    SessionStoreImpl: FromRef<S> + SessionStore,
+    SessionService: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = diagnostics::Error;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let store = SessionStoreImpl::from_ref(state);
-
-        let cookies = parts
-            .extract::<TypedHeader<headers::Cookie>>()
-            .await
-            .map_err(|e| match *e.name() {
-                header::COOKIE => match e.reason() {
-                    TypedHeaderRejectionReason::Missing => diagnostics::Error::Unauthorized,
-                    _ => diagnostics::Error::CookieError(format!(
-                        "unexpected error getting Cookie header(s): {e}"
-                    )),
-                },
-                _ => diagnostics::Error::CookieError(format!(
-                    "unexpected error getting cookies: {e}"
-                )),
-            })?;
-        let session_cookie = cookies
-            .get(SESSION_COOKIE)
-            .ok_or(diagnostics::Error::Unauthorized)?;
-
-        let session = store
-            .load_session(urlencoding::decode(session_cookie).unwrap().to_string())
-            .await?
-            .ok_or(diagnostics::Error::Unauthorized)?;
-
-        Ok(Depends(session))
+        Ok(Depends(load_session(parts, state).await?))
     }
 }
+
+/// `Unauthorized` when the request carries no live session
+pub(crate) async fn load_session<S>(parts: &Parts, state: &S) -> diagnostics::Result<Session>
+where
+    SessionService: FromRef<S>,
+{
+    SessionService::from_ref(state)
+        .load(&parts.headers)
+        .await?
+        .ok_or(diagnostics::Error::Unauthorized)
+}
@@ -1,55 +1,47 @@
-use async_session::SessionStore;
 use async_trait::async_trait;
 use axum::{
-    extract::{rejection::TypedHeaderRejectionReason, FromRef, FromRequestParts},
-    headers,
+    extract::{FromRef, FromRequestParts},
     http::request::Parts,
-    RequestPartsExt, TypedHeader,
 };
-use hyper::header;
 
-use crate::{app_state::SessionStoreImpl, define::SESSION_COOKIE, diagnostics, entity::User};
+use crate::{
+    app_state::{JwtKeys, SessionService},
+    diagnostics,
+    entity::User,
+    repository::{BasicRepository, UserRepositoryDB},
+    session_impl,
+    util::request_id,
+};
 
-use super::Depends;
+use super::{claims::bearer_claims, session::load_session, Depends};
 
+/// the user of the bearer access token, or else of the session. read from the users table
+/// on every request so a deleted user is logged out right away
 #[async_trait]
 impl<S> FromRequestParts<S> for Depends<User>
 where
-    SessionStoreImpl: FromRef<S> + SessionStore,
+    SessionService: FromRef<S>,
+    JwtKeys: FromRef<S>,
+    UserRepositoryDB: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = diagnostics::Error;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let store = SessionStoreImpl::from_ref(state);
-
-        let cookies = parts
-            .extract::<TypedHeader<headers::Cookie>>()
+        let user_id = match bearer_claims(parts, state) {
+            Some(claims) => claims?.user_id(),
+            None => session_impl::authenticated_user_id(&load_session(parts, state).await?),
+        }
+        .ok_or(diagnostics::Error::Unauthorized)?;
+
+        let user = UserRepositoryDB::from_ref(state)
+            .find_by_id(&user_id)
             .await
-            .map_err(|e| match *e.name() {
-                header::COOKIE => match e.reason() {
-                    TypedHeaderRejectionReason::Missing => diagnostics::Error::Unauthorized,
-                    _ => diagnostics::Error::CookieError(format!(
-                        "unexpected error getting Cookie header(s): {e}"
-                    )),
-                },
-                _ => diagnostics::Error::CookieError(format!(
-                    "unexpected error getting cookies: {e}"
-                )),
+            .map_err(|e| match e {
+                diagnostics::Error::RowNotFound => diagnostics::Error::Unauthorized,
+                e => e,
             })?;
-
-        let session_cookie = cookies
-            .get(SESSION_COOKIE)
-            .ok_or(diagnostics::Error::Unauthorized)?;
-
-        let session = store
-            .load_session(urlencoding::decode(session_cookie).unwrap().to_string())
-            .await?
-            .ok_or(diagnostics::Error::Unauthorized)?;
-
-        let user = session
-            .get::<User>("user")
-            .ok_or(diagnostics::Error::Unauthorized)?;
+        request_id::record_user_id(user.id);
 
         Ok(Depends(user))
     }
@@ -8,14 +8,16 @@ use axum::{
 };
 use bb8::RunError;
 use bb8_redis::redis::RedisError;
-use hyper::StatusCode;
-use serde_json::json;
+use hyper::{header, StatusCode};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tonic_types::{ErrorDetails, StatusExt};
 
 use crate::define;
 
 pub(crate) type Result<T> = core::result::Result<T, Error>;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum Error {
     // generic errors
@@ -40,6 +42,18 @@ pub enum Error {
     #[error("Unauthorized")]
     Unauthorized,
 
+    // the password was right, the account wants its second factor as well
+    #[error("MfaRequired")]
+    MfaRequired,
+
+    // authenticated but without the permission the route needs
+    #[error("Forbidden")]
+    Forbidden,
+
+    // the resource exists already, e.g. a unique column
+    #[error("Conflict {0}")]
+    Conflict(String),
+
     #[error("NotImplemented")]
     NotImplemented,
 
@@ -68,7 +82,19 @@ pub enum Error {
     RedisError(#[from] RunError<RedisError>),
 
     #[error("CookieError {0}")]
-    CookieError(String)
+    CookieError(String),
+
+    // unsafe request on a cookie session without the session's csrf token
+    #[error("CsrfError {0}")]
+    CsrfError(&'static str),
+
+    // the openid provider failed or answered with something unusable
+    #[error("OidcError {0}")]
+    OidcError(String),
+
+    // status returned by an upstream grpc call
+    #[error("GrpcStatus {0}")]
+    GrpcStatus(Box<tonic::Status>),
 }
 
 impl From<sqlx::Error> for Error {
@@ -80,27 +106,181 @@ impl From<sqlx::Error> for Error {
     }
 }
 
+/// rfc 7807 body. `code` is the stable, machine readable part clients should match on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Problem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    // only filled in when `http.debug_errors` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<String>,
+}
+
+impl Problem {
+    pub(crate) fn new(status: StatusCode, code: impl Into<String>) -> Self {
+        Problem {
+            kind: "about:blank".to_owned(),
+            title: status.canonical_reason().unwrap_or("Error").to_owned(),
+            status: status.as_u16(),
+            code: code.into(),
+            detail: None,
+            request_id: None,
+            debug: None,
+        }
+    }
+
+    /// for error responses that did not come from `diagnostics::Error`
+    pub(crate) fn from_status(status: StatusCode) -> Self {
+        let code = status
+            .canonical_reason()
+            .unwrap_or("error")
+            .to_lowercase()
+            .replace([' ', '-'], "_");
+        Problem::new(status, code)
+    }
+
+    pub(crate) fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> axum::response::Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = serde_json::to_vec(&self).unwrap_or_default();
+        let mut res = (
+            status,
+            [(header::CONTENT_TYPE, define::PROBLEM_JSON_CONTENT_TYPE)],
+            body,
+        )
+            .into_response();
+        res.extensions_mut().insert(self);
+        res
+    }
+}
+
+/// internal detail of the error, picked up by `middleware::problem_details`
+#[derive(Debug, Clone)]
+pub(crate) struct ProblemDebug(pub String);
+
+impl Error {
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            Error::IoError(_) | Error::AnyhowError(_) | Error::SqlXMigrateError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Error::Message(_) | Error::CookieError(_) => StatusCode::BAD_REQUEST,
+            Error::JsonResponse { code, .. } => *code,
+            Error::NotFound | Error::RowNotFound => StatusCode::NOT_FOUND,
+            Error::Unauthorized | Error::MfaRequired => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::CsrfError(_) => StatusCode::FORBIDDEN,
+            Error::OidcError(_) => StatusCode::BAD_GATEWAY,
+            Error::NotImplemented => StatusCode::NOT_IMPLEMENTED,
+            Error::JsonRejection(e) => e.status(),
+            Error::PathRejection(e) => e.status(),
+            Error::MultipartError(e) => e.status(),
+            Error::SqlXError(e) => match e {
+                sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::Io(_)
+                | sqlx::Error::Tls(_) => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Error::BB8Error(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::GrpcStatus(status) => http_status(status.code()),
+            Error::RedisError(e) => match e {
+                RunError::TimedOut => StatusCode::SERVICE_UNAVAILABLE,
+                RunError::User(e)
+                    if e.is_io_error() || e.is_connection_dropped() || e.is_timeout() =>
+                {
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+                RunError::User(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        }
+    }
+
+    /// stable error code, part of the api contract
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Error::IoError(_) | Error::AnyhowError(_) => "internal_error",
+            Error::Message(_) => "bad_request",
+            Error::JsonResponse { .. } => "custom",
+            Error::NotFound | Error::RowNotFound => "not_found",
+            Error::Unauthorized => "unauthorized",
+            Error::MfaRequired => "mfa_required",
+            Error::Forbidden => "forbidden",
+            Error::Conflict(_) => "conflict",
+            Error::NotImplemented => "not_implemented",
+            Error::JsonRejection(_) => "invalid_json",
+            Error::PathRejection(_) => "invalid_path",
+            Error::MultipartError(_) => "invalid_multipart",
+            Error::SqlXError(_) if self.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                "database_unavailable"
+            }
+            Error::SqlXError(_) => "database_error",
+            Error::SqlXMigrateError(_) => "migration_error",
+            Error::BB8Error(_) => "redis_unavailable",
+            Error::RedisError(_) if self.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                "redis_unavailable"
+            }
+            Error::RedisError(_) => "redis_error",
+            Error::CookieError(_) => "invalid_cookie",
+            Error::CsrfError(_) => "csrf_token_invalid",
+            Error::OidcError(_) => "oidc_provider_error",
+            Error::GrpcStatus(_) => "upstream_error",
+        }
+    }
+
+    /// what the client is allowed to see, server side errors stay opaque
+    fn public_detail(&self) -> Option<String> {
+        match self {
+            Error::Message(message) => Some(message.clone()),
+            Error::CookieError(message) => Some(message.clone()),
+            Error::Conflict(message) => Some(message.clone()),
+            Error::CsrfError(message) => Some((*message).to_owned()),
+            Error::JsonRejection(e) => Some(e.body_text()),
+            Error::PathRejection(e) => Some(e.body_text()),
+            Error::MultipartError(e) => Some(e.body_text()),
+            Error::RowNotFound => Some("resource not found".to_owned()),
+            Error::GrpcStatus(status) if !self.status().is_server_error() => {
+                Some(status.message().to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_problem(&self) -> Problem {
+        let problem = Problem::new(self.status(), self.code());
+        match self.public_detail() {
+            Some(detail) => problem.with_detail(detail),
+            None => problem,
+        }
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         let mut res = match self {
             Error::JsonResponse { code, json } => (code, Json(json)).into_response(),
-            Error::JsonRejection(err) => (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "message": format!("{:?}", err) })),
-            )
-                .into_response(),
-            Error::PathRejection(err) => (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "message": format!("{:?}", err) })),
-            )
-                .into_response(),
-            Error::NotFound => StatusCode::NOT_FOUND.into_response(),
-            Error::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
-            _ => (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "message": format!("{:?}", self) })),
-            )
-                .into_response(),
+            _ => {
+                if self.status().is_server_error() {
+                    tracing::error!("{self:?}");
+                }
+                let mut res = self.to_problem().into_response();
+                res.extensions_mut().insert(ProblemDebug(format!("{self:?}")));
+                res
+            }
         };
         res.headers_mut().insert(
             define::CUSTOM_HEADER_IS_DIAGNOSTICS_ERROR,
@@ -110,4 +290,95 @@ impl IntoResponse for Error {
     }
 }
 
+impl Error {
+    /// same grouping as `status` but in grpc terms
+    pub(crate) fn grpc_code(&self) -> tonic::Code {
+        match self {
+            Error::NotFound | Error::RowNotFound => tonic::Code::NotFound,
+            Error::Unauthorized | Error::MfaRequired => tonic::Code::Unauthenticated,
+            Error::NotImplemented => tonic::Code::Unimplemented,
+            Error::Message(_)
+            | Error::CookieError(_)
+            | Error::JsonRejection(_)
+            | Error::PathRejection(_)
+            | Error::MultipartError(_) => tonic::Code::InvalidArgument,
+            Error::GrpcStatus(status) => status.code(),
+            _ => grpc_code(self.status()),
+        }
+    }
+}
+
+/// message is the public detail (or the error code), the stable code travels
+/// as `google.rpc.ErrorInfo.reason` so grpc clients can match on it like rest clients
+impl From<Error> for tonic::Status {
+    fn from(error: Error) -> Self {
+        if let Error::GrpcStatus(status) = error {
+            return *status;
+        }
+        if error.status().is_server_error() {
+            tracing::error!("{error:?}");
+        }
+        let code = error.grpc_code();
+        let message = error
+            .public_detail()
+            .unwrap_or_else(|| error.code().to_owned());
+        let details = ErrorDetails::with_error_info(
+            error.code(),
+            ERROR_DOMAIN,
+            [("http_status".to_owned(), error.status().as_u16().to_string())],
+        );
+        tonic::Status::with_error_details(code, message, details)
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        match status.code() {
+            tonic::Code::NotFound => Error::NotFound,
+            tonic::Code::Unauthenticated => Error::Unauthorized,
+            tonic::Code::Unimplemented => Error::NotImplemented,
+            tonic::Code::InvalidArgument => Error::Message(status.message().to_owned()),
+            _ => Error::GrpcStatus(Box::new(status)),
+        }
+    }
+}
+
+const ERROR_DOMAIN: &str = define::APP_NAME;
+
+fn grpc_code(status: StatusCode) -> tonic::Code {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => tonic::Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => tonic::Code::Unauthenticated,
+        StatusCode::FORBIDDEN => tonic::Code::PermissionDenied,
+        StatusCode::NOT_FOUND => tonic::Code::NotFound,
+        StatusCode::CONFLICT => tonic::Code::AlreadyExists,
+        StatusCode::TOO_MANY_REQUESTS => tonic::Code::ResourceExhausted,
+        StatusCode::NOT_IMPLEMENTED => tonic::Code::Unimplemented,
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY => tonic::Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => tonic::Code::DeadlineExceeded,
+        s if s.is_client_error() => tonic::Code::FailedPrecondition,
+        _ => tonic::Code::Internal,
+    }
+}
+
+fn http_status(code: tonic::Code) -> StatusCode {
+    match code {
+        tonic::Code::Ok => StatusCode::OK,
+        tonic::Code::Cancelled => StatusCode::from_u16(499).unwrap(),
+        tonic::Code::InvalidArgument | tonic::Code::OutOfRange => StatusCode::BAD_REQUEST,
+        tonic::Code::FailedPrecondition => StatusCode::BAD_REQUEST,
+        tonic::Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        tonic::Code::NotFound => StatusCode::NOT_FOUND,
+        tonic::Code::AlreadyExists | tonic::Code::Aborted => StatusCode::CONFLICT,
+        tonic::Code::PermissionDenied => StatusCode::FORBIDDEN,
+        tonic::Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        tonic::Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        tonic::Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        tonic::Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        tonic::Code::Unknown | tonic::Code::Internal | tonic::Code::DataLoss => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
 // todo minidump?
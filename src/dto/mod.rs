@@ -1,3 +1,5 @@
 mod sample;
+mod user;
 
-pub(crate) use self::sample::*;
\ No newline at end of file
+pub(crate) use self::sample::*;
+pub(crate) use self::user::*;
\ No newline at end of file
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use crate::entity::ApiKey;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct UserRegister {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct UserLogin {
+    pub email: String,
+    pub password: String,
+    // totp or recovery code, for accounts with a second factor
+    #[serde(default)]
+    pub mfa_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MfaCode {
+    pub code: String,
+}
+
+/// a totp secret waiting for its first code
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TotpEnrollment {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+/// shown once when the second factor is turned on, only their hashes are kept
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TokenRefresh {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ApiKeyCreate {
+    pub name: String,
+    pub scopes: Vec<String>,
+    // never expires when omitted
+    pub expires_in_secs: Option<u64>,
+}
+
+/// the stored key plus the key itself, which is not shown again
+#[derive(Debug, Serialize)]
+pub(crate) struct ApiKeyCreated {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use super::Entity;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    // start of the key in the clear, tells keys apart in listings
+    pub prefix: String,
+    // sha-256 of the key, the key itself is only shown once
+    #[serde(skip)]
+    pub key_hash: String,
+    // permission names, see `authz::ALL_PERMISSIONS`
+    #[sqlx(json)]
+    pub scopes: Vec<String>,
+    // unix seconds
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl ApiKey {
+    pub fn new(
+        name: String,
+        prefix: String,
+        key_hash: String,
+        scopes: Vec<String>,
+        created_at: i64,
+        expires_at: Option<i64>,
+    ) -> Self {
+        ApiKey {
+            id: i64::default(),
+            name,
+            prefix,
+            key_hash,
+            scopes,
+            created_at,
+            expires_at,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl Entity for ApiKey {
+    type ID = i64;
+
+    fn get_id(&self) -> &Self::ID {
+        &self.id
+    }
+}
@@ -1,8 +1,10 @@
+mod api_key;
 mod sample;
 mod user;
 
+pub(crate) use self::api_key::ApiKey;
 pub(crate) use self::sample::Sample;
-pub(crate) use self::user::User;
+pub(crate) use self::user::{User, UserTotp};
 
 pub(crate) trait Entity: Sync + Send
 {
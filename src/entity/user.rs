@@ -2,27 +2,23 @@ use serde::{Deserialize, Serialize};
 
 use super::Entity;
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: i64,
     pub name: String,
     pub email: String,
+    // argon2 phc string, never leaves the server
+    #[serde(skip)]
+    pub password_hash: String,
 }
 
 impl User {
-    pub fn with_name(name: String) -> Self {
+    pub fn new(name: String, email: String, password_hash: String) -> Self {
         User {
             id: i64::default(),
             name,
-            email: "abc@d.e".to_owned(),
-        }
-    }
-
-    pub fn new(id: i64, name: String) -> Self {
-        User {
-            id,
-            name,
-            email: "abc@d.e".to_owned(),
+            email,
+            password_hash,
         }
     }
 }
@@ -34,3 +30,23 @@ impl Entity for User {
         &self.id
     }
 }
+
+/// totp secret of a user, a second factor only once `confirmed_at` is set
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct UserTotp {
+    pub user_id: i64,
+    // base32, needed in the clear to compute codes
+    pub secret: String,
+    // unix seconds
+    pub confirmed_at: Option<i64>,
+    // time step of the last accepted code, older and equal ones are replays
+    pub last_step: Option<i64>,
+    // wrong codes since the last right one, the second factor is locked at `max_attempts`
+    pub failed_attempts: i64,
+}
+
+impl UserTotp {
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, future::Future, time::Duration};
+
+use serde::Serialize;
+use tokio::time::Instant;
+use tonic_health::server::HealthReporter;
+
+use crate::{
+    app_state::AppState,
+    diagnostics, migration,
+    proto::voting::{voting_server::VotingServer, VotingService},
+};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+const GRPC_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Ok,
+    Fail,
+}
+
+// what a single check reports, the cause only goes to the log
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CheckStatus {
+    Ok,
+    Error,
+    Timeout,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Check {
+    pub status: CheckStatus,
+    pub latency_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Readiness {
+    pub status: Status,
+    pub checks: BTreeMap<&'static str, Check>,
+}
+
+impl Readiness {
+    pub(crate) fn is_ready(&self) -> bool {
+        self.status == Status::Ok
+    }
+}
+
+async fn timed<F>(name: &str, check: F) -> Check
+where
+    F: Future<Output = diagnostics::Result<()>>,
+{
+    let started = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let status = match result {
+        Ok(Ok(())) => CheckStatus::Ok,
+        Ok(Err(e)) => {
+            tracing::warn!("readiness check {name} failed: {e}");
+            CheckStatus::Error
+        }
+        Err(_) => {
+            tracing::warn!("readiness check {name} timed out after {CHECK_TIMEOUT:?}");
+            CheckStatus::Timeout
+        }
+    };
+    Check { status, latency_ms }
+}
+
+async fn check_database(app_state: &AppState) -> diagnostics::Result<()> {
+    sqlx::query("select 1").execute(&app_state.db_pool).await?;
+    Ok(())
+}
+
+async fn check_redis(app_state: &AppState) -> diagnostics::Result<()> {
+    let mut conn = app_state.redis_pool.get().await?;
+    bb8_redis::redis::cmd("PING")
+        .query_async::<_, String>(&mut *conn)
+        .await
+        .map_err(|e| diagnostics::Error::BB8Error(e.to_string()))?;
+    Ok(())
+}
+
+async fn check_migrations(app_state: &AppState) -> diagnostics::Result<()> {
+    let status = migration::status(&app_state.db_pool).await?;
+    let pending = status.iter().filter(|m| !m.applied).count();
+    let mismatched = status.iter().filter(|m| m.checksum_mismatch).count();
+    match (pending, mismatched) {
+        (0, 0) => Ok(()),
+        (pending, mismatched) => Err(diagnostics::Error::Message(format!(
+            "{pending} pending, {mismatched} checksum mismatch"
+        ))),
+    }
+}
+
+/// database, redis and migrations, checked concurrently
+pub(crate) async fn readiness(app_state: &AppState) -> Readiness {
+    let (database, redis, migrations) = tokio::join!(
+        timed("database", check_database(app_state)),
+        timed("redis", check_redis(app_state)),
+        timed("migrations", check_migrations(app_state)),
+    );
+    let checks = BTreeMap::from([
+        ("database", database),
+        ("redis", redis),
+        ("migrations", migrations),
+    ]);
+    let status = if checks.values().all(|c| c.status == CheckStatus::Ok) {
+        Status::Ok
+    } else {
+        Status::Fail
+    };
+    Readiness { status, checks }
+}
+
+/// keeps `grpc.health.v1.Health` in line with `readiness` until shutdown
+pub(crate) async fn report_grpc(mut reporter: HealthReporter, app_state: AppState) {
+    let mut shutdown = app_state.shutdown.subscribe();
+    let mut interval = tokio::time::interval(GRPC_REPORT_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => break,
+        }
+        if readiness(&app_state).await.is_ready() {
+            reporter
+                .set_service_status("", tonic_health::ServingStatus::Serving)
+                .await;
+            reporter.set_serving::<VotingServer<VotingService>>().await;
+        } else {
+            reporter
+                .set_service_status("", tonic_health::ServingStatus::NotServing)
+                .await;
+            reporter
+                .set_not_serving::<VotingServer<VotingService>>()
+                .await;
+        }
+    }
+    reporter
+        .set_service_status("", tonic_health::ServingStatus::NotServing)
+        .await;
+    reporter
+        .set_not_serving::<VotingServer<VotingService>>()
+        .await;
+}
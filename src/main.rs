@@ -1,13 +1,23 @@
 #![allow(dead_code)]
 
+use std::{convert::Infallible, future::Ready, net::SocketAddr};
+
 use app_state::AppState;
-use tokio;
+use axum::extract::{ConnectInfo, FromRef};
+use clap::Parser;
+use hyper::server::conn::AddrStream;
+use tower_http::add_extension::{AddExtension, AddExtensionLayer};
 
 mod app_state;
+mod authz;
+mod cli;
 mod define;
 mod diagnostics;
 mod dto;
 mod entity;
+mod health;
+mod metrics;
+mod migration;
 mod proto;
 mod repository;
 mod router;
@@ -15,6 +25,8 @@ mod usecase;
 mod util;
 mod depends;
 mod session_impl;
+mod token_impl;
+mod oidc_impl;
 
 #[cfg(feature = "enable_websocket_pubsub_sample")]
 mod ws;
@@ -23,26 +35,129 @@ mod ws;
 mod tests;
 
 #[tokio::main]
-async fn main() {
-    let config = util::config::TomlConfig::from_file("app_config_local2.toml").unwrap();
-    let _guard = util::tracing::init(&config.tracing).unwrap();
-    tracing::trace!("{config:?}");
-    let app_state = AppState::new(&config).await;
-    if config.database.with_migrations {
-        if let Err(e) = app_state.migrate_database().await {
-            tracing::error!("{e:?}");
-        }
+async fn main() -> anyhow::Result<()> {
+    let cli = cli::Cli::parse();
+    match cli.command {
+        None => serve(cli.config.load()?, cli::ServeArgs::default()).await,
+        Some(Command::Serve(args)) => serve(cli.config.load()?, args).await,
+        Some(Command::Migrate { action }) => migrate(cli.config.load()?, action).await,
+        Some(Command::Config {
+            action: ConfigAction::Check,
+        }) => check_config(&cli.config),
+        Some(Command::Routes) => print_routes(),
     }
-    run_with_grpc(app_state, config).await;
-    //run(app_state, config).await;
 }
 
 use crate::{
+    authz::{Authenticator, GrpcPermission, VotingVote},
+    cli::{Command, ConfigAction, ConfigArgs, MigrateAction, ServeArgs},
     proto::{voting::VotingService, MultiplexService},
-    util::config::TomlConfig,
+    metrics::GrpcMetricsLayer,
+    oidc_impl::OidcProviders,
+    token_impl::JwtKeys,
+    util::{config::TomlConfig, request_id::RequestIdLayer},
 };
+use tower::Layer;
+
+async fn serve(config: TomlConfig, args: ServeArgs) -> anyhow::Result<()> {
+    config.validate()?;
+    let guard = util::tracing::init(&config.tracing)?;
+    tracing::trace!("effective config\n{}", config.to_redacted_string()?);
+    let app_state = AppState::new(&config).await?;
+    if config.database.with_migrations && !args.no_migrate {
+        if let Err(e) = app_state.migrate_database().await {
+            tracing::error!("{e:?}");
+        }
+    }
+
+    let shutdown = app_state.shutdown.clone();
+    tokio::spawn(async move {
+        util::shutdown::signal().await;
+        shutdown.trigger();
+    });
+
+    let resources = app_state.clone();
+    if args.no_grpc {
+        run(app_state, config).await;
+    } else {
+        run_with_grpc(app_state, config).await;
+    }
+
+    resources.close().await;
+    tracing::info!("shutdown complete");
+    // flush the non blocking file writer
+    drop(guard);
+    Ok(())
+}
+
+async fn migrate(config: TomlConfig, action: MigrateAction) -> anyhow::Result<()> {
+    config.validate()?;
+    let _guard = util::tracing::init(&config.tracing)?;
+    #[cfg(feature = "use_sqlite")]
+    AppState::sqlite_create_database(&config).await;
+    let pool = AppState::connect_database(&config).await;
+    match action {
+        MigrateAction::Up => {
+            migration::up(&pool).await?;
+            println!("migrations applied");
+        }
+        MigrateAction::Status => {
+            for m in migration::status(&pool).await? {
+                println!(
+                    "{:>6} {:<10} {}{}",
+                    m.version,
+                    if m.applied { "applied" } else { "pending" },
+                    m.description,
+                    if m.checksum_mismatch {
+                        " (checksum mismatch)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        MigrateAction::Revert { target } => {
+            let reverted = migration::revert(&pool, target).await?;
+            if reverted.is_empty() {
+                println!("nothing to revert");
+            }
+            for version in reverted {
+                println!("reverted {version}");
+            }
+        }
+    }
+    pool.close().await;
+    Ok(())
+}
+
+fn check_config(args: &ConfigArgs) -> anyhow::Result<()> {
+    let config = args.load()?;
+    config.validate()?;
+    // what `AppState::new` loads besides the config itself
+    JwtKeys::new(&config.jwt)?;
+    OidcProviders::new(&config.oidc)?;
+    println!("{}", config.to_redacted_string()?);
+    println!("# {} is valid", args.config);
+    Ok(())
+}
+
+fn print_routes() -> anyhow::Result<()> {
+    for route in router::routes() {
+        println!("{route}");
+    }
+    for route in proto::grpc_routes()? {
+        println!("{:<12} {}", "GRPC", route);
+    }
+    Ok(())
+}
 
 pub(crate) async fn run_with_grpc(app_state: AppState, config: TomlConfig) {
+    let shutdown = app_state.shutdown.clone();
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(health::report_grpc(health_reporter, app_state.clone()));
+    spawn_admin(&app_state, &config);
+    let grpc_metrics = GrpcMetricsLayer::new(app_state.metrics.clone());
+    let grpc_auth = Authenticator::from_ref(&app_state);
     let rest = router::init_router(app_state, &config.http);
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
@@ -51,25 +166,71 @@ pub(crate) async fn run_with_grpc(app_state: AppState, config: TomlConfig) {
 
     let grpc = tonic::transport::Server::builder()
         .add_service(reflection_service)
-        .add_service(proto::voting::voting_server::VotingServer::new(
-            VotingService::default(),
+        .add_service(health_service)
+        .add_service(GrpcPermission::<VotingVote>::new(grpc_auth).layer(
+            proto::voting::voting_server::VotingServer::new(VotingService::default()),
         ))
         .into_service();
+    let grpc = grpc_metrics.layer(grpc);
 
-    let service = MultiplexService::new(rest, grpc);
+    let service = RequestIdLayer.layer(MultiplexService::new(rest, grpc));
     let address = config.http.socket_addr().unwrap();
     tracing::debug!("listening on {address}");
-    hyper::Server::bind(&address)
-        .serve(tower::make::Shared::new(service))
-        .await
-        .unwrap();
+    let server = hyper::Server::bind(&address)
+        .serve(with_connect_info(service))
+        .with_graceful_shutdown(shutdown.triggered());
+    util::shutdown::serve_until_shutdown(server, &shutdown, config.http.shutdown_timeout()).await;
 }
 
 pub(crate) async fn run(app_state: AppState, config: TomlConfig) {
+    let shutdown = app_state.shutdown.clone();
+    spawn_admin(&app_state, &config);
     let rest = router::init_router(app_state, &config.http);
     let address = config.http.socket_addr().unwrap();
-    axum::Server::bind(&address)
-        .serve(rest.into_make_service())
-        .await
-        .unwrap()
+    tracing::debug!("listening on {address}");
+    let service = RequestIdLayer.layer(rest);
+    let server = axum::Server::bind(&address)
+        .serve(with_connect_info(service))
+        .with_graceful_shutdown(shutdown.triggered());
+    util::shutdown::serve_until_shutdown(server, &shutdown, config.http.shutdown_timeout()).await;
+}
+
+type WithPeer<S> = AddExtension<S, ConnectInfo<SocketAddr>>;
+
+/// one service per connection with the peer address in the request extensions, as
+/// `ConnectInfo` for `Depends<ClientInfo>`
+fn with_connect_info<S>(
+    service: S,
+) -> impl for<'a> tower::Service<
+    &'a AddrStream,
+    Response = WithPeer<S>,
+    Error = Infallible,
+    Future = Ready<Result<WithPeer<S>, Infallible>>,
+> + Clone
+where
+    S: Clone,
+{
+    tower::service_fn(move |conn: &AddrStream| {
+        let service =
+            AddExtensionLayer::new(ConnectInfo(conn.remote_addr())).layer(service.clone());
+        std::future::ready(Ok(service))
+    })
+}
+
+/// separate listener for the admin routes when `http.admin_port` is set
+fn spawn_admin(app_state: &AppState, config: &TomlConfig) {
+    let Ok(Some(address)) = config.http.admin_socket_addr() else {
+        return;
+    };
+    let shutdown = app_state.shutdown.clone();
+    let admin = router::init_admin_router(app_state.clone());
+    tokio::spawn(async move {
+        tracing::debug!("admin listening on {address}");
+        let server = axum::Server::bind(&address)
+            .serve(admin.into_make_service())
+            .with_graceful_shutdown(shutdown.triggered());
+        if let Err(e) = server.await {
+            tracing::error!("admin server error: {e:?}");
+        }
+    });
 }
@@ -0,0 +1,392 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use futures::future::BoxFuture;
+use hyper::{body::HttpBody, HeaderMap};
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use tower::{Layer, Service};
+
+use crate::app_state::AppState;
+
+// how long a scrape waits for a database connection before it counts a miss
+const ACQUIRE_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// prometheus registry of the process. request metrics are recorded as they happen,
+/// pool and pubsub gauges are sampled from `AppState` on every scrape
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    registry: Registry,
+    started: Instant,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    grpc_requests: IntCounterVec,
+    grpc_duration: HistogramVec,
+    db_pool_connections: IntGauge,
+    db_pool_idle: IntGauge,
+    db_pool_max: IntGauge,
+    db_pool_acquire: Gauge,
+    db_pool_acquire_misses: IntCounter,
+    redis_pool_connections: IntGauge,
+    redis_pool_idle: IntGauge,
+    ws_connections: IntGauge,
+    pubsub_topics: IntGauge,
+    uptime: Gauge,
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        Self::try_new().expect("metric definitions are valid")
+    }
+
+    fn try_new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+        let labels = ["method", "route", "status"];
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "rest requests by matched route"),
+            &labels,
+        )?;
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "rest request latency by matched route",
+            ),
+            &labels,
+        )?;
+        let grpc_requests = IntCounterVec::new(
+            Opts::new("grpc_requests_total", "grpc calls by method and code"),
+            &["method", "code"],
+        )?;
+        let grpc_duration = HistogramVec::new(
+            HistogramOpts::new("grpc_request_duration_seconds", "grpc call latency"),
+            &["method", "code"],
+        )?;
+        let db_pool_connections =
+            IntGauge::new("db_pool_connections", "open database connections")?;
+        let db_pool_idle = IntGauge::new("db_pool_idle_connections", "idle database connections")?;
+        let db_pool_max = IntGauge::new("db_pool_max_connections", "database pool size limit")?;
+        // sqlx keeps no wait statistics, the scrape acquires a connection itself and
+        // reports how long that took
+        let db_pool_acquire = Gauge::new(
+            "db_pool_acquire_wait_seconds",
+            "time the last scrape waited for a database connection",
+        )?;
+        let db_pool_acquire_misses = IntCounter::new(
+            "db_pool_acquire_misses_total",
+            "scrapes that got no database connection within the probe timeout",
+        )?;
+        let redis_pool_connections =
+            IntGauge::new("redis_pool_connections", "open redis connections")?;
+        let redis_pool_idle =
+            IntGauge::new("redis_pool_idle_connections", "idle redis connections")?;
+        let ws_connections =
+            IntGauge::new("websocket_connections", "open pubsub websocket connections")?;
+        let pubsub_topics = IntGauge::new("pubsub_topics", "pubsub topics with subscribers")?;
+        let uptime = Gauge::new(
+            "process_uptime_seconds",
+            "seconds since the process started",
+        )?;
+        let build_info = IntGaugeVec::new(
+            Opts::new("build_info", "always 1, labeled with the build"),
+            &["name", "version"],
+        )?;
+        build_info
+            .with_label_values(&[env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")])
+            .set(1);
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_duration.clone()))?;
+        registry.register(Box::new(grpc_requests.clone()))?;
+        registry.register(Box::new(grpc_duration.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_idle.clone()))?;
+        registry.register(Box::new(db_pool_max.clone()))?;
+        registry.register(Box::new(db_pool_acquire.clone()))?;
+        registry.register(Box::new(db_pool_acquire_misses.clone()))?;
+        registry.register(Box::new(redis_pool_connections.clone()))?;
+        registry.register(Box::new(redis_pool_idle.clone()))?;
+        registry.register(Box::new(ws_connections.clone()))?;
+        registry.register(Box::new(pubsub_topics.clone()))?;
+        registry.register(Box::new(uptime.clone()))?;
+        registry.register(Box::new(build_info))?;
+
+        Ok(Metrics {
+            inner: Arc::new(Inner {
+                registry,
+                started: Instant::now(),
+                http_requests,
+                http_duration,
+                grpc_requests,
+                grpc_duration,
+                db_pool_connections,
+                db_pool_idle,
+                db_pool_max,
+                db_pool_acquire,
+                db_pool_acquire_misses,
+                redis_pool_connections,
+                redis_pool_idle,
+                ws_connections,
+                pubsub_topics,
+                uptime,
+            }),
+        })
+    }
+
+    pub(crate) fn observe_http(&self, method: &str, route: &str, status: u16, started: Instant) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+        self.inner.http_requests.with_label_values(&labels).inc();
+        self.inner
+            .http_duration
+            .with_label_values(&labels)
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    pub(crate) fn observe_grpc(&self, method: &str, code: tonic::Code, started: Instant) {
+        let code = format!("{code:?}");
+        let labels = [method, code.as_str()];
+        self.inner.grpc_requests.with_label_values(&labels).inc();
+        self.inner
+            .grpc_duration
+            .with_label_values(&labels)
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    /// samples the gauges, then `encode`
+    pub(crate) async fn render(&self, app_state: &AppState) -> String {
+        let inner = &self.inner;
+        inner
+            .db_pool_connections
+            .set(app_state.db_pool.size() as i64);
+        inner.db_pool_idle.set(app_state.db_pool.num_idle() as i64);
+        inner
+            .db_pool_max
+            .set(app_state.db_pool.options().get_max_connections() as i64);
+        let started = Instant::now();
+        // bounded well below the scrape timeout, an outage must not take the metrics down
+        match tokio::time::timeout(ACQUIRE_PROBE_TIMEOUT, app_state.db_pool.acquire()).await {
+            Ok(Ok(conn)) => drop(conn),
+            _ => inner.db_pool_acquire_misses.inc(),
+        }
+        inner.db_pool_acquire.set(started.elapsed().as_secs_f64());
+        let redis = app_state.redis_pool.state();
+        inner.redis_pool_connections.set(redis.connections as i64);
+        inner.redis_pool_idle.set(redis.idle_connections as i64);
+        #[cfg(feature = "enable_websocket_pubsub_sample")]
+        {
+            inner
+                .ws_connections
+                .set(app_state.pubsub.connection_count() as i64);
+            inner
+                .pubsub_topics
+                .set(app_state.pubsub.topic_count().await as i64);
+        }
+        self.encode()
+    }
+
+    /// prometheus text exposition format
+    pub(crate) fn encode(&self) -> String {
+        self.inner
+            .uptime
+            .set(self.inner.started.elapsed().as_secs_f64());
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.inner.registry.gather(), &mut buffer) {
+            tracing::error!("unable to encode metrics: {e:?}");
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// rest side, layered in `init_router`. the static fallback is not a route and is not counted
+pub(crate) async fn track_http<B>(
+    State(metrics): State<Metrics>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned());
+    if let Some(route) = &route {
+        tracing::Span::current().record("route", route.as_str());
+    }
+    let res = next.run(request).await;
+    if let Some(route) = route {
+        metrics.observe_http(method.as_str(), &route, res.status().as_u16(), started);
+    }
+    res
+}
+
+/// grpc side, wraps the tonic service before it goes into `MultiplexService`.
+/// the code usually arrives in the trailers so the body is wrapped to catch it
+#[derive(Clone, Debug)]
+pub(crate) struct GrpcMetricsLayer {
+    metrics: Metrics,
+}
+
+impl GrpcMetricsLayer {
+    pub(crate) fn new(metrics: Metrics) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for GrpcMetricsLayer {
+    type Service = GrpcMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcMetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct GrpcMetricsService<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for GrpcMetricsService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<GrpcMetricsBody<ResBody>>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let mut recorder = Some(GrpcRecorder {
+            metrics: self.metrics.clone(),
+            method: req.uri().path().to_owned(),
+            started: Instant::now(),
+        });
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let res = match future.await {
+                Ok(res) => res,
+                Err(e) => {
+                    if let Some(recorder) = recorder.take() {
+                        recorder.finish(tonic::Code::Unknown);
+                    }
+                    return Err(e);
+                }
+            };
+            // trailers-only responses (most errors) carry the code in the headers
+            if let Some(code) = grpc_code(res.headers()) {
+                if let Some(recorder) = recorder.take() {
+                    recorder.finish(code);
+                }
+            }
+            Ok(res.map(|inner| GrpcMetricsBody { inner, recorder }))
+        })
+    }
+}
+
+struct GrpcRecorder {
+    metrics: Metrics,
+    method: String,
+    started: Instant,
+}
+
+impl GrpcRecorder {
+    fn finish(self, code: tonic::Code) {
+        self.metrics.observe_grpc(&self.method, code, self.started);
+    }
+}
+
+pub(crate) struct GrpcMetricsBody<B> {
+    inner: B,
+    recorder: Option<GrpcRecorder>,
+}
+
+impl<B> HttpBody for GrpcMetricsBody<B>
+where
+    B: HttpBody + Unpin,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let result = futures::ready!(Pin::new(&mut self.inner).poll_trailers(cx));
+        if let Some(recorder) = self.recorder.take() {
+            let code = match &result {
+                Ok(Some(trailers)) => grpc_code(trailers).unwrap_or(tonic::Code::Ok),
+                Ok(None) => tonic::Code::Ok,
+                Err(_) => tonic::Code::Unknown,
+            };
+            recorder.finish(code);
+        }
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for GrpcMetricsBody<B> {
+    fn drop(&mut self) {
+        // body dropped before the trailers, the client went away
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish(tonic::Code::Cancelled);
+        }
+    }
+}
+
+fn grpc_code(headers: &HeaderMap) -> Option<tonic::Code> {
+    headers
+        .get("grpc-status")
+        .map(|v| tonic::Code::from_bytes(v.as_bytes()))
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    Pool,
+};
+
+use crate::{app_state::DataBase, diagnostics};
+
+// one directory per database, the versions have to stay in step
+#[cfg(feature = "use_sqlite")]
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+#[cfg(feature = "use_postgres")]
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[derive(Debug)]
+pub(crate) struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    pub checksum_mismatch: bool,
+    pub reversible: bool,
+}
+
+pub(crate) async fn up(pool: &Pool<DataBase>) -> diagnostics::Result<()> {
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+pub(crate) async fn status(pool: &Pool<DataBase>) -> diagnostics::Result<Vec<MigrationStatus>> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum))
+        .collect::<HashMap<_, _>>();
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.contains_key(&m.version),
+            checksum_mismatch: applied
+                .get(&m.version)
+                .is_some_and(|checksum| *checksum != m.checksum),
+            reversible: m.migration_type.is_reversible(),
+        })
+        .collect())
+}
+
+/// reverts applied migrations down to `target`, or only the latest one when `None`.
+/// returns the reverted versions
+pub(crate) async fn revert(
+    pool: &Pool<DataBase>,
+    target: Option<i64>,
+) -> diagnostics::Result<Vec<i64>> {
+    let applied = status(pool)
+        .await?
+        .into_iter()
+        .filter(|m| m.applied)
+        .collect::<Vec<_>>();
+    let target = match target {
+        Some(target) => target,
+        None => match applied.iter().rev().nth(1) {
+            Some(previous) => previous.version,
+            None => 0,
+        },
+    };
+
+    let reverting = applied
+        .iter()
+        .filter(|m| m.version > target)
+        .collect::<Vec<_>>();
+    if let Some(m) = reverting.iter().find(|m| !m.reversible) {
+        return Err(diagnostics::Error::Message(format!(
+            "migration {} ({}) has no down script",
+            m.version, m.description
+        )));
+    }
+
+    MIGRATOR.undo(pool, target).await?;
+    Ok(reverting.iter().map(|m| m.version).collect())
+}
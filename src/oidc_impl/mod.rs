@@ -0,0 +1,129 @@
+mod provider;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_session::Session;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{diagnostics, util::config::OidcConfig};
+
+pub(crate) use self::provider::{Identity, OidcProvider};
+
+// login in progress, kept in the session between the redirect and the callback
+const PENDING_KEY: &str = "__oidc";
+// discovery, keys and the token exchange each get this long
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// the providers of `[oidc.providers]` by name
+#[derive(Clone, Debug)]
+pub(crate) struct OidcProviders {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    providers: HashMap<String, OidcProvider>,
+    post_login_redirect: String,
+    flow_ttl: Duration,
+}
+
+impl OidcProviders {
+    /// `Err` for a provider config that `OidcConfig::validate` rejects
+    pub(crate) fn new(config: &OidcConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let http = reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?;
+        let providers = config
+            .providers
+            .iter()
+            .map(|(name, provider)| {
+                (
+                    name.clone(),
+                    OidcProvider::new(provider.clone(), http.clone()),
+                )
+            })
+            .collect();
+        Ok(Self {
+            inner: Arc::new(Inner {
+                providers,
+                post_login_redirect: config.post_login_redirect.clone(),
+                flow_ttl: config.flow_ttl(),
+            }),
+        })
+    }
+
+    /// `NotFound` for a name that is not configured
+    pub(crate) fn get(&self, name: &str) -> diagnostics::Result<&OidcProvider> {
+        self.inner
+            .providers
+            .get(name)
+            .ok_or(diagnostics::Error::NotFound)
+    }
+
+    pub(crate) fn post_login_redirect(&self) -> &str {
+        &self.inner.post_login_redirect
+    }
+
+    pub(crate) fn flow_ttl(&self) -> Duration {
+        self.inner.flow_ttl
+    }
+}
+
+/// one login between the redirect to the provider and its callback. `state` guards the
+/// callback against forged requests, `nonce` the id token against replay and
+/// `code_verifier` the code against interception (pkce)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PendingLogin {
+    pub provider: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    // unix seconds
+    pub started_at: i64,
+}
+
+impl PendingLogin {
+    pub(crate) fn new(provider: &str) -> Self {
+        Self {
+            provider: provider.to_owned(),
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+            started_at: now(),
+        }
+    }
+
+    /// S256 challenge of the verifier, sent with the authorization request
+    pub(crate) fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+
+    pub(crate) fn is_expired(&self, ttl: Duration) -> bool {
+        now() - self.started_at > ttl.as_secs() as i64
+    }
+
+    pub(crate) fn store(&self, session: &mut Session) -> serde_json::Result<()> {
+        session.insert(PENDING_KEY, self)
+    }
+
+    /// removes the pending login from the session. store the session right after, so a
+    /// callback can only use it once even when it fails
+    pub(crate) fn take(session: &mut Session) -> Option<Self> {
+        let pending = session.get(PENDING_KEY);
+        session.remove(PENDING_KEY);
+        pending
+    }
+}
+
+// 256 random bits, url safe
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn now() -> i64 {
+    jsonwebtoken::get_current_timestamp() as i64
+}
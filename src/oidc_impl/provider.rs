@@ -0,0 +1,215 @@
+use std::fmt;
+
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::{
+    diagnostics::{self, Error},
+    util::config::OidcProviderConfig,
+};
+
+/// the parts of the discovery document the login needs
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// who signed in, from the verified id token
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Identity {
+    pub sub: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: Option<bool>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    #[serde(flatten)]
+    identity: Identity,
+    #[serde(default)]
+    nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// one configured openid provider. discovery and keys are fetched on first use, so a
+/// provider that is down does not keep the app from starting
+pub(crate) struct OidcProvider {
+    config: OidcProviderConfig,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: RwLock<JwkSet>,
+}
+
+impl fmt::Debug for OidcProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcProvider")
+            .field("discovery_url", &self.config.discovery_url)
+            .field("client_id", &self.config.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OidcProvider {
+    pub(crate) fn new(config: OidcProviderConfig, http: reqwest::Client) -> Self {
+        Self {
+            config,
+            http,
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(JwkSet { keys: Vec::new() }),
+        }
+    }
+
+    pub(crate) async fn metadata(&self) -> diagnostics::Result<&ProviderMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let metadata: ProviderMetadata = self.get_json(&self.config.discovery_url).await?;
+                tracing::debug!("discovered openid provider `{}`", metadata.issuer);
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// where to send the browser, the provider redirects back to `redirect_url` with a code
+    pub(crate) async fn authorize_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> diagnostics::Result<String> {
+        let metadata = self.metadata().await?;
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| Error::OidcError(format!("authorization_endpoint: {e}")))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    /// trades the code for tokens and returns the identity of the verified id token.
+    /// `Unauthorized` when the provider rejects the code or the id token does not check out
+    pub(crate) async fn exchange(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> diagnostics::Result<Identity> {
+        let metadata = self.metadata().await?;
+        let res = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.config.redirect_url),
+                ("client_id", &self.config.client_id),
+                ("client_secret", &self.config.client_secret),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .map_err(|e| Error::OidcError(format!("token endpoint: {e}")))?;
+        if res.status().is_client_error() {
+            tracing::debug!("token endpoint rejected the code: {}", res.status());
+            return Err(Error::Unauthorized);
+        }
+        let tokens: TokenResponse = json_body(res).await?;
+        self.verify_id_token(&tokens.id_token, nonce).await
+    }
+
+    async fn verify_id_token(&self, token: &str, nonce: &str) -> diagnostics::Result<Identity> {
+        let metadata = self.metadata().await?;
+        let header = jsonwebtoken::decode_header(token).map_err(|e| {
+            tracing::debug!("malformed id token: {e}");
+            Error::Unauthorized
+        })?;
+        // provider keys are asymmetric, an hmac id token would be signed with our own secret
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            tracing::debug!("rejected id token signed with {:?}", header.alg);
+            return Err(Error::Unauthorized);
+        }
+        let jwk = self.jwk(header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk)
+            .map_err(|e| Error::OidcError(format!("unusable jwk: {e}")))?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(token, &key, &validation)
+            .map_err(|e| {
+                tracing::debug!("rejected id token: {e}");
+                Error::Unauthorized
+            })?
+            .claims;
+        // the nonce ties the token to the login this browser started
+        match claims.nonce {
+            Some(claims_nonce) if claims_nonce == nonce => Ok(claims.identity),
+            _ => {
+                tracing::debug!("id token nonce does not match");
+                Err(Error::Unauthorized)
+            }
+        }
+    }
+
+    /// signing key by id, the key set is fetched again once when the id is unknown
+    /// since providers rotate keys
+    async fn jwk(&self, kid: Option<&str>) -> diagnostics::Result<Jwk> {
+        let find = |jwks: &JwkSet| match kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None => (jwks.keys.len() == 1).then(|| jwks.keys[0].clone()),
+        };
+        if let Some(jwk) = find(&*self.jwks.read().await) {
+            return Ok(jwk);
+        }
+        let metadata = self.metadata().await?;
+        let fetched: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+        let jwk = find(&fetched);
+        *self.jwks.write().await = fetched;
+        jwk.ok_or_else(|| {
+            tracing::debug!("no provider key with id {kid:?}");
+            Error::Unauthorized
+        })
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> diagnostics::Result<T> {
+        let res = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| Error::OidcError(format!("{url}: {e}")))?;
+        json_body(res).await
+    }
+}
+
+async fn json_body<T: DeserializeOwned>(res: reqwest::Response) -> diagnostics::Result<T> {
+    let url = res.url().clone();
+    res.error_for_status()
+        .map_err(|e| Error::OidcError(e.to_string()))?
+        .json()
+        .await
+        .map_err(|e| Error::OidcError(format!("{url}: {e}")))
+}
@@ -6,6 +6,7 @@ use std::{
 use axum::response::{IntoResponse, Response};
 use futures::{future::BoxFuture, ready};
 use hyper::{header::CONTENT_TYPE, Request};
+use prost::Message;
 use tower::Service;
 
 // https://github.com/tokio-rs/axum/blob/main/examples/rest-grpc-multiplex/src/multiplex_service.rs
@@ -119,3 +120,33 @@ pub(crate) mod voting;
 
 pub(crate) const FILE_DESCRIPTOR_SET: &[u8] =
     tonic::include_file_descriptor_set!("proto_descriptor");
+
+// registered in `run_with_grpc` but not part of FILE_DESCRIPTOR_SET
+const EXTERNAL_GRPC_ROUTES: [&str; 3] = [
+    "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo",
+    "/grpc.health.v1.Health/Check",
+    "/grpc.health.v1.Health/Watch",
+];
+
+/// `/package.Service/Method` for every served grpc method
+pub(crate) fn grpc_routes() -> anyhow::Result<Vec<String>> {
+    let descriptor_set = prost_types::FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)?;
+    let mut routes = descriptor_set
+        .file
+        .iter()
+        .flat_map(|file| {
+            file.service.iter().flat_map(move |service| {
+                service.method.iter().map(move |method| {
+                    format!(
+                        "/{}.{}/{}",
+                        file.package(),
+                        service.name(),
+                        method.name()
+                    )
+                })
+            })
+        })
+        .collect::<Vec<String>>();
+    routes.extend(EXTERNAL_GRPC_ROUTES.iter().map(|r| r.to_string()));
+    Ok(routes)
+}
@@ -2,9 +2,9 @@ tonic::include_proto!("voting");
 
 use tonic::{Request, Response, Status};
 
-use self::voting_server::Voting;
-
+use crate::diagnostics::Error;
 
+use self::voting_server::Voting;
 
 #[derive(Debug, Default)]
 pub(crate) struct VotingService {}
@@ -23,10 +23,7 @@ impl Voting for VotingService {
             1 => Ok(Response::new(VotingResponse {
                 confirmation: { format!("Confirmation that you downvoted for {}", r.url) },
             })),
-            _ => Err(Status::new(
-                tonic::Code::OutOfRange,
-                "Invalid vote provided",
-            )),
+            _ => Err(Error::Message("Invalid vote provided".to_owned()).into()),
         }
     }
 }
@@ -0,0 +1,170 @@
+use axum::{async_trait, extract::FromRef};
+use sqlx::{types::Json, Pool, QueryBuilder};
+
+use crate::{
+    app_state::{AppState, DataBase},
+    diagnostics,
+    entity::{ApiKey, Entity},
+    repository::{
+        page::{self, FieldKind, Pageable, Value},
+        BasicRepository, Page, PageRequest,
+    },
+};
+
+#[async_trait]
+pub(crate) trait ApiKeyRepository: BasicRepository<ApiKey> + FromRef<AppState> {
+    async fn find_by_hash(&self, key_hash: &str) -> diagnostics::Result<Option<ApiKey>>;
+}
+
+#[derive(Clone)]
+pub(crate) struct ApiKeyRepositoryDB {
+    pub pool: Pool<DataBase>,
+}
+
+impl ApiKeyRepositoryDB {
+    pub fn new(pool: Pool<DataBase>) -> Self {
+        ApiKeyRepositoryDB { pool }
+    }
+}
+
+#[async_trait]
+impl BasicRepository<ApiKey> for ApiKeyRepositoryDB {
+    async fn create(&self, entity: ApiKey) -> diagnostics::Result<ApiKey> {
+        sqlx::query_as::<_, ApiKey>(
+            r#" insert into api_keys(name, prefix, key_hash, scopes, created_at, expires_at)
+                values ($1, $2, $3, $4, $5, $6) returning * "#,
+        )
+        .bind(entity.name.as_str())
+        .bind(entity.prefix.as_str())
+        .bind(entity.key_hash.as_str())
+        .bind(Json(&entity.scopes))
+        .bind(entity.created_at)
+        .bind(entity.expires_at)
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(diagnostics::Error::RowNotFound)
+    }
+
+    async fn find_all(&self) -> diagnostics::Result<Vec<ApiKey>> {
+        Ok(
+            sqlx::query_as::<_, ApiKey>("select * from api_keys order by id")
+                .fetch_all(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn find_page(&self, request: PageRequest) -> diagnostics::Result<Page<ApiKey>> {
+        page::find_page(&self.pool, &request).await
+    }
+
+    async fn find_by_id(&self, id: &'_ i64) -> diagnostics::Result<ApiKey> {
+        Ok(
+            sqlx::query_as::<_, ApiKey>("select * from api_keys where id = ($1)")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn update(&self, entity: ApiKey) -> diagnostics::Result<ApiKey> {
+        sqlx::query_as::<_, ApiKey>(
+            r#" update api_keys set name = $1, scopes = $2, expires_at = $3 where id = $4 returning * "#,
+        )
+        .bind(entity.name.as_str())
+        .bind(Json(&entity.scopes))
+        .bind(entity.expires_at)
+        .bind(entity.id)
+        .fetch_all(&self.pool)
+        .await?
+        .pop()
+        .ok_or(diagnostics::Error::RowNotFound)
+    }
+
+    async fn delete_all(&self) -> diagnostics::Result<()> {
+        sqlx::query(r#"delete from api_keys"#)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_by_id(&self, id: &'_ i64) -> diagnostics::Result<()> {
+        sqlx::query(r#"delete from api_keys where id = ($1)"#)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_all_by_id<I>(&self, ids: I) -> diagnostics::Result<Vec<ApiKey>>
+    where
+        I: Iterator<Item = &'async_trait <ApiKey as Entity>::ID> + Send,
+        <ApiKey as Entity>::ID: 'async_trait,
+    {
+        let mut query_builder: QueryBuilder<DataBase> =
+            QueryBuilder::new("select * from api_keys where id in (");
+        let mut separated = query_builder.separated(", ");
+        ids.for_each(|id| {
+            separated.push_bind(id);
+        });
+        separated.push_unseparated(") ");
+        Ok(query_builder
+            .build_query_as::<ApiKey>()
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn delete_all_by_id<I>(&self, ids: I) -> diagnostics::Result<()>
+    where
+        I: Iterator<Item = &'async_trait <ApiKey as Entity>::ID> + Send,
+        <ApiKey as Entity>::ID: 'async_trait,
+    {
+        let mut query_builder: QueryBuilder<DataBase> =
+            QueryBuilder::new("delete from api_keys where id in (");
+        let mut separated = query_builder.separated(", ");
+        ids.for_each(|id| {
+            separated.push_bind(id);
+        });
+        separated.push_unseparated(") ");
+        query_builder.build().execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+impl Pageable for ApiKey {
+    const TABLE: &'static str = "api_keys";
+    const FIELDS: &'static [(&'static str, FieldKind)] = &[
+        ("id", FieldKind::Integer),
+        ("name", FieldKind::Text),
+        ("prefix", FieldKind::Text),
+        ("created_at", FieldKind::Integer),
+    ];
+
+    fn value(&self, field: &str) -> Option<Value> {
+        match field {
+            "id" => Some(Value::Integer(self.id)),
+            "name" => Some(Value::Text(self.name.clone())),
+            "prefix" => Some(Value::Text(self.prefix.clone())),
+            "created_at" => Some(Value::Integer(self.created_at)),
+            _ => None,
+        }
+    }
+}
+
+impl FromRef<AppState> for ApiKeyRepositoryDB {
+    fn from_ref(state: &AppState) -> Self {
+        ApiKeyRepositoryDB::new(state.db_pool.clone())
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for ApiKeyRepositoryDB {
+    async fn find_by_hash(&self, key_hash: &str) -> diagnostics::Result<Option<ApiKey>> {
+        Ok(
+            sqlx::query_as::<_, ApiKey>("select * from api_keys where key_hash = ($1)")
+                .bind(key_hash)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+}
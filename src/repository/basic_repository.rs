@@ -1,6 +1,10 @@
 use axum::async_trait;
 
-use crate::{diagnostics, entity::Entity};
+use crate::{
+    diagnostics,
+    entity::Entity,
+    repository::{Page, PageRequest},
+};
 
 #[async_trait]
 pub(crate) trait BasicRepository<EntityT>
@@ -10,6 +14,8 @@ where
     async fn create(&self, entity: EntityT) -> diagnostics::Result<EntityT>;
 
     async fn find_all(&self) -> diagnostics::Result<Vec<EntityT>>;
+    /// rows in the order and with the filters of `request`, see `page::find_page`
+    async fn find_page(&self, request: PageRequest) -> diagnostics::Result<Page<EntityT>>;
     async fn find_by_id(&self, id: &'_ EntityT::ID) -> diagnostics::Result<EntityT>;
 
     async fn find_all_by_id<I>(&self, ids: I) -> diagnostics::Result<Vec<EntityT>>
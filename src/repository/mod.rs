@@ -1,5 +1,8 @@
+pub(crate) mod api_key_repository;
 pub(crate) mod basic_repository;
+pub(crate) mod page;
 pub(crate) mod sample_repository;
+pub(crate) mod user_repository;
 
 use axum::{
     async_trait,
@@ -25,4 +28,7 @@ where
 }
 
 pub(crate) use basic_repository::BasicRepository;
+pub(crate) use self::api_key_repository::{ApiKeyRepository, ApiKeyRepositoryDB};
+pub(crate) use self::page::{Page, PageRequest};
 pub(crate) use self::sample_repository::{SampleRepository, SampleRepositoryDB};
+pub(crate) use self::user_repository::{UserRepository, UserRepositoryDB};
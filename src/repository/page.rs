@@ -0,0 +1,377 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::{Database, FromRow, Pool, QueryBuilder};
+
+use crate::{
+    app_state::DataBase,
+    diagnostics::{self, Error},
+    entity::Entity,
+};
+
+pub(crate) const DEFAULT_LIMIT: u32 = 20;
+pub(crate) const MAX_LIMIT: u32 = 100;
+// values of one `in` filter
+const MAX_IN_VALUES: usize = 100;
+// every pageable table has it, it breaks ties between equal sort values
+const KEY_FIELD: &str = "id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FieldKind {
+    Integer,
+    Text,
+}
+
+/// a column value in a filter or a cursor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Value {
+    Integer(i64),
+    Text(String),
+}
+
+impl Value {
+    fn parse(field: &str, kind: FieldKind, raw: &str) -> diagnostics::Result<Self> {
+        match kind {
+            FieldKind::Integer => raw
+                .trim()
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| Error::Message(format!("`{field}` takes integers"))),
+            FieldKind::Text => Ok(Value::Text(raw.to_owned())),
+        }
+    }
+
+    fn kind(&self) -> FieldKind {
+        match self {
+            Value::Integer(_) => FieldKind::Integer,
+            Value::Text(_) => FieldKind::Text,
+        }
+    }
+
+    fn push_bind(&self, query: &mut QueryBuilder<'_, DataBase>) {
+        match self {
+            Value::Integer(v) => query.push_bind(*v),
+            Value::Text(v) => query.push_bind(v.clone()),
+        };
+    }
+}
+
+/// an entity `find_page` can list. only the columns in `FIELDS` can be sorted and
+/// filtered by, names from a request never reach the sql otherwise
+pub(crate) trait Pageable:
+    Entity + for<'r> FromRow<'r, <DataBase as Database>::Row> + Send + Unpin
+{
+    const TABLE: &'static str;
+    /// has to include the `id` primary key
+    const FIELDS: &'static [(&'static str, FieldKind)];
+
+    /// value of one of `FIELDS`, for the cursor after this row
+    fn value(&self, field: &str) -> Option<Value>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Sort {
+    pub field: &'static str,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum FilterOp {
+    Eq(Value),
+    // sql `like` pattern, how case is treated is up to the database
+    Like(String),
+    In(Vec<Value>),
+    // inclusive bounds, at least one of them set
+    Range(Option<Value>, Option<Value>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Filter {
+    pub field: &'static str,
+    pub op: FilterOp,
+}
+
+/// where a page starts: after the row a previous page ended with. only valid for the
+/// sort it was made for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Cursor {
+    sort: String,
+    descending: bool,
+    value: Value,
+    id: i64,
+}
+
+impl Cursor {
+    fn after<T: Pageable>(row: &T, sort: &Sort) -> Option<Self> {
+        let Some(Value::Integer(id)) = row.value(KEY_FIELD) else {
+            return None;
+        };
+        Some(Cursor {
+            sort: sort.field.to_owned(),
+            descending: sort.descending,
+            value: row.value(sort.field)?,
+            id,
+        })
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub(crate) fn decode(cursor: &str) -> diagnostics::Result<Self> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| Error::Message("cursor is not valid".to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PageRequest {
+    pub limit: u32,
+    pub offset: u32,
+    pub cursor: Option<Cursor>,
+    pub sort: Sort,
+    pub filters: Vec<Filter>,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_LIMIT,
+            offset: 0,
+            cursor: None,
+            sort: Sort {
+                field: KEY_FIELD,
+                descending: false,
+            },
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl PageRequest {
+    /// from query string pairs: `limit`, `offset`, `cursor`, `sort=name` or `sort=-name`
+    /// and filters `field=v`, `field[eq]=v`, `field[like]=a%`, `field[in]=a,b`,
+    /// `field[gte]=v`, `field[lte]=v`. everything is checked against `T::FIELDS`
+    pub(crate) fn from_query<T: Pageable>(pairs: &[(String, String)]) -> diagnostics::Result<Self> {
+        let mut request = PageRequest::default();
+        let mut cursor = None;
+        for (key, raw) in pairs {
+            match key.as_str() {
+                "limit" => {
+                    request.limit = raw
+                        .parse()
+                        .ok()
+                        .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                        .ok_or_else(|| {
+                            Error::Message(format!("limit must be between 1 and {MAX_LIMIT}"))
+                        })?
+                }
+                "offset" => {
+                    request.offset = raw.parse().map_err(|_| {
+                        Error::Message("offset must be a non-negative integer".to_owned())
+                    })?
+                }
+                "cursor" => cursor = Some(Cursor::decode(raw)?),
+                "sort" => {
+                    let (name, descending) = match raw.strip_prefix('-') {
+                        Some(name) => (name, true),
+                        None => (raw.as_str(), false),
+                    };
+                    let (field, _) = field::<T>(name)?;
+                    request.sort = Sort { field, descending };
+                }
+                _ => request.push_filter::<T>(key, raw)?,
+            }
+        }
+
+        if let Some(cursor) = cursor {
+            if request.offset != 0 {
+                return Err(Error::Message(
+                    "cursor and offset can't be used together".to_owned(),
+                ));
+            }
+            let (_, kind) = field::<T>(&cursor.sort)?;
+            if cursor.sort != request.sort.field
+                || cursor.descending != request.sort.descending
+                || cursor.value.kind() != kind
+            {
+                return Err(Error::Message(
+                    "cursor was made for another sort".to_owned(),
+                ));
+            }
+            request.cursor = Some(cursor);
+        }
+        Ok(request)
+    }
+
+    fn push_filter<T: Pageable>(&mut self, key: &str, raw: &str) -> diagnostics::Result<()> {
+        let (name, op) = match key.split_once('[') {
+            Some((name, op)) => match op.strip_suffix(']') {
+                Some(op) => (name, op),
+                None => return Err(Error::Message(format!("`{key}` is not a filter"))),
+            },
+            None => (key, "eq"),
+        };
+        let (field, kind) = field::<T>(name)?;
+        let value = |raw: &str| Value::parse(field, kind, raw);
+        let op = match op {
+            "eq" => FilterOp::Eq(value(raw)?),
+            "like" if kind == FieldKind::Text => FilterOp::Like(raw.to_owned()),
+            "like" => {
+                return Err(Error::Message(format!(
+                    "`{field}` can't be filtered with like"
+                )))
+            }
+            "in" => {
+                let values = raw.split(',').map(value).collect::<Result<Vec<_>, _>>()?;
+                if values.len() > MAX_IN_VALUES {
+                    return Err(Error::Message(format!(
+                        "`in` takes at most {MAX_IN_VALUES} values"
+                    )));
+                }
+                FilterOp::In(values)
+            }
+            "gte" | "lte" => {
+                let bound = Some(value(raw)?);
+                // both bounds of a field end up in one range
+                let index = match self.filters.iter().position(|filter| {
+                    filter.field == field && matches!(filter.op, FilterOp::Range(..))
+                }) {
+                    Some(index) => index,
+                    None => {
+                        self.filters.push(Filter {
+                            field,
+                            op: FilterOp::Range(None, None),
+                        });
+                        self.filters.len() - 1
+                    }
+                };
+                if let FilterOp::Range(min, max) = &mut self.filters[index].op {
+                    if op == "gte" {
+                        *min = bound;
+                    } else {
+                        *max = bound;
+                    }
+                }
+                return Ok(());
+            }
+            _ => return Err(Error::Message(format!("unknown filter `{op}`"))),
+        };
+        self.filters.push(Filter { field, op });
+        Ok(())
+    }
+}
+
+fn field<T: Pageable>(name: &str) -> diagnostics::Result<(&'static str, FieldKind)> {
+    T::FIELDS
+        .iter()
+        .find(|(field, _)| *field == name)
+        .copied()
+        .ok_or_else(|| Error::Message(format!("unknown field `{name}`")))
+}
+
+/// one page of a listing. `total` counts every row the filters match, `next_cursor` is
+/// `None` on the last page
+#[derive(Debug, Serialize)]
+pub(crate) struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+/// `find_page` for the database repositories
+pub(crate) async fn find_page<T: Pageable>(
+    pool: &Pool<DataBase>,
+    request: &PageRequest,
+) -> diagnostics::Result<Page<T>> {
+    let mut count = QueryBuilder::new(format!("select count(*) from {} where 1 = 1", T::TABLE));
+    push_filters(&mut count, &request.filters);
+    let total = count.build_query_scalar::<i64>().fetch_one(pool).await?;
+
+    let sort = &request.sort;
+    let mut select = QueryBuilder::new(format!("select * from {} where 1 = 1", T::TABLE));
+    push_filters(&mut select, &request.filters);
+    if let Some(cursor) = &request.cursor {
+        let cmp = if sort.descending { " < " } else { " > " };
+        if sort.field == KEY_FIELD {
+            select
+                .push(format!(" and {KEY_FIELD}{cmp}"))
+                .push_bind(cursor.id);
+        } else {
+            select.push(format!(" and ({}{cmp}", sort.field));
+            cursor.value.push_bind(&mut select);
+            select.push(format!(" or ({} = ", sort.field));
+            cursor.value.push_bind(&mut select);
+            select
+                .push(format!(" and {KEY_FIELD}{cmp}"))
+                .push_bind(cursor.id)
+                .push("))");
+        }
+    }
+    let direction = if sort.descending { "desc" } else { "asc" };
+    select.push(format!(
+        " order by {} {direction}, {KEY_FIELD} {direction}",
+        sort.field
+    ));
+    // one row more than asked for tells whether there is a next page
+    select
+        .push(" limit ")
+        .push_bind(request.limit as i64 + 1)
+        .push(" offset ")
+        .push_bind(request.offset as i64);
+    let mut items = select.build_query_as::<T>().fetch_all(pool).await?;
+
+    let next_cursor = if items.len() > request.limit as usize {
+        items.truncate(request.limit as usize);
+        items
+            .last()
+            .and_then(|row| Cursor::after(row, sort))
+            .map(|cursor| cursor.encode())
+    } else {
+        None
+    };
+    Ok(Page {
+        items,
+        total,
+        next_cursor,
+    })
+}
+
+fn push_filters(query: &mut QueryBuilder<'_, DataBase>, filters: &[Filter]) {
+    for Filter { field, op } in filters {
+        query.push(format!(" and {field}"));
+        match op {
+            FilterOp::Eq(value) => {
+                query.push(" = ");
+                value.push_bind(query);
+            }
+            FilterOp::Like(pattern) => {
+                query.push(" like ").push_bind(pattern.clone());
+            }
+            FilterOp::In(values) => {
+                query.push(" in (");
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        query.push(", ");
+                    }
+                    value.push_bind(query);
+                }
+                query.push(")");
+            }
+            FilterOp::Range(min, max) => {
+                query.push(" is not null");
+                if let Some(min) = min {
+                    query.push(format!(" and {field} >= "));
+                    min.push_bind(query);
+                }
+                if let Some(max) = max {
+                    query.push(format!(" and {field} <= "));
+                    max.push_bind(query);
+                }
+            }
+        }
+    }
+}
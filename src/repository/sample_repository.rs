@@ -5,7 +5,10 @@ use crate::{
     app_state::{AppState, DataBase},
     diagnostics,
     entity::{Entity, Sample},
-    repository::BasicRepository,
+    repository::{
+        page::{self, FieldKind, Pageable, Value},
+        BasicRepository, Page, PageRequest,
+    },
 };
 
 #[async_trait]
@@ -38,6 +41,10 @@ impl BasicRepository<Sample> for SampleRepositoryDB {
             .await?)
     }
 
+    async fn find_page(&self, request: PageRequest) -> diagnostics::Result<Page<Sample>> {
+        page::find_page(&self.pool, &request).await
+    }
+
     async fn find_by_id(&self, id: &'_ i64) -> diagnostics::Result<Sample> {
         Ok(
             sqlx::query_as::<_, Sample>("select * from sample where id = ($1)")
@@ -172,6 +179,20 @@ impl BasicRepository<Sample> for SampleRepositoryDB {
     // }
 }
 
+impl Pageable for Sample {
+    const TABLE: &'static str = "sample";
+    const FIELDS: &'static [(&'static str, FieldKind)] =
+        &[("id", FieldKind::Integer), ("name", FieldKind::Text)];
+
+    fn value(&self, field: &str) -> Option<Value> {
+        match field {
+            "id" => Some(Value::Integer(self.id)),
+            "name" => Some(Value::Text(self.name.clone())),
+            _ => None,
+        }
+    }
+}
+
 impl FromRef<AppState> for SampleRepositoryDB {
     fn from_ref(state: &AppState) -> Self {
         SampleRepositoryDB::new(state.db_pool.clone())
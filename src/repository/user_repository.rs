@@ -0,0 +1,428 @@
+use axum::{async_trait, extract::FromRef};
+use sqlx::{Pool, QueryBuilder};
+
+use crate::{
+    app_state::{AppState, DataBase},
+    authz::Grants,
+    diagnostics,
+    entity::{Entity, User, UserTotp},
+    repository::{
+        page::{self, FieldKind, Pageable, Value},
+        BasicRepository, Page, PageRequest,
+    },
+};
+
+#[async_trait]
+pub(crate) trait UserRepository: BasicRepository<User> + FromRef<AppState> {
+    async fn find_by_email(&self, email: &str) -> diagnostics::Result<Option<User>>;
+
+    /// roles of the user and the permissions of those roles
+    async fn grants(&self, user_id: i64) -> diagnostics::Result<Grants>;
+
+    async fn add_role(&self, user_id: i64, role: &str) -> diagnostics::Result<()>;
+
+    /// `false` when the user did not have the role
+    async fn remove_role(&self, user_id: i64, role: &str) -> diagnostics::Result<bool>;
+
+    /// the user an openid provider account is linked to
+    async fn find_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> diagnostics::Result<Option<User>>;
+
+    async fn link_identity(
+        &self,
+        user_id: i64,
+        provider: &str,
+        subject: &str,
+    ) -> diagnostics::Result<()>;
+
+    async fn find_totp(&self, user_id: i64) -> diagnostics::Result<Option<UserTotp>>;
+
+    /// new unconfirmed secret, replaces one that was not confirmed
+    async fn save_totp(&self, user_id: i64, secret: &str) -> diagnostics::Result<()>;
+
+    /// turns the second factor on with the step of the confirming code and replaces the
+    /// recovery codes. `false` when there is no unconfirmed secret
+    async fn confirm_totp(
+        &self,
+        user_id: i64,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> diagnostics::Result<bool>;
+
+    /// records `step` as used, `false` when it is not newer than the last one
+    async fn use_totp_step(&self, user_id: i64, step: i64) -> diagnostics::Result<bool>;
+
+    /// `false` when the user has no such unused recovery code
+    async fn use_recovery_code(&self, user_id: i64, code_hash: &str) -> diagnostics::Result<bool>;
+
+    /// counts a code check against the user before it is made, `false` when
+    /// `max_attempts` wrong codes in a row locked the second factor
+    async fn claim_mfa_attempt(&self, user_id: i64, max_attempts: u32)
+        -> diagnostics::Result<bool>;
+
+    /// a code checked out, the count starts over
+    async fn reset_mfa_attempts(&self, user_id: i64) -> diagnostics::Result<()>;
+
+    /// removes the secret and the recovery codes, `false` when there was no secret
+    async fn delete_totp(&self, user_id: i64) -> diagnostics::Result<bool>;
+}
+
+#[derive(Clone)]
+pub(crate) struct UserRepositoryDB {
+    pub pool: Pool<DataBase>,
+}
+
+impl UserRepositoryDB {
+    pub fn new(pool: Pool<DataBase>) -> Self {
+        UserRepositoryDB { pool }
+    }
+}
+
+// `insert/update .. returning` is read with `fetch_all`: sqlite keeps the write open on
+// the pooled connection until the statement ran to completion, other connections would
+// not see the row yet
+
+// the email is unique, a second registration is a conflict and not a database error
+fn unique_email(e: sqlx::Error) -> diagnostics::Error {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            diagnostics::Error::Conflict("email is already registered".to_owned())
+        }
+        _ => e.into(),
+    }
+}
+
+#[async_trait]
+impl BasicRepository<User> for UserRepositoryDB {
+    async fn create(&self, entity: User) -> diagnostics::Result<User> {
+        sqlx::query_as::<_, User>(
+            r#" insert into users(name, email, password_hash) values ($1, $2, $3) returning * "#,
+        )
+        .bind(entity.name.as_str())
+        .bind(entity.email.as_str())
+        .bind(entity.password_hash.as_str())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(unique_email)?
+        .pop()
+        .ok_or(diagnostics::Error::RowNotFound)
+    }
+
+    async fn find_all(&self) -> diagnostics::Result<Vec<User>> {
+        Ok(sqlx::query_as::<_, User>("select * from users")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn find_page(&self, request: PageRequest) -> diagnostics::Result<Page<User>> {
+        page::find_page(&self.pool, &request).await
+    }
+
+    async fn find_by_id(&self, id: &'_ i64) -> diagnostics::Result<User> {
+        Ok(
+            sqlx::query_as::<_, User>("select * from users where id = ($1)")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn update(&self, entity: User) -> diagnostics::Result<User> {
+        sqlx::query_as::<_, User>(
+            r#" update users set name = $1, email = $2, password_hash = $3 where id = $4 returning * "#,
+        )
+        .bind(entity.name.as_str())
+        .bind(entity.email.as_str())
+        .bind(entity.password_hash.as_str())
+        .bind(entity.id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(unique_email)?
+        .pop()
+        .ok_or(diagnostics::Error::RowNotFound)
+    }
+
+    async fn delete_all(&self) -> diagnostics::Result<()> {
+        sqlx::query(r#"delete from users"#)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_by_id(&self, id: &'_ i64) -> diagnostics::Result<()> {
+        sqlx::query(r#"delete from users where id = ($1)"#)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_all_by_id<I>(&self, ids: I) -> diagnostics::Result<Vec<User>>
+    where
+        I: Iterator<Item = &'async_trait <User as Entity>::ID> + Send,
+        <User as Entity>::ID: 'async_trait,
+    {
+        let mut query_builder: QueryBuilder<DataBase> =
+            QueryBuilder::new("select * from users where id in (");
+        let mut separated = query_builder.separated(", ");
+        ids.for_each(|id| {
+            separated.push_bind(id);
+        });
+        separated.push_unseparated(") ");
+        Ok(query_builder
+            .build_query_as::<User>()
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn delete_all_by_id<I>(&self, ids: I) -> diagnostics::Result<()>
+    where
+        I: Iterator<Item = &'async_trait <User as Entity>::ID> + Send,
+        <User as Entity>::ID: 'async_trait,
+    {
+        let mut query_builder: QueryBuilder<DataBase> =
+            QueryBuilder::new("delete from users where id in (");
+        let mut separated = query_builder.separated(", ");
+        ids.for_each(|id| {
+            separated.push_bind(id);
+        });
+        separated.push_unseparated(") ");
+        query_builder.build().execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+impl Pageable for User {
+    const TABLE: &'static str = "users";
+    const FIELDS: &'static [(&'static str, FieldKind)] = &[
+        ("id", FieldKind::Integer),
+        ("name", FieldKind::Text),
+        ("email", FieldKind::Text),
+    ];
+
+    fn value(&self, field: &str) -> Option<Value> {
+        match field {
+            "id" => Some(Value::Integer(self.id)),
+            "name" => Some(Value::Text(self.name.clone())),
+            "email" => Some(Value::Text(self.email.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl FromRef<AppState> for UserRepositoryDB {
+    fn from_ref(state: &AppState) -> Self {
+        UserRepositoryDB::new(state.db_pool.clone())
+    }
+}
+
+#[async_trait]
+impl UserRepository for UserRepositoryDB {
+    async fn find_by_email(&self, email: &str) -> diagnostics::Result<Option<User>> {
+        Ok(
+            sqlx::query_as::<_, User>("select * from users where email = ($1)")
+                .bind(email)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn grants(&self, user_id: i64) -> diagnostics::Result<Grants> {
+        let roles = sqlx::query_scalar::<_, String>(
+            "select role from user_roles where user_id = ($1) order by role",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let permissions = sqlx::query_scalar::<_, String>(
+            r#" select distinct p.permission from user_roles r
+                join role_permissions p on p.role = r.role
+                where r.user_id = ($1) order by p.permission "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Grants { roles, permissions })
+    }
+
+    async fn add_role(&self, user_id: i64, role: &str) -> diagnostics::Result<()> {
+        sqlx::query(
+            r#" insert into user_roles(user_id, role) values ($1, $2) on conflict do nothing "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_role(&self, user_id: i64, role: &str) -> diagnostics::Result<bool> {
+        let removed = sqlx::query(r#"delete from user_roles where user_id = ($1) and role = ($2)"#)
+            .bind(user_id)
+            .bind(role)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(removed > 0)
+    }
+
+    async fn find_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> diagnostics::Result<Option<User>> {
+        Ok(sqlx::query_as::<_, User>(
+            r#" select u.* from users u join user_identities i on i.user_id = u.id
+                where i.provider = ($1) and i.subject = ($2) "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn link_identity(
+        &self,
+        user_id: i64,
+        provider: &str,
+        subject: &str,
+    ) -> diagnostics::Result<()> {
+        sqlx::query(
+            r#" insert into user_identities(provider, subject, user_id) values ($1, $2, $3) "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn find_totp(&self, user_id: i64) -> diagnostics::Result<Option<UserTotp>> {
+        Ok(
+            sqlx::query_as::<_, UserTotp>("select * from user_totp where user_id = ($1)")
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn save_totp(&self, user_id: i64, secret: &str) -> diagnostics::Result<()> {
+        sqlx::query(
+            r#" insert into user_totp(user_id, secret) values ($1, $2)
+                on conflict (user_id) do update set secret = excluded.secret
+                where user_totp.confirmed_at is null "#,
+        )
+        .bind(user_id)
+        .bind(secret)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn confirm_totp(
+        &self,
+        user_id: i64,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> diagnostics::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let confirmed = sqlx::query(
+            r#" update user_totp set confirmed_at = $1, last_step = $2
+                where user_id = ($3) and confirmed_at is null "#,
+        )
+        .bind(jsonwebtoken::get_current_timestamp() as i64)
+        .bind(step)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if confirmed == 0 {
+            return Ok(false);
+        }
+        sqlx::query(r#"delete from user_recovery_codes where user_id = ($1)"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for code_hash in recovery_code_hashes {
+            sqlx::query(r#" insert into user_recovery_codes(user_id, code_hash) values ($1, $2) "#)
+                .bind(user_id)
+                .bind(code_hash)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn use_totp_step(&self, user_id: i64, step: i64) -> diagnostics::Result<bool> {
+        // one statement, two requests with the same code can't both pass
+        let used = sqlx::query(
+            r#" update user_totp set last_step = $1
+                where user_id = ($2) and confirmed_at is not null
+                and (last_step is null or last_step < $1) "#,
+        )
+        .bind(step)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(used > 0)
+    }
+
+    async fn use_recovery_code(&self, user_id: i64, code_hash: &str) -> diagnostics::Result<bool> {
+        let used = sqlx::query(
+            r#"delete from user_recovery_codes where user_id = ($1) and code_hash = ($2)"#,
+        )
+        .bind(user_id)
+        .bind(code_hash)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(used > 0)
+    }
+
+    async fn claim_mfa_attempt(
+        &self,
+        user_id: i64,
+        max_attempts: u32,
+    ) -> diagnostics::Result<bool> {
+        // one statement, parallel requests can't check more codes than allowed
+        let claimed = sqlx::query(
+            r#" update user_totp set failed_attempts = failed_attempts + 1
+                where user_id = ($1) and failed_attempts < $2 "#,
+        )
+        .bind(user_id)
+        .bind(max_attempts as i64)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(claimed > 0)
+    }
+
+    async fn reset_mfa_attempts(&self, user_id: i64) -> diagnostics::Result<()> {
+        sqlx::query(r#"update user_totp set failed_attempts = 0 where user_id = ($1)"#)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_totp(&self, user_id: i64) -> diagnostics::Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(r#"delete from user_recovery_codes where user_id = ($1)"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query(r#"delete from user_totp where user_id = ($1)"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok(deleted > 0)
+    }
+}
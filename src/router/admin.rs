@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::WithRejection;
+use hyper::{header, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    app_state::{AppState, TokenService},
+    depends::{admin::Admin, Depends},
+    diagnostics::{self, Error},
+    dto,
+    repository::{UserRepository, UserRepositoryDB},
+    router::routing::{delete, get, put, RouteTable},
+    usecase::{ApiKeyUsecase, Usecase, UserUsecase},
+    util::{extractorext, tracing::log_levels},
+};
+
+// prometheus scrape target
+async fn metrics(State(app_state): State<AppState>) -> impl IntoResponse {
+    let body = app_state.metrics.render(&app_state).await;
+    ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body)
+}
+
+// the same next to the api, where only the admin token keeps it from the public
+async fn guarded_metrics(_: Depends<Admin>, state: State<AppState>) -> impl IntoResponse {
+    metrics(state).await
+}
+
+// account lockout, drops every session of the user
+async fn revoke_user_sessions(
+    _: Depends<Admin>,
+    State(app_state): State<AppState>,
+    WithRejection(Path(user_id), _): WithRejection<Path<i64>, Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let revoked = app_state
+        .session_store
+        .revoke_user_sessions(user_id)
+        .await?;
+    Ok(Json(json!({ "revoked": revoked })))
+}
+
+// grants are copied into sessions and tokens, the user has to log in again to pick up
+// the change
+async fn put_user_role(
+    _: Depends<Admin>,
+    State(app_state): State<AppState>,
+    State(users): State<UserRepositoryDB>,
+    State(tokens): State<TokenService>,
+    WithRejection(Path((user_id, role)), _): WithRejection<Path<(i64, String)>, Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    users.add_role(user_id, &role).await?;
+    revoke_user_credentials(&app_state, &tokens, user_id).await?;
+    Ok(Json(users.grants(user_id).await?))
+}
+
+async fn delete_user_role(
+    _: Depends<Admin>,
+    State(app_state): State<AppState>,
+    State(users): State<UserRepositoryDB>,
+    State(tokens): State<TokenService>,
+    WithRejection(Path((user_id, role)), _): WithRejection<Path<(i64, String)>, Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    if !users.remove_role(user_id, &role).await? {
+        return Err(Error::RowNotFound);
+    }
+    revoke_user_credentials(&app_state, &tokens, user_id).await?;
+    Ok(Json(users.grants(user_id).await?))
+}
+
+async fn revoke_user_credentials(
+    app_state: &AppState,
+    tokens: &TokenService,
+    user_id: i64,
+) -> diagnostics::Result<()> {
+    let sessions = app_state
+        .session_store
+        .revoke_user_sessions(user_id)
+        .await?;
+    let refresh_tokens = tokens.revoke_user(user_id).await?;
+    tracing::info!(
+        "roles of user {user_id} changed, revoked {sessions} sessions and {refresh_tokens} refresh tokens"
+    );
+    Ok(())
+}
+
+// for users locked out of their second factor, the next login needs the password only
+async fn reset_user_mfa(
+    _: Depends<Admin>,
+    Usecase(users): Usecase<UserUsecase>,
+    WithRejection(Path(user_id), _): WithRejection<Path<i64>, Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    users.reset_mfa(user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// the key is only in this response, the database keeps its hash
+async fn create_api_key(
+    _: Depends<Admin>,
+    Usecase(api_keys): Usecase<ApiKeyUsecase>,
+    extractorext::Json(request): extractorext::Json<dto::ApiKeyCreate>,
+) -> diagnostics::Result<impl IntoResponse> {
+    Ok((StatusCode::CREATED, Json(api_keys.create(request).await?)))
+}
+
+async fn list_api_keys(
+    _: Depends<Admin>,
+    Usecase(api_keys): Usecase<ApiKeyUsecase>,
+) -> diagnostics::Result<impl IntoResponse> {
+    Ok(Json(api_keys.list().await?))
+}
+
+async fn revoke_api_key(
+    _: Depends<Admin>,
+    Usecase(api_keys): Usecase<ApiKeyUsecase>,
+    WithRejection(Path(id), _): WithRejection<Path<i64>, Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    api_keys.revoke(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct LogLevelRequest {
+    // EnvFilter directives, e.g. "app=debug,sqlx=warn"
+    directives: String,
+    // "console" or "rolling_file", both when omitted
+    output: Option<String>,
+    // back to the configured directives after this long
+    revert_after_secs: Option<u64>,
+}
+
+async fn get_log_level(_: Depends<Admin>) -> diagnostics::Result<impl IntoResponse> {
+    let levels = log_levels().ok_or(Error::NotImplemented)?;
+    Ok(Json(levels.current()))
+}
+
+async fn put_log_level(
+    _: Depends<Admin>,
+    extractorext::Json(request): extractorext::Json<LogLevelRequest>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let levels = log_levels().ok_or(Error::NotImplemented)?;
+    levels.set(
+        request.output.as_deref(),
+        &request.directives,
+        request.revert_after_secs.map(Duration::from_secs),
+    )?;
+    Ok(Json(levels.current()))
+}
+
+/// served on `http.admin_port`, a listener that is not exposed with the api
+pub(crate) fn router() -> RouteTable<AppState> {
+    admin_routes().route("/metrics", get(metrics))
+}
+
+/// merged into the api when there is no `http.admin_port`, `/metrics` then needs the
+/// admin token like everything else
+pub(crate) fn shared_router() -> RouteTable<AppState> {
+    admin_routes().route("/metrics", get(guarded_metrics))
+}
+
+fn admin_routes() -> RouteTable<AppState> {
+    RouteTable::new()
+        .route("/admin/log-level", get(get_log_level).put(put_log_level))
+        .route("/admin/users/:id/sessions", delete(revoke_user_sessions))
+        .route(
+            "/admin/users/:id/roles/:role",
+            put(put_user_role).delete(delete_user_role),
+        )
+        .route("/admin/users/:id/mfa", delete(reset_user_mfa))
+        .route("/admin/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/admin/api-keys/:id", delete(revoke_api_key))
+}
@@ -1,22 +1,22 @@
 use std::collections::HashMap;
 
-use async_session::{Session, SessionStore};
+use async_session::Session;
 use axum::extract::{Multipart, Path, Query};
 use axum::response::{Html, IntoResponse};
-use axum::routing::{get, post};
-use axum::{extract::State, Router};
+use axum::extract::State;
 use axum::{Json, TypedHeader};
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::cookie::Cookie;
 use axum_extra::extract::{CookieJar, WithRejection};
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::app_state::{AppState, RedisConnection, SessionStoreImpl};
-use crate::define;
+use crate::app_state::{AppState, RedisConnection, SessionService, SessionStoreImpl};
 use crate::depends::Depends;
 use crate::diagnostics::{self, Error, Result};
 use crate::entity::User;
+use crate::router::routing::{delete, get, post, RouteTable};
+use crate::session_impl::{self, RotateSession};
 
 async fn index() -> &'static str {
     tracing::debug!("hello_axum");
@@ -169,12 +169,12 @@ async fn tree(
     format!("Path : {}", path)
 }
 
-async fn redis_ping(RedisConnection(mut conn): RedisConnection) -> impl IntoResponse {
+async fn redis_ping(RedisConnection(mut conn): RedisConnection) -> diagnostics::Result<String> {
     let replay: String = bb8_redis::redis::cmd("PING")
         .query_async(&mut *conn)
         .await
-        .unwrap();
-    replay
+        .map_err(|e| diagnostics::Error::BB8Error(e.to_string()))?;
+    Ok(replay)
 }
 
 #[derive(Deserialize, Debug)]
@@ -190,7 +190,7 @@ async fn redis_set(
     bb8_redis::redis::cmd("SET")
         .arg(param.key)
         .arg(param.value)
-        .query_async(&mut *conn)
+        .query_async::<_, ()>(&mut *conn)
         .await
         .map_err(|e| diagnostics::Error::BB8Error(e.to_string()))?;
     Ok(())
@@ -219,45 +219,61 @@ async fn session_required(Depends(user): Depends<User>) -> impl IntoResponse {
     user.name
 }
 
-async fn session_create(
-    State(session_store): State<SessionStoreImpl>,
-    WithRejection(Path(name), _): WithRejection<Path<String>, diagnostics::Error>,
-    jar: CookieJar,
-) -> diagnostics::Result<impl IntoResponse> {
-    let user = User::with_name(name);
-
-    let mut session = Session::new();
-    session
-        .insert("user", &user)
-        .map_err(|e| diagnostics::Error::Message(e.to_string()))?;
-
-    let cookie = session_store
-        .store_session(session)
-        .await
-        .map_err(|e| diagnostics::Error::Message(e.to_string()))?
-        .unwrap();
-
-    let mut cookie = Cookie::new(define::SESSION_COOKIE, cookie);
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_path("/");
-
-    let jar = jar.add(cookie);
+// new id for the current session, e.g. after a password change
+async fn session_rotate(Depends(session): Depends<Session>) -> impl IntoResponse {
+    (StatusCode::NO_CONTENT, RotateSession(session))
+}
 
+async fn session_delete(
+    Depends(session): Depends<Session>,
+    State(sessions): State<SessionService>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let jar = sessions.destroy(session).await?;
     Ok((StatusCode::OK, jar))
 }
 
-async fn session_delete(
+// sessions of the logged in user, the calling one is marked `current`
+async fn session_list(
     Depends(session): Depends<Session>,
     State(session_store): State<SessionStoreImpl>,
-    jar: CookieJar,
 ) -> diagnostics::Result<impl IntoResponse> {
-    session_store.destroy_session(session).await?;
-    let jar = jar.remove(Cookie::named(define::SESSION_COOKIE));
-    Ok((StatusCode::OK, jar))
+    let user_id = session_impl::authenticated_user_id(&session).ok_or(Error::Unauthorized)?;
+    let sessions = session_store.user_sessions(user_id).await?;
+    let sessions = sessions
+        .into_iter()
+        .map(|info| {
+            let current = info.id == session.id();
+            json!({ "current": current, "session": info })
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(json!({ "sessions": sessions })))
+}
+
+async fn session_revoke(
+    Depends(session): Depends<Session>,
+    State(session_store): State<SessionStoreImpl>,
+    WithRejection(Path(id), _): WithRejection<Path<String>, diagnostics::Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let user_id = session_impl::authenticated_user_id(&session).ok_or(Error::Unauthorized)?;
+    if !session_store.revoke_session(user_id, &id).await? {
+        return Err(Error::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub(crate) fn router() -> Router<AppState> {
-    Router::new()
+// log out everywhere
+async fn session_revoke_all(
+    Depends(session): Depends<Session>,
+    State(sessions): State<SessionService>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let user_id = session_impl::authenticated_user_id(&session).ok_or(Error::Unauthorized)?;
+    let revoked = sessions.store().revoke_user_sessions(user_id).await?;
+    let jar = sessions.cookies().remove();
+    Ok((jar, Json(json!({ "revoked": revoked }))))
+}
+
+pub(crate) fn router() -> RouteTable<AppState> {
+    RouteTable::new()
         .route("/api/basic", get(index))
         .route("/api/basic/error", get(error))
         .route("/api/basic/state", get(state))
@@ -276,6 +292,11 @@ pub(crate) fn router() -> Router<AppState> {
         .route("/api/basic/redis/:key", get(redis_get))
         .route("/api/basic/session/option", get(session_option))
         .route("/api/basic/session/required", get(session_required))
-        .route("/api/basic/session/:name", get(session_create))
-        .route("/api/basic/session/remove", get(session_delete))
+        .route("/api/basic/session/remove", post(session_delete))
+        .route("/api/basic/session/rotate", post(session_rotate))
+        .route(
+            "/api/basic/sessions",
+            get(session_list).delete(session_revoke_all),
+        )
+        .route("/api/basic/sessions/:id", delete(session_revoke))
 }
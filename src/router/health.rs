@@ -0,0 +1,31 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use hyper::StatusCode;
+use serde_json::json;
+
+use crate::{
+    app_state::AppState,
+    health,
+    router::routing::{get, RouteTable},
+};
+
+// process is up and serving requests
+async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+// every dependency is reachable and the schema is up to date
+async fn readyz(State(app_state): State<AppState>) -> impl IntoResponse {
+    let readiness = health::readiness(&app_state).await;
+    let status = if readiness.is_ready() && !app_state.shutdown.is_triggered() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+pub(crate) fn router() -> RouteTable<AppState> {
+    RouteTable::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
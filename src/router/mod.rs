@@ -1,69 +1,188 @@
+mod admin;
 pub(crate) mod basic;
+mod health;
+pub(crate) mod routing;
 mod v1;
 
-use std::time::Duration;
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
-use axum::{extract::DefaultBodyLimit, handler::HandlerWithoutStateExt, Router};
-use hyper::{header, Method, Uri};
+use axum::{
+    extract::{DefaultBodyLimit, FromRef},
+    handler::HandlerWithoutStateExt,
+    Router,
+};
+use hyper::{
+    header::HeaderName,
+    http::HeaderValue,
+    Method, Uri,
+};
 use tower_http::{
-    cors::{Any, CorsLayer},
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders},
     services::ServeDir,
-    //    trace::{DefaultMakeSpan, TraceLayer},
 };
 
 use crate::{
-    app_state::AppState,
+    app_state::{AppState, SessionService},
     diagnostics::Error,
-    util::{config::HttpConfig, middleware},
+    metrics,
+    util::{
+        config::{CorsConfig, HttpConfig},
+        middleware,
+    },
 };
 
+use self::routing::{RouteInfo, RouteTable};
+
+fn route_table() -> RouteTable<AppState> {
+    let table = RouteTable::new()
+        .merge(health::router())
+        .merge(basic::router())
+        .merge(v1::sample_router::router())
+        .merge(v1::auth_router::router())
+        .merge(v1::oidc_router::router());
+
+    #[cfg(feature = "enable_websocket_pubsub_sample")]
+    let table = table.merge(crate::ws::pubsub::router());
+
+    table
+}
+
+/// every registered rest route, building the table does not need an `AppState`
+pub(crate) fn routes() -> Vec<RouteInfo> {
+    route_table().merge(admin::router()).routes().to_vec()
+}
+
 pub(crate) fn init_router(app_state: AppState, config: &HttpConfig) -> Router {
     let static_serv_service = {
         ServeDir::new(config.static_directory.as_str())
             .not_found_service((|_uri: Uri| async move { Error::NotFound }).into_service())
     };
 
-    let router = Router::new()
-        .merge(basic::router())
-        .merge(v1::sample_router::router());
+    let table = match config.admin_port {
+        Some(_) => route_table(),
+        None => route_table().merge(admin::shared_router()),
+    };
 
-    #[cfg(feature = "enable_websocket_pubsub_sample")]
-    let router = router.merge(crate::ws::pubsub::router());
+    let exempt = table
+        .routes()
+        .iter()
+        .filter(|route| route.csrf_exempt)
+        .map(|route| route.path.clone())
+        .collect::<HashSet<_>>();
+    let router = table
+        .into_router()
+        .layer(axum::middleware::from_fn_with_state(
+            SessionService::from_ref(&app_state),
+            middleware::rotate_session,
+        ));
+    let router = if config.csrf {
+        router.layer(axum::middleware::from_fn_with_state(
+            middleware::Csrf {
+                sessions: SessionService::from_ref(&app_state),
+                exempt: Arc::new(exempt),
+            },
+            middleware::csrf_check,
+        ))
+    } else {
+        router
+    };
 
     router
-        .layer(cors())
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.metrics.clone(),
+            metrics::track_http,
+        ))
+        .layer(cors(&config.cors))
         .layer(DefaultBodyLimit::max(10 * 1024 * 1024))
         .layer(axum::middleware::map_response(middleware::response_map))
-        // .layer( TraceLayer::new_for_http() .make_span_with(DefaultMakeSpan::default().include_headers(true)))
+        .layer(axum::middleware::from_fn_with_state(
+            config.debug_errors,
+            middleware::problem_details,
+        ))
         .fallback_service(static_serv_service)
         .with_state(app_state)
 }
 
-// #todo from config
-pub(crate) fn cors() -> CorsLayer {
+/// admin routes alone, for `http.admin_port`
+pub(crate) fn init_admin_router(app_state: AppState) -> Router {
+    admin::router()
+        .into_router()
+        .layer(axum::middleware::map_response(middleware::response_map))
+        .with_state(app_state)
+}
+
+/// built from `[http.cors]`, the config is validated at startup so unparsable
+/// entries can not show up here
+pub(crate) fn cors(config: &CorsConfig) -> CorsLayer {
+    let is_wildcard = |values: &[String]| values.iter().any(|v| v == "*");
+
+    let allow_origin = if is_wildcard(&config.allowed_origins) {
+        AllowOrigin::any()
+    } else if config.allowed_origins.iter().any(|o| o.contains('*')) {
+        let origins = config.allowed_origins.clone();
+        AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| origins.iter().any(|p| origin_matches(p, origin)))
+        })
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|o| HeaderValue::from_str(o).ok()),
+        )
+    };
+
+    let allow_methods = if is_wildcard(&config.allowed_methods) {
+        AllowMethods::any()
+    } else {
+        AllowMethods::list(
+            config
+                .allowed_methods
+                .iter()
+                .filter_map(|m| Method::from_bytes(m.as_bytes()).ok()),
+        )
+    };
+
+    let header_names = |values: &[String]| {
+        values
+            .iter()
+            .filter_map(|h| HeaderName::from_bytes(h.as_bytes()).ok())
+            .collect::<Vec<_>>()
+    };
+    let allow_headers = if is_wildcard(&config.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(header_names(&config.allowed_headers))
+    };
+    let expose_headers = if is_wildcard(&config.exposed_headers) {
+        ExposeHeaders::any()
+    } else {
+        ExposeHeaders::list(header_names(&config.exposed_headers))
+    };
+
     CorsLayer::new()
-        //.allow_credentials(true)
-        .allow_headers(vec![
-            header::ACCEPT,
-            header::ACCEPT_LANGUAGE,
-            header::AUTHORIZATION,
-            header::CONTENT_LANGUAGE,
-            header::CONTENT_TYPE,
-        ])
-        .allow_methods(vec![
-            Method::GET,
-            Method::POST,
-            Method::PUT,
-            Method::DELETE,
-            Method::HEAD,
-            Method::OPTIONS,
-            Method::CONNECT,
-            Method::PATCH,
-            Method::TRACE,
-        ])
-        // .allow_origin(AllowOrigin::exact(
-        //     "http://localhost:5173".parse().unwrap(), // Make sure this matches your frontend url
-        // ))
-        .allow_origin(Any)
-        .max_age(Duration::from_secs(60 * 60))
+        .allow_origin(allow_origin)
+        .allow_methods(allow_methods)
+        .allow_headers(allow_headers)
+        .expose_headers(expose_headers)
+        .allow_credentials(config.allow_credentials)
+        .max_age(Duration::from_secs(config.max_age_secs))
+}
+
+/// `https://*.example.com` matches `https://a.example.com` and `https://a.b.example.com`
+/// but not `https://example.com`, anything else is compared as is
+pub(crate) fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let Some((scheme, host)) = pattern.split_once("://") else {
+        return false;
+    };
+    match host.strip_prefix('*') {
+        Some(domain) => origin
+            .strip_prefix(scheme)
+            .and_then(|rest| rest.strip_prefix("://"))
+            .and_then(|rest| rest.strip_suffix(domain))
+            .is_some_and(|sub| !sub.is_empty() && !sub.contains('/')),
+        None => pattern.eq_ignore_ascii_case(origin),
+    }
 }
@@ -0,0 +1,132 @@
+use std::fmt;
+
+use axum::{handler::Handler, routing::MethodRouter, Router};
+use hyper::{Body, Method};
+
+use crate::app_state::AppState;
+
+// drop-in replacements for axum::routing::{get, post, ..} that remember the methods
+// so `app routes` can list what is registered
+
+#[derive(Debug, Clone)]
+pub(crate) struct RouteInfo {
+    pub methods: Vec<Method>,
+    pub path: String,
+    // skipped by `middleware::csrf_check`
+    pub csrf_exempt: bool,
+}
+
+impl fmt::Display for RouteInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let methods = self
+            .methods
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(f, "{:<12} {}", methods, self.path)
+    }
+}
+
+pub(crate) struct Endpoint<S = AppState> {
+    methods: Vec<Method>,
+    inner: MethodRouter<S>,
+    csrf_exempt: bool,
+}
+
+impl<S> Endpoint<S> {
+    /// no csrf check on any method of the route, for callers that do not come from a
+    /// browser such as webhook callbacks
+    pub(crate) fn csrf_exempt(mut self) -> Self {
+        self.csrf_exempt = true;
+        self
+    }
+}
+
+macro_rules! endpoint_method {
+    ($name:ident, $method:ident) => {
+        pub(crate) fn $name<H, T, S>(handler: H) -> Endpoint<S>
+        where
+            H: Handler<T, S, Body>,
+            T: 'static,
+            S: Clone + Send + Sync + 'static,
+        {
+            Endpoint {
+                methods: vec![Method::$method],
+                inner: axum::routing::$name(handler),
+                csrf_exempt: false,
+            }
+        }
+
+        impl<S> Endpoint<S>
+        where
+            S: Clone + Send + Sync + 'static,
+        {
+            pub(crate) fn $name<H, T>(mut self, handler: H) -> Self
+            where
+                H: Handler<T, S, Body>,
+                T: 'static,
+            {
+                self.methods.push(Method::$method);
+                self.inner = self.inner.$name(handler);
+                self
+            }
+        }
+    };
+}
+
+endpoint_method!(get, GET);
+endpoint_method!(post, POST);
+endpoint_method!(put, PUT);
+endpoint_method!(patch, PATCH);
+endpoint_method!(delete, DELETE);
+
+pub(crate) struct RouteTable<S = AppState> {
+    router: Router<S>,
+    routes: Vec<RouteInfo>,
+}
+
+impl<S> RouteTable<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            router: Router::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn route(mut self, path: &str, endpoint: Endpoint<S>) -> Self {
+        self.routes.push(RouteInfo {
+            methods: endpoint.methods,
+            path: path.to_owned(),
+            csrf_exempt: endpoint.csrf_exempt,
+        });
+        self.router = self.router.route(path, endpoint.inner);
+        self
+    }
+
+    pub(crate) fn merge(mut self, other: RouteTable<S>) -> Self {
+        self.routes.extend(other.routes);
+        self.router = self.router.merge(other.router);
+        self
+    }
+
+    pub(crate) fn routes(&self) -> &[RouteInfo] {
+        &self.routes
+    }
+
+    pub(crate) fn into_router(self) -> Router<S> {
+        self.router
+    }
+}
+
+impl<S> Default for RouteTable<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
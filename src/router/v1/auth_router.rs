@@ -0,0 +1,196 @@
+use async_session::{Session, SessionStore};
+use axum::{extract::State, response::IntoResponse, Json};
+use axum_extra::extract::WithRejection;
+use hyper::StatusCode;
+use serde_json::json;
+
+use crate::{
+    app_state::{AppState, SessionService, TokenService},
+    depends::Depends,
+    diagnostics::{self, Error},
+    dto,
+    entity::User,
+    router::routing::{get, post, RouteTable},
+    session_impl::{self, ClientInfo},
+    token_impl::TokenPair,
+    usecase::{Usecase, UserUsecase},
+    util::config::MfaConfig,
+};
+
+async fn register(
+    Usecase(user_usecase): Usecase<UserUsecase>,
+    WithRejection(Json(v), _): WithRejection<Json<dto::UserRegister>, diagnostics::Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let user = user_usecase.register(v).await?;
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+// a session the client already has keeps its data but moves to a new id,
+// an id planted before the login is useless afterwards. users with a second factor
+// who did not send a code get a session that waits for it, 202 instead of the user
+async fn login(
+    Usecase(user_usecase): Usecase<UserUsecase>,
+    State(sessions): State<SessionService>,
+    State(mfa): State<MfaConfig>,
+    Depends(client): Depends<ClientInfo>,
+    current: Option<Depends<Session>>,
+    WithRejection(Json(v), _): WithRejection<Json<dto::UserLogin>, diagnostics::Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let mfa_code = v.mfa_code.clone();
+    let user = user_usecase.login(v).await?;
+    let mfa_pending = match user_usecase
+        .second_factor(&user, mfa_code.as_deref(), mfa.max_attempts)
+        .await
+    {
+        Ok(()) => false,
+        Err(Error::MfaRequired) => true,
+        Err(e) => return Err(e),
+    };
+
+    let (mut session, existing) = match current {
+        Some(Depends(session)) => (session, true),
+        None => (Session::new(), false),
+    };
+    session_impl::bind_user(&mut session, user.id, &client).map_err(anyhow::Error::from)?;
+    if mfa_pending {
+        session_impl::set_mfa_pending(&mut session, 0)
+    } else {
+        // a login the reused session was left waiting in, maybe of another user, is over
+        session_impl::clear_mfa_pending(&mut session);
+        session_impl::bind_grants(&mut session, &user_usecase.grants(user.id).await?)
+    }
+    .map_err(anyhow::Error::from)?;
+
+    let jar = if existing {
+        sessions.rotate(session).await?.1
+    } else {
+        sessions.create(session).await?
+    };
+    if mfa_pending {
+        let body = Json(json!({ "mfa_required": true }));
+        return Ok((StatusCode::ACCEPTED, jar, body).into_response());
+    }
+    Ok((jar, Json(user)).into_response())
+}
+
+// second step of a login that is waiting for a code. after `max_attempts` wrong codes
+// the session is gone and the login starts over with the password
+async fn mfa_verify(
+    Usecase(user_usecase): Usecase<UserUsecase>,
+    State(sessions): State<SessionService>,
+    State(mfa): State<MfaConfig>,
+    Depends(mut session): Depends<Session>,
+    WithRejection(Json(v), _): WithRejection<Json<dto::MfaCode>, diagnostics::Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let (Some(user_id), Some(attempts)) = (
+        session_impl::session_user_id(&session),
+        session_impl::mfa_pending_attempts(&session),
+    ) else {
+        return Err(Error::Unauthorized);
+    };
+    let user = match user_usecase
+        .verify_mfa(user_id, &v.code, mfa.max_attempts)
+        .await
+    {
+        Ok(user) => user,
+        Err(Error::Unauthorized) => {
+            let attempts = attempts + 1;
+            if attempts >= mfa.max_attempts {
+                tracing::info!("too many wrong codes for user {user_id}, login dropped");
+                sessions.destroy(session).await?;
+            } else {
+                session_impl::set_mfa_pending(&mut session, attempts)
+                    .map_err(anyhow::Error::from)?;
+                sessions.store().store_session(session).await?;
+            }
+            return Err(Error::Unauthorized);
+        }
+        Err(e) => return Err(e),
+    };
+
+    session_impl::clear_mfa_pending(&mut session);
+    session_impl::bind_grants(&mut session, &user_usecase.grants(user.id).await?)
+        .map_err(anyhow::Error::from)?;
+    let (_, jar) = sessions.rotate(session).await?;
+    Ok((jar, Json(user)))
+}
+
+// new totp secret for the authenticator app, confirmed by `mfa_totp_confirm`
+async fn mfa_totp_enroll(
+    Usecase(user_usecase): Usecase<UserUsecase>,
+    State(mfa): State<MfaConfig>,
+    Depends(user): Depends<User>,
+) -> diagnostics::Result<Json<dto::TotpEnrollment>> {
+    Ok(Json(user_usecase.enroll_totp(&user, &mfa.issuer).await?))
+}
+
+async fn mfa_totp_confirm(
+    Usecase(user_usecase): Usecase<UserUsecase>,
+    Depends(user): Depends<User>,
+    WithRejection(Json(v), _): WithRejection<Json<dto::MfaCode>, diagnostics::Error>,
+) -> diagnostics::Result<Json<dto::RecoveryCodes>> {
+    Ok(Json(user_usecase.confirm_totp(user.id, &v.code).await?))
+}
+
+async fn logout(
+    Depends(session): Depends<Session>,
+    State(sessions): State<SessionService>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let jar = sessions.destroy(session).await?;
+    Ok((StatusCode::NO_CONTENT, jar))
+}
+
+// bearer tokens for clients without cookies: mobile apps and other services
+async fn token(
+    Usecase(user_usecase): Usecase<UserUsecase>,
+    State(tokens): State<TokenService>,
+    State(mfa): State<MfaConfig>,
+    WithRejection(Json(v), _): WithRejection<Json<dto::UserLogin>, diagnostics::Error>,
+) -> diagnostics::Result<Json<TokenPair>> {
+    let mfa_code = v.mfa_code.clone();
+    let user = user_usecase.login(v).await?;
+    user_usecase
+        .second_factor(&user, mfa_code.as_deref(), mfa.max_attempts)
+        .await?;
+    let grants = user_usecase.grants(user.id).await?;
+    Ok(Json(tokens.issue(user.id, grants).await?))
+}
+
+async fn token_refresh(
+    State(tokens): State<TokenService>,
+    WithRejection(Json(v), _): WithRejection<Json<dto::TokenRefresh>, diagnostics::Error>,
+) -> diagnostics::Result<Json<TokenPair>> {
+    Ok(Json(tokens.refresh(&v.refresh_token).await?))
+}
+
+async fn token_revoke(
+    State(tokens): State<TokenService>,
+    WithRejection(Json(v), _): WithRejection<Json<dto::TokenRefresh>, diagnostics::Error>,
+) -> diagnostics::Result<StatusCode> {
+    tokens.revoke(&v.refresh_token).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn me(Depends(user): Depends<User>) -> Json<User> {
+    Json(user)
+}
+
+pub(crate) fn router() -> RouteTable<AppState> {
+    RouteTable::new()
+        .route("/api/v1/auth/register", post(register))
+        .route("/api/v1/auth/login", post(login))
+        .route("/api/v1/auth/logout", post(logout))
+        .route("/api/v1/auth/me", get(me))
+        .route("/api/v1/auth/mfa/verify", post(mfa_verify))
+        .route("/api/v1/auth/mfa/totp", post(mfa_totp_enroll))
+        .route("/api/v1/auth/mfa/totp/confirm", post(mfa_totp_confirm))
+        .route("/api/v1/auth/token", post(token).csrf_exempt())
+        .route(
+            "/api/v1/auth/token/refresh",
+            post(token_refresh).csrf_exempt(),
+        )
+        .route(
+            "/api/v1/auth/token/revoke",
+            post(token_revoke).csrf_exempt(),
+        )
+}
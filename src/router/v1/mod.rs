@@ -1 +1,3 @@
-pub mod sample_router;
+pub mod auth_router;
+pub mod oidc_router;
+pub mod sample_router;
\ No newline at end of file
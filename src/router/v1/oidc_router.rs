@@ -0,0 +1,113 @@
+use async_session::{Session, SessionStore};
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Redirect},
+};
+use axum_extra::extract::WithRejection;
+use serde::Deserialize;
+
+use crate::{
+    app_state::{AppState, OidcProviders, SessionService},
+    depends::{admin::constant_time_eq, Depends},
+    diagnostics::{self, Error},
+    oidc_impl::PendingLogin,
+    router::routing::{get, RouteTable},
+    session_impl::{self, ClientInfo},
+    usecase::{Usecase, UserUsecase},
+};
+
+#[derive(Deserialize, Debug)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    // set instead of `code` when the user declined or the provider failed
+    error: Option<String>,
+}
+
+// "sign in with <provider>": the login is remembered in the session, then the browser goes
+// to the provider
+async fn oidc_login(
+    State(providers): State<OidcProviders>,
+    State(sessions): State<SessionService>,
+    current: Option<Depends<Session>>,
+    WithRejection(Path(name), _): WithRejection<Path<String>, Error>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let provider = providers.get(&name)?;
+    let pending = PendingLogin::new(&name);
+    let url = provider
+        .authorize_url(&pending.state, &pending.nonce, &pending.code_challenge())
+        .await?;
+
+    let (mut session, existing) = match current {
+        Some(Depends(session)) => (session, true),
+        None => (Session::new(), false),
+    };
+    pending.store(&mut session).map_err(anyhow::Error::from)?;
+    let jar = if existing {
+        sessions.rotate(session).await?.1
+    } else {
+        sessions.create(session).await?
+    };
+    Ok((jar, Redirect::to(&url)))
+}
+
+// the provider sends the browser back here. the pending login of the session has to match,
+// then the user is logged in like with a password. accounts with a second factor land on
+// `post_login_redirect?mfa=required` with a session waiting for the code
+async fn oidc_callback(
+    Usecase(user_usecase): Usecase<UserUsecase>,
+    State(providers): State<OidcProviders>,
+    State(sessions): State<SessionService>,
+    Depends(client): Depends<ClientInfo>,
+    Depends(mut session): Depends<Session>,
+    WithRejection(Path(name), _): WithRejection<Path<String>, Error>,
+    Query(query): Query<CallbackQuery>,
+) -> diagnostics::Result<impl IntoResponse> {
+    let provider = providers.get(&name)?;
+    let pending = PendingLogin::take(&mut session).ok_or(Error::Unauthorized)?;
+    // used up before anything is checked, a failed callback can not be tried again
+    sessions.store().store_session(session.clone()).await?;
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        tracing::debug!("{name} login failed: {:?}", query.error);
+        return Err(Error::Unauthorized);
+    };
+    if pending.provider != name
+        || !constant_time_eq(state.as_bytes(), pending.state.as_bytes())
+        || pending.is_expired(providers.flow_ttl())
+    {
+        return Err(Error::Unauthorized);
+    }
+
+    let identity = provider
+        .exchange(&code, &pending.code_verifier, &pending.nonce)
+        .await?;
+    let user = user_usecase.login_external(&name, identity).await?;
+    // a second factor of the account is asked for here too, the provider knows nothing of it
+    let mfa_pending = user_usecase.has_second_factor(&user).await?;
+    session_impl::bind_user(&mut session, user.id, &client).map_err(anyhow::Error::from)?;
+    if mfa_pending {
+        session_impl::set_mfa_pending(&mut session, 0)
+    } else {
+        // a login the reused session was left waiting in, maybe of another user, is over
+        session_impl::clear_mfa_pending(&mut session);
+        session_impl::bind_grants(&mut session, &user_usecase.grants(user.id).await?)
+    }
+    .map_err(anyhow::Error::from)?;
+    let (_, jar) = sessions.rotate(session).await?;
+
+    let redirect = providers.post_login_redirect();
+    if mfa_pending {
+        let separator = if redirect.contains('?') { '&' } else { '?' };
+        return Ok((
+            jar,
+            Redirect::to(&format!("{redirect}{separator}mfa=required")),
+        ));
+    }
+    Ok((jar, Redirect::to(redirect)))
+}
+
+pub(crate) fn router() -> RouteTable<AppState> {
+    RouteTable::new()
+        .route("/api/v1/auth/oidc/:provider/login", get(oidc_login))
+        .route("/api/v1/auth/oidc/:provider/callback", get(oidc_callback))
+}
@@ -1,11 +1,17 @@
-use axum::{extract::State, routing::get, Json, Router};
+use axum::{
+    extract::{Query, State},
+    Json, Router,
+};
 use axum_extra::extract::WithRejection;
 
 use crate::{
     app_state::AppState,
+    authz::{SampleRead, SampleWrite},
+    depends::permission::RequirePermission,
     diagnostics, dto,
     entity::Sample,
-    repository::{BasicRepository, Repository, SampleRepositoryDB},
+    repository::{BasicRepository, Page, PageRequest, Repository, SampleRepositoryDB},
+    router::routing::{get, RouteTable},
     usecase::{SampleUsecase, Usecase},
 };
 
@@ -39,14 +45,19 @@ async fn create_sample_v2(
     Ok(Json(samples))
 }
 
+// paged, see `PageRequest::from_query` for the query string
 async fn get_samples_v3(
+    _: RequirePermission<SampleRead>,
     Usecase(sample_usecase): Usecase<SampleUsecase>,
-) -> diagnostics::Result<Json<Vec<Sample>>> {
-    let samples = sample_usecase.find_all().await?;
+    Query(query): Query<Vec<(String, String)>>,
+) -> diagnostics::Result<Json<Page<Sample>>> {
+    let request = PageRequest::from_query::<Sample>(&query)?;
+    let samples = sample_usecase.find_page(request).await?;
     Ok(Json(samples))
 }
 
 async fn create_sample_v3(
+    _: RequirePermission<SampleWrite>,
     Usecase(sample_usecase): Usecase<SampleUsecase>,
     WithRejection(Json(v), _): WithRejection<Json<dto::SampleCreate>, diagnostics::Error>,
 ) -> diagnostics::Result<Json<Sample>> {
@@ -55,11 +66,12 @@ async fn create_sample_v3(
 }
 
 pub(crate) fn router_(app_state: AppState) -> Router {
-    Router::new()
+    RouteTable::new()
         .route("/", get(get_samples).post(create_sample))
+        .into_router()
         .with_state(app_state)
 }
 
-pub(crate) fn router() -> Router<AppState> {
-    Router::new().route("/api/v1/sample", get(get_samples_v3).post(create_sample_v3))
+pub(crate) fn router() -> RouteTable<AppState> {
+    RouteTable::new().route("/api/v1/sample", get(get_samples_v3).post(create_sample_v3))
 }
@@ -0,0 +1,166 @@
+use std::{convert::Infallible, fmt, sync::Arc, time::Duration};
+
+use axum::{
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+};
+use axum_extra::extract::{
+    cookie::{Cookie, Key, PrivateCookieJar, SameSite, SignedCookieJar},
+    CookieJar,
+};
+
+use crate::{
+    define,
+    util::config::{CookieMode, CookieSameSite, SessionCookieConfig},
+};
+
+/// keyring and attributes of the session cookie. cookies are sealed with the newest key
+/// and opened with any key of the ring, so keys rotate without logging everyone out
+#[derive(Clone)]
+pub(crate) struct SessionCookies {
+    keys: Arc<[Key]>,
+    mode: CookieMode,
+    secure: bool,
+    http_only: bool,
+    same_site: SameSite,
+    domain: Option<String>,
+    path: String,
+}
+
+impl fmt::Debug for SessionCookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionCookies")
+            .field("keys", &self.keys.len())
+            .field("mode", &self.mode)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionCookies {
+    pub(crate) fn new(config: &SessionCookieConfig) -> Self {
+        let mut keys = config
+            .keys
+            .iter()
+            .map(|key| Key::derive_from(key.as_bytes()))
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            tracing::warn!("session.cookie.keys is empty, sessions will not survive a restart");
+            keys.push(Key::generate());
+        }
+        Self {
+            keys: keys.into(),
+            mode: config.mode,
+            secure: config.secure,
+            http_only: config.http_only,
+            same_site: match config.same_site {
+                CookieSameSite::Strict => SameSite::Strict,
+                CookieSameSite::Lax => SameSite::Lax,
+                CookieSameSite::None => SameSite::None,
+            },
+            domain: config.domain.clone(),
+            path: config.path.clone(),
+        }
+    }
+
+    /// session cookie value of the request, `None` when missing or not sealed by a key
+    /// of the ring
+    pub(crate) fn read(&self, headers: &HeaderMap) -> Option<String> {
+        self.keys.iter().find_map(|key| {
+            let cookie =
+                match self.mode {
+                    CookieMode::Private => PrivateCookieJar::from_headers(headers, key.clone())
+                        .get(define::SESSION_COOKIE),
+                    CookieMode::Signed => SignedCookieJar::from_headers(headers, key.clone())
+                        .get(define::SESSION_COOKIE),
+                };
+            cookie.map(|cookie| cookie.value().to_owned())
+        })
+    }
+
+    /// sets the session cookie sealed with the newest key
+    pub(crate) fn issue(&self, value: String, max_age: Duration) -> SessionCookieJar {
+        let mut cookie = self.cookie(define::SESSION_COOKIE, value);
+        cookie.set_max_age(cookie::time::Duration::seconds(max_age.as_secs() as i64));
+        let key = self.keys[0].clone();
+        let sealed = match self.mode {
+            CookieMode::Private => Sealed::Private(PrivateCookieJar::new(key).add(cookie)),
+            CookieMode::Signed => Sealed::Signed(SignedCookieJar::new(key).add(cookie)),
+        };
+        SessionCookieJar { sealed, csrf: None }
+    }
+
+    /// the csrf token for scripts, same scope as the session cookie but not HttpOnly
+    pub(crate) fn csrf_cookie(&self, token: &str) -> Cookie<'static> {
+        let mut cookie = self.cookie(define::CSRF_COOKIE, token.to_owned());
+        cookie.set_http_only(false);
+        cookie
+    }
+
+    /// expires the session and csrf cookies, domain and path have to match for the
+    /// browser to drop them
+    pub(crate) fn remove(&self) -> CookieJar {
+        [define::SESSION_COOKIE, define::CSRF_COOKIE]
+            .into_iter()
+            .fold(CookieJar::new(), |jar, name| {
+                let mut cookie = self.cookie(name, String::new());
+                cookie.make_removal();
+                jar.add(cookie)
+            })
+    }
+
+    fn cookie(&self, name: &'static str, value: String) -> Cookie<'static> {
+        let mut cookie = Cookie::new(name, value);
+        cookie.set_secure(self.secure);
+        cookie.set_http_only(self.http_only);
+        cookie.set_same_site(self.same_site);
+        cookie.set_path(self.path.clone());
+        if let Some(domain) = &self.domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+}
+
+/// `Set-Cookie` for the session cookie in the configured mode, plus the csrf token as
+/// cookie and header when one goes with it
+pub(crate) struct SessionCookieJar {
+    sealed: Sealed,
+    csrf: Option<Cookie<'static>>,
+}
+
+enum Sealed {
+    Private(PrivateCookieJar),
+    Signed(SignedCookieJar),
+}
+
+impl SessionCookieJar {
+    /// `cookie` from `SessionCookies::csrf_cookie`
+    pub(crate) fn with_csrf(mut self, cookie: Cookie<'static>) -> Self {
+        self.csrf = Some(cookie);
+        self
+    }
+}
+
+impl IntoResponseParts for SessionCookieJar {
+    type Error = Infallible;
+
+    fn into_response_parts(self, res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let mut res = match self.sealed {
+            Sealed::Private(jar) => jar.into_response_parts(res)?,
+            Sealed::Signed(jar) => jar.into_response_parts(res)?,
+        };
+        if let Some(cookie) = self.csrf {
+            if let Ok(token) = HeaderValue::from_str(cookie.value()) {
+                res.headers_mut().insert(define::CSRF_HEADER, token);
+            }
+            res = CookieJar::new().add(cookie).into_response_parts(res)?;
+        }
+        Ok(res)
+    }
+}
+
+impl IntoResponse for SessionCookieJar {
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
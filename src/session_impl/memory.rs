@@ -0,0 +1,121 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_session::chrono::{DateTime, Utc};
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use super::{SessionBackend, SessionRecord};
+
+/// process local sessions, gone on restart and not shared between instances.
+/// the user index is a scan, fine for tests and local development
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    records: Arc<RwLock<HashMap<String, Entry>>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    user_id: Option<i64>,
+    value: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionBackend for MemoryStore {
+    async fn get(&self, id: &str) -> anyhow::Result<Option<String>> {
+        let now = Utc::now();
+        Ok(self
+            .records
+            .read()
+            .await
+            .get(id)
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.value.clone()))
+    }
+
+    async fn put(&self, record: SessionRecord<'_>, only_existing: bool) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let mut records = self.records.write().await;
+        if only_existing {
+            if let Some(entry) = records.get_mut(record.id) {
+                entry.user_id = record.user_id;
+                entry.value = record.value;
+                entry.expires_at = record.expires_at;
+            }
+            return Ok(());
+        }
+        // new sessions sweep the expired ones
+        records.retain(|_, entry| entry.expires_at > now);
+        records.insert(
+            record.id.to_owned(),
+            Entry {
+                user_id: record.user_id,
+                value: record.value,
+                expires_at: record.expires_at,
+            },
+        );
+        Ok(())
+    }
+
+    async fn replace(&self, old_id: &str, record: SessionRecord<'_>) -> anyhow::Result<bool> {
+        let now = Utc::now();
+        let mut records = self.records.write().await;
+        match records.remove(old_id) {
+            Some(entry) if entry.expires_at > now => {}
+            _ => return Ok(false),
+        }
+        records.insert(
+            record.id.to_owned(),
+            Entry {
+                user_id: record.user_id,
+                value: record.value,
+                expires_at: record.expires_at,
+            },
+        );
+        Ok(true)
+    }
+
+    async fn remove(&self, id: &str, _user_id: Option<i64>) -> anyhow::Result<()> {
+        self.records.write().await.remove(id);
+        Ok(())
+    }
+
+    async fn user_values(&self, user_id: i64) -> anyhow::Result<Vec<String>> {
+        let now = Utc::now();
+        Ok(self
+            .records
+            .read()
+            .await
+            .values()
+            .filter(|entry| entry.user_id == Some(user_id) && entry.expires_at > now)
+            .map(|entry| entry.value.clone())
+            .collect())
+    }
+
+    async fn remove_user_session(&self, user_id: i64, id: &str) -> anyhow::Result<bool> {
+        let mut records = self.records.write().await;
+        if records.get(id).and_then(|entry| entry.user_id) != Some(user_id) {
+            return Ok(false);
+        }
+        records.remove(id);
+        Ok(true)
+    }
+
+    async fn remove_user(&self, user_id: i64) -> anyhow::Result<usize> {
+        let mut records = self.records.write().await;
+        let before = records.len();
+        records.retain(|_, entry| entry.user_id != Some(user_id));
+        Ok(before - records.len())
+    }
+
+    async fn clear(&self) -> anyhow::Result<()> {
+        self.records.write().await.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+mod cookie;
+mod memory;
+mod redis;
+mod service;
+mod sql;
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use async_session::{
+    chrono::{DateTime, TimeZone, Utc},
+    Session, SessionStore,
+};
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::Pool;
+
+use crate::{
+    app_state::{DataBase, RedisPool},
+    authz::Grants,
+    util::config::{SessionBackendKind, SessionConfig},
+};
+
+pub(crate) use self::{
+    cookie::{SessionCookieJar, SessionCookies},
+    memory::MemoryStore,
+    redis::RedisStore,
+    service::{RotateSession, SessionService},
+    sql::SqlStore,
+};
+
+// unix seconds of the first store, the absolute lifetime counts from here
+const CREATED_AT_KEY: &str = "__created_at";
+// unix seconds of the last load
+const LAST_SEEN_KEY: &str = "__last_seen";
+// set by `bind_user`, sessions with it are listed in the per user index
+const USER_ID_KEY: &str = "__user_id";
+const USER_AGENT_KEY: &str = "__user_agent";
+const IP_KEY: &str = "__ip";
+// synchronizer token checked by `middleware::csrf_check`
+const CSRF_TOKEN_KEY: &str = "__csrf";
+// roles and permissions at login, sessions are revoked when they change
+const GRANTS_KEY: &str = "__grants";
+// set at login for users with a second factor until it is verified, with the wrong
+// codes sent so far
+const MFA_PENDING_KEY: &str = "__mfa_pending";
+
+/// where a session was created from, recorded by `bind_user`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+/// one entry of a user's session listing
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SessionInfo {
+    pub id: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl SessionInfo {
+    pub(crate) fn from_session(session: &Session) -> Self {
+        let timestamp = |key| {
+            session
+                .get::<i64>(key)
+                .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        };
+        Self {
+            id: session.id().to_owned(),
+            created_at: timestamp(CREATED_AT_KEY),
+            last_seen: timestamp(LAST_SEEN_KEY),
+            expires_at: session.expiry().copied(),
+            user_agent: session.get(USER_AGENT_KEY),
+            ip: session.get(IP_KEY),
+        }
+    }
+}
+
+/// ties the session to `user_id` so it shows up in `user_sessions` and can be revoked
+pub(crate) fn bind_user(
+    session: &mut Session,
+    user_id: i64,
+    client: &ClientInfo,
+) -> serde_json::Result<()> {
+    session.insert(USER_ID_KEY, user_id)?;
+    if let Some(user_agent) = &client.user_agent {
+        session.insert(USER_AGENT_KEY, user_agent)?;
+    }
+    if let Some(ip) = &client.ip {
+        session.insert(IP_KEY, ip)?;
+    }
+    Ok(())
+}
+
+/// the user the session is bound to, also while the second factor is pending. use
+/// `authenticated_user_id` to decide what the session may do
+pub(crate) fn session_user_id(session: &Session) -> Option<i64> {
+    session.get(USER_ID_KEY)
+}
+
+/// the logged in user, `None` while the second factor is pending
+pub(crate) fn authenticated_user_id(session: &Session) -> Option<i64> {
+    match mfa_pending_attempts(session) {
+        Some(_) => None,
+        None => session_user_id(session),
+    }
+}
+
+/// the session counts as logged out until `clear_mfa_pending`
+pub(crate) fn set_mfa_pending(session: &mut Session, attempts: u32) -> serde_json::Result<()> {
+    session.insert(MFA_PENDING_KEY, attempts)
+}
+
+/// wrong codes sent so far, `None` when no second factor is pending
+pub(crate) fn mfa_pending_attempts(session: &Session) -> Option<u32> {
+    session.get(MFA_PENDING_KEY)
+}
+
+pub(crate) fn clear_mfa_pending(session: &mut Session) {
+    session.remove(MFA_PENDING_KEY);
+}
+
+pub(crate) fn bind_grants(session: &mut Session, grants: &Grants) -> serde_json::Result<()> {
+    session.insert(GRANTS_KEY, grants)
+}
+
+/// empty for sessions without grants, they are allowed nothing
+pub(crate) fn session_grants(session: &Session) -> Grants {
+    session.get(GRANTS_KEY).unwrap_or_default()
+}
+
+pub(crate) fn csrf_token(session: &Session) -> Option<String> {
+    session.get(CSRF_TOKEN_KEY)
+}
+
+/// new csrf token for the session, `SessionService` does this on create and rotate
+pub(crate) fn reset_csrf_token(session: &mut Session) -> serde_json::Result<String> {
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    session.insert(CSRF_TOKEN_KEY, &token)?;
+    Ok(token)
+}
+
+/// idle expiry from `now`, capped by the absolute lifetime. `None` when already past it
+pub(crate) fn session_expiry(
+    created_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    idle_ttl: Duration,
+    absolute_ttl: Duration,
+) -> Option<DateTime<Utc>> {
+    let idle = now + async_session::chrono::Duration::from_std(idle_ttl).ok()?;
+    let absolute = created_at + async_session::chrono::Duration::from_std(absolute_ttl).ok()?;
+    let expiry = idle.min(absolute);
+    (expiry > now).then_some(expiry)
+}
+
+/// a serialized session as handed to a backend
+#[derive(Debug)]
+pub(crate) struct SessionRecord<'a> {
+    pub id: &'a str,
+    pub user_id: Option<i64>,
+    pub value: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// storage side of `SessionStoreImpl`. expiry, the absolute lifetime and the session
+/// metadata are handled once above it, a backend only keeps records and the user index
+#[async_trait]
+pub(crate) trait SessionBackend: fmt::Debug + Send + Sync {
+    /// the serialized session, `None` when missing or past `expires_at`
+    async fn get(&self, id: &str) -> anyhow::Result<Option<String>>;
+
+    /// with `only_existing` a record removed in the meantime must stay removed
+    async fn put(&self, record: SessionRecord<'_>, only_existing: bool) -> anyhow::Result<()>;
+
+    /// moves the session under `old_id` to `record.id` in one step. `false` when there
+    /// was nothing live under `old_id`, nothing is written then
+    async fn replace(&self, old_id: &str, record: SessionRecord<'_>) -> anyhow::Result<bool>;
+
+    async fn remove(&self, id: &str, user_id: Option<i64>) -> anyhow::Result<()>;
+
+    /// serialized sessions bound to the user that did not expire yet
+    async fn user_values(&self, user_id: i64) -> anyhow::Result<Vec<String>>;
+
+    /// `false` when the session does not exist or belongs to someone else
+    async fn remove_user_session(&self, user_id: i64, id: &str) -> anyhow::Result<bool>;
+
+    /// returns how many sessions were removed
+    async fn remove_user(&self, user_id: i64) -> anyhow::Result<usize>;
+
+    async fn clear(&self) -> anyhow::Result<()>;
+}
+
+/// the session store of the app, backend picked by `session.backend`.
+/// `Depends<Session>` and `Depends<User>` go through it whatever the backend
+#[derive(Clone, Debug)]
+pub(crate) struct SessionStoreImpl {
+    backend: Arc<dyn SessionBackend>,
+    idle_ttl: Duration,
+    absolute_ttl: Duration,
+}
+
+impl SessionStoreImpl {
+    pub(crate) fn new(backend: impl SessionBackend + 'static, config: &SessionConfig) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            idle_ttl: config.idle_ttl(),
+            absolute_ttl: config.absolute_ttl(),
+        }
+    }
+
+    pub(crate) fn from_config(
+        config: &SessionConfig,
+        db_pool: Pool<DataBase>,
+        redis_pool: RedisPool,
+    ) -> Self {
+        tracing::debug!("session backend {:?}", config.backend);
+        match config.backend {
+            SessionBackendKind::Memory => Self::new(MemoryStore::new(), config),
+            SessionBackendKind::Sql => Self::new(SqlStore::new(db_pool), config),
+            SessionBackendKind::Redis => {
+                Self::new(RedisStore::new(redis_pool, config.absolute_ttl()), config)
+            }
+        }
+    }
+
+    /// the longest a session can live, used as the cookie Max-Age since the
+    /// cookie is not re-sent when the idle expiry slides
+    pub(crate) fn absolute_ttl(&self) -> Duration {
+        self.absolute_ttl
+    }
+
+    /// live sessions of the user, most recently seen first
+    pub(crate) async fn user_sessions(&self, user_id: i64) -> anyhow::Result<Vec<SessionInfo>> {
+        let mut sessions = self
+            .backend
+            .user_values(user_id)
+            .await?
+            .iter()
+            .filter_map(|value| serde_json::from_str::<Session>(value).ok())
+            .filter_map(Session::validate)
+            .map(|session| SessionInfo::from_session(&session))
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_seen));
+        Ok(sessions)
+    }
+
+    /// `false` when the session does not exist or belongs to someone else
+    pub(crate) async fn revoke_session(
+        &self,
+        user_id: i64,
+        session_id: &str,
+    ) -> anyhow::Result<bool> {
+        self.backend.remove_user_session(user_id, session_id).await
+    }
+
+    /// log out everywhere, returns how many sessions were revoked
+    pub(crate) async fn revoke_user_sessions(&self, user_id: i64) -> anyhow::Result<usize> {
+        self.backend.remove_user(user_id).await
+    }
+
+    /// moves the session to a fresh id and drops the old one in one step, after a login or
+    /// any other privilege change so an id planted before it is worthless. returns the
+    /// session under its new id and the cookie value for it, `None` when the session was
+    /// destroyed in the meantime or reached its absolute lifetime
+    pub(crate) async fn rotate(
+        &self,
+        mut session: Session,
+    ) -> anyhow::Result<Option<(Session, String)>> {
+        let old_id = session.id().to_owned();
+        let now = Utc::now();
+        let Some(expires_at) = self.extend(&mut session, now) else {
+            return Ok(None);
+        };
+        session.insert(LAST_SEEN_KEY, now.timestamp())?;
+        session.regenerate();
+        let record = SessionRecord {
+            id: session.id(),
+            user_id: session_user_id(&session),
+            value: serde_json::to_string(&session)?,
+            expires_at,
+        };
+        if !self.backend.replace(&old_id, record).await? {
+            return Ok(None);
+        }
+        tracing::trace!("session `{old_id}` rotated to `{}`", session.id());
+        session.reset_data_changed();
+        // clones share the data but not the cookie value
+        let rotated = session.clone();
+        Ok(session
+            .into_cookie_value()
+            .map(|cookie_value| (rotated, cookie_value)))
+    }
+
+    /// sets the next expiry on the session, `None` once it may not live any longer
+    fn extend(&self, session: &mut Session, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let created_at = session
+            .get::<i64>(CREATED_AT_KEY)
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())?;
+        let expiry = session_expiry(created_at, now, self.idle_ttl, self.absolute_ttl)?;
+        session.set_expiry(expiry);
+        Some(expiry)
+    }
+
+    async fn write(
+        &self,
+        session: &Session,
+        expires_at: DateTime<Utc>,
+        only_existing: bool,
+    ) -> anyhow::Result<()> {
+        let record = SessionRecord {
+            id: session.id(),
+            user_id: session_user_id(session),
+            value: serde_json::to_string(session)?,
+            expires_at,
+        };
+        self.backend.put(record, only_existing).await
+    }
+}
+
+#[async_trait]
+impl SessionStore for SessionStoreImpl {
+    async fn load_session(&self, cookie_value: String) -> Result<Option<Session>, anyhow::Error> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let Some(value) = self.backend.get(&id).await? else {
+            return Ok(None);
+        };
+        let Some(mut session) = serde_json::from_str::<Session>(&value)?.validate() else {
+            return Ok(None);
+        };
+        // sliding expiry, every load pushes it out again up to the absolute lifetime
+        let now = Utc::now();
+        let Some(expires_at) = self.extend(&mut session, now) else {
+            tracing::trace!("session `{}` reached its absolute lifetime", session.id());
+            return Ok(None);
+        };
+        session.insert(LAST_SEEN_KEY, now.timestamp())?;
+        // a refresh must not bring back a session destroyed in the meantime
+        self.write(&session, expires_at, true).await?;
+        session.reset_data_changed();
+        Ok(Some(session))
+    }
+
+    async fn store_session(&self, mut session: Session) -> Result<Option<String>, anyhow::Error> {
+        tracing::trace!("storing session by id `{}`", session.id());
+        let now = Utc::now();
+        if session.get::<i64>(CREATED_AT_KEY).is_none() {
+            session.insert(CREATED_AT_KEY, now.timestamp())?;
+        }
+        session.insert(LAST_SEEN_KEY, now.timestamp())?;
+        let Some(expires_at) = self.extend(&mut session, now) else {
+            return Ok(None);
+        };
+        self.write(&session, expires_at, false).await?;
+
+        session.reset_data_changed();
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> Result<(), anyhow::Error> {
+        tracing::trace!("destroying session by id `{}`", session.id());
+        self.backend
+            .remove(session.id(), session_user_id(&session))
+            .await
+    }
+
+    async fn clear_store(&self) -> Result<(), anyhow::Error> {
+        tracing::trace!("clearing session store");
+        self.backend.clear().await
+    }
+}
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use async_session::chrono::Utc;
+use async_trait::async_trait;
+
+use super::{SessionBackend, SessionRecord};
+use crate::{app_state::RedisPool, diagnostics};
+
+// keys per SCAN round trip in `clear`
+const SCAN_COUNT: usize = 500;
+
+// KEYS old, new. ARGV value, ttl in millis. only moves a session that still exists
+const REPLACE_SCRIPT: &str = r#"
+if redis.call('DEL', KEYS[1]) == 0 then
+    return 0
+end
+redis.call('SET', KEYS[2], ARGV[1], 'PX', ARGV[2])
+return 1
+"#;
+
+/// `{prefix}:{id}` strings expiring with the session, plus a `{prefix}_user:{id}` set per user
+#[derive(Debug, Clone)]
+pub struct RedisStore {
+    redis_pool: RedisPool,
+    prefix: String,
+    // lifetime of the user index, outlives every session in it
+    index_ttl: Duration,
+}
+
+impl RedisStore {
+    pub fn new(redis_pool: RedisPool, index_ttl: Duration) -> Self {
+        Self {
+            redis_pool,
+            prefix: "session".to_owned(),
+            index_ttl,
+        }
+    }
+
+    pub async fn get_connection(
+        &self,
+    ) -> diagnostics::Result<bb8::PooledConnection<'_, bb8_redis::RedisConnectionManager>> {
+        Ok(self.redis_pool.get().await?)
+    }
+
+    pub fn key(&self, key: impl AsRef<str>) -> String {
+        format!("{}:{}", self.prefix, key.as_ref())
+    }
+
+    /// set of the session ids of one user, outside of the `{prefix}:*` namespace
+    fn user_key(&self, user_id: i64) -> String {
+        format!("{}_user:{}", self.prefix, user_id)
+    }
+
+    /// SCAN + UNLINK in batches, never blocks redis on one huge command
+    async fn unlink_matching(&self, pattern: &str) -> anyhow::Result<usize> {
+        let mut conn = self.get_connection().await?;
+        let mut cursor = 0u64;
+        let mut removed = 0;
+        loop {
+            let (next, keys): (u64, Vec<String>) = bb8_redis::redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut *conn)
+                .await?;
+            if !keys.is_empty() {
+                removed += keys.len();
+                bb8_redis::redis::cmd("UNLINK")
+                    .arg(keys)
+                    .query_async::<_, ()>(&mut *conn)
+                    .await?;
+            }
+            if next == 0 {
+                return Ok(removed);
+            }
+            cursor = next;
+        }
+    }
+}
+
+#[async_trait]
+impl SessionBackend for RedisStore {
+    async fn get(&self, id: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.get_connection().await?;
+        Ok(bb8_redis::redis::cmd("GET")
+            .arg(self.key(id))
+            .query_async(&mut *conn)
+            .await?)
+    }
+
+    async fn put(&self, record: SessionRecord<'_>, only_existing: bool) -> anyhow::Result<()> {
+        let ttl = (record.expires_at - Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        let mut conn = self.get_connection().await?;
+        let mut cmd = bb8_redis::redis::cmd("SET");
+        cmd.arg(self.key(record.id))
+            .arg(record.value)
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64);
+        if only_existing {
+            cmd.arg("XX");
+        }
+        let written: Option<String> = cmd.query_async(&mut *conn).await?;
+        if written.is_none() {
+            return Ok(());
+        }
+
+        if let Some(user_id) = record.user_id {
+            let user_key = self.user_key(user_id);
+            bb8_redis::redis::cmd("SADD")
+                .arg(&user_key)
+                .arg(record.id)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+            // stale ids are pruned by `user_values`
+            bb8_redis::redis::cmd("EXPIRE")
+                .arg(&user_key)
+                .arg(self.index_ttl.as_secs().max(1))
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn replace(&self, old_id: &str, record: SessionRecord<'_>) -> anyhow::Result<bool> {
+        let ttl = (record.expires_at - Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        let mut conn = self.get_connection().await?;
+        let replaced: i64 = bb8_redis::redis::cmd("EVAL")
+            .arg(REPLACE_SCRIPT)
+            .arg(2)
+            .arg(self.key(old_id))
+            .arg(self.key(record.id))
+            .arg(record.value)
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut *conn)
+            .await?;
+        if replaced == 0 {
+            return Ok(false);
+        }
+
+        // the index is pruned on read, it does not have to move in the same step
+        if let Some(user_id) = record.user_id {
+            let user_key = self.user_key(user_id);
+            bb8_redis::redis::cmd("SREM")
+                .arg(&user_key)
+                .arg(old_id)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+            bb8_redis::redis::cmd("SADD")
+                .arg(&user_key)
+                .arg(record.id)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+            bb8_redis::redis::cmd("EXPIRE")
+                .arg(&user_key)
+                .arg(self.index_ttl.as_secs().max(1))
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        Ok(true)
+    }
+
+    async fn remove(&self, id: &str, user_id: Option<i64>) -> anyhow::Result<()> {
+        let mut conn = self.get_connection().await?;
+        bb8_redis::redis::cmd("DEL")
+            .arg(self.key(id))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        if let Some(user_id) = user_id {
+            bb8_redis::redis::cmd("SREM")
+                .arg(self.user_key(user_id))
+                .arg(id)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn user_values(&self, user_id: i64) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.get_connection().await?;
+        let ids: Vec<String> = bb8_redis::redis::cmd("SMEMBERS")
+            .arg(self.user_key(user_id))
+            .query_async(&mut *conn)
+            .await?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let values: Vec<Option<String>> = bb8_redis::redis::cmd("MGET")
+            .arg(ids.iter().map(|id| self.key(id)).collect::<Vec<_>>())
+            .query_async(&mut *conn)
+            .await?;
+
+        let mut live = Vec::new();
+        let mut stale = Vec::new();
+        for (id, value) in ids.into_iter().zip(values) {
+            match value {
+                Some(value) => live.push(value),
+                None => stale.push(id),
+            }
+        }
+        if !stale.is_empty() {
+            bb8_redis::redis::cmd("SREM")
+                .arg(self.user_key(user_id))
+                .arg(stale)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        Ok(live)
+    }
+
+    async fn remove_user_session(&self, user_id: i64, id: &str) -> anyhow::Result<bool> {
+        let mut conn = self.get_connection().await?;
+        let removed: i64 = bb8_redis::redis::cmd("SREM")
+            .arg(self.user_key(user_id))
+            .arg(id)
+            .query_async(&mut *conn)
+            .await?;
+        if removed == 0 {
+            return Ok(false);
+        }
+        bb8_redis::redis::cmd("UNLINK")
+            .arg(self.key(id))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(true)
+    }
+
+    async fn remove_user(&self, user_id: i64) -> anyhow::Result<usize> {
+        let mut conn = self.get_connection().await?;
+        let ids: Vec<String> = bb8_redis::redis::cmd("SMEMBERS")
+            .arg(self.user_key(user_id))
+            .query_async(&mut *conn)
+            .await?;
+        let mut keys = ids.iter().map(|id| self.key(id)).collect::<Vec<_>>();
+        keys.push(self.user_key(user_id));
+        bb8_redis::redis::cmd("UNLINK")
+            .arg(keys)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(ids.len())
+    }
+
+    async fn clear(&self) -> anyhow::Result<()> {
+        let sessions = self.unlink_matching(&format!("{}:*", self.prefix)).await?;
+        let users = self
+            .unlink_matching(&format!("{}_user:*", self.prefix))
+            .await?;
+        tracing::debug!("cleared {sessions} sessions and {users} user indexes");
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::convert::Infallible;
+
+use async_session::{Session, SessionStore};
+use axum::{
+    http::HeaderMap,
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+};
+use axum_extra::extract::CookieJar;
+
+use super::{reset_csrf_token, SessionCookieJar, SessionCookies, SessionStoreImpl};
+use crate::diagnostics;
+
+/// the session store and the session cookie together: what handlers use to start, rotate
+/// and end sessions so the stored id and the cookie never drift apart
+#[derive(Clone, Debug)]
+pub(crate) struct SessionService {
+    store: SessionStoreImpl,
+    cookies: SessionCookies,
+}
+
+impl SessionService {
+    pub(crate) fn new(store: SessionStoreImpl, cookies: SessionCookies) -> Self {
+        Self { store, cookies }
+    }
+
+    pub(crate) fn store(&self) -> &SessionStoreImpl {
+        &self.store
+    }
+
+    pub(crate) fn cookies(&self) -> &SessionCookies {
+        &self.cookies
+    }
+
+    /// the session behind the request's session cookie, `None` when there is none,
+    /// the cookie was not sealed by a key of the ring or the session is gone
+    pub(crate) async fn load(&self, headers: &HeaderMap) -> diagnostics::Result<Option<Session>> {
+        let Some(cookie_value) = self.cookies.read(headers) else {
+            return Ok(None);
+        };
+        Ok(self.store.load_session(cookie_value).await?)
+    }
+
+    /// stores a new session and sets its cookie and csrf token
+    pub(crate) async fn create(
+        &self,
+        mut session: Session,
+    ) -> diagnostics::Result<SessionCookieJar> {
+        let token = reset_csrf_token(&mut session).map_err(anyhow::Error::from)?;
+        let cookie_value = self
+            .store
+            .store_session(session)
+            .await?
+            .ok_or(diagnostics::Error::Unauthorized)?;
+        Ok(self.issue(cookie_value, &token))
+    }
+
+    /// moves the session to a new id and re-issues the cookie and csrf token, call it
+    /// after login, password change or role elevation. `Unauthorized` when the session is gone
+    pub(crate) async fn rotate(
+        &self,
+        mut session: Session,
+    ) -> diagnostics::Result<(Session, SessionCookieJar)> {
+        let token = reset_csrf_token(&mut session).map_err(anyhow::Error::from)?;
+        let (session, cookie_value) = self
+            .store
+            .rotate(session)
+            .await?
+            .ok_or(diagnostics::Error::Unauthorized)?;
+        Ok((session, self.issue(cookie_value, &token)))
+    }
+
+    /// destroys the session and expires its cookies
+    pub(crate) async fn destroy(&self, session: Session) -> diagnostics::Result<CookieJar> {
+        self.store.destroy_session(session).await?;
+        Ok(self.cookies.remove())
+    }
+
+    fn issue(&self, cookie_value: String, csrf_token: &str) -> SessionCookieJar {
+        self.cookies
+            .issue(cookie_value, self.store.absolute_ttl())
+            .with_csrf(self.cookies.csrf_cookie(csrf_token))
+    }
+}
+
+/// returned by a handler that wants the session it got from `Depends<Session>` on a new
+/// id. the `rotate_session` layer of the router rotates it once the handler is done and
+/// adds the new cookies to the response
+#[derive(Debug, Clone)]
+pub(crate) struct RotateSession(pub Session);
+
+impl IntoResponseParts for RotateSession {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.extensions_mut().insert(self);
+        Ok(res)
+    }
+}
+
+impl IntoResponse for RotateSession {
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
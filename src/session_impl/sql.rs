@@ -0,0 +1,124 @@
+use async_session::chrono::Utc;
+use async_trait::async_trait;
+use sqlx::Pool;
+
+use super::{SessionBackend, SessionRecord};
+use crate::app_state::DataBase;
+
+/// rows of the `sessions` table (migrations/0002_sessions.sql), `expires_at` in unix millis.
+/// the user index is the indexed `user_id` column
+#[derive(Debug, Clone)]
+pub struct SqlStore {
+    pool: Pool<DataBase>,
+}
+
+impl SqlStore {
+    pub fn new(pool: Pool<DataBase>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionBackend for SqlStore {
+    async fn get(&self, id: &str) -> anyhow::Result<Option<String>> {
+        Ok(sqlx::query_scalar::<_, String>(
+            "select value from sessions where id = $1 and expires_at > $2",
+        )
+        .bind(id)
+        .bind(Utc::now().timestamp_millis())
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    async fn put(&self, record: SessionRecord<'_>, only_existing: bool) -> anyhow::Result<()> {
+        if only_existing {
+            sqlx::query(
+                "update sessions set user_id = $1, value = $2, expires_at = $3 where id = $4",
+            )
+            .bind(record.user_id)
+            .bind(record.value)
+            .bind(record.expires_at.timestamp_millis())
+            .bind(record.id)
+            .execute(&self.pool)
+            .await?;
+            return Ok(());
+        }
+        // new sessions sweep the expired ones
+        sqlx::query("delete from sessions where expires_at <= $1")
+            .bind(Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            r#" insert into sessions(id, user_id, value, expires_at) values ($1, $2, $3, $4)
+                on conflict(id) do update set user_id = excluded.user_id,
+                value = excluded.value, expires_at = excluded.expires_at "#,
+        )
+        .bind(record.id)
+        .bind(record.user_id)
+        .bind(record.value)
+        .bind(record.expires_at.timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn replace(&self, old_id: &str, record: SessionRecord<'_>) -> anyhow::Result<bool> {
+        // the primary key changes in place, one statement so no reader sees both ids
+        let result = sqlx::query(
+            r#" update sessions set id = $1, user_id = $2, value = $3, expires_at = $4
+                where id = $5 and expires_at > $6 "#,
+        )
+        .bind(record.id)
+        .bind(record.user_id)
+        .bind(record.value)
+        .bind(record.expires_at.timestamp_millis())
+        .bind(old_id)
+        .bind(Utc::now().timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove(&self, id: &str, _user_id: Option<i64>) -> anyhow::Result<()> {
+        sqlx::query("delete from sessions where id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn user_values(&self, user_id: i64) -> anyhow::Result<Vec<String>> {
+        Ok(sqlx::query_scalar::<_, String>(
+            "select value from sessions where user_id = $1 and expires_at > $2",
+        )
+        .bind(user_id)
+        .bind(Utc::now().timestamp_millis())
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn remove_user_session(&self, user_id: i64, id: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("delete from sessions where id = $1 and user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn remove_user(&self, user_id: i64) -> anyhow::Result<usize> {
+        let result = sqlx::query("delete from sessions where user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() as usize)
+    }
+
+    async fn clear(&self) -> anyhow::Result<()> {
+        let result = sqlx::query("delete from sessions")
+            .execute(&self.pool)
+            .await?;
+        tracing::debug!("cleared {} sessions", result.rows_affected());
+        Ok(())
+    }
+}
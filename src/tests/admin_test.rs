@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use axum::{body::Body, routing::get, Router};
+use hyper::{header, Request, StatusCode};
+use tower::ServiceExt;
+
+use crate::{
+    app_state::AdminToken,
+    depends::{admin::Admin, Depends},
+};
+
+async fn status(token: Option<&str>, authorization: Option<&str>) -> StatusCode {
+    let app = Router::new()
+        .route("/admin", get(|_: Depends<Admin>| async { "ok" }))
+        .with_state(AdminToken(token.map(Arc::from)));
+    let mut req = Request::builder().uri("/admin");
+    if let Some(authorization) = authorization {
+        req = req.header(header::AUTHORIZATION, authorization);
+    }
+    let res = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+    res.status()
+}
+
+#[tokio::test]
+async fn bearer_token_required() {
+    assert_eq!(
+        status(Some("s3cret"), Some("Bearer s3cret")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(Some("s3cret"), Some("Bearer wrong")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(status(Some("s3cret"), None).await, StatusCode::UNAUTHORIZED);
+    // no token configured, admin routes stay closed
+    assert_eq!(
+        status(None, Some("Bearer s3cret")).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
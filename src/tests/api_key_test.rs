@@ -0,0 +1,202 @@
+use std::convert::Infallible;
+
+use axum::{body::Body, http::Response};
+use hyper::{header, Request, StatusCode};
+use tower::{Layer, ServiceExt};
+
+use crate::{
+    authz::{principal::Subject, Authenticator, GrpcPermission, Principal, VotingVote},
+    define, diagnostics, dto,
+    repository::ApiKeyRepositoryDB,
+    token_impl::JwtKeys,
+    usecase::{ApiKeyUsecase, BasicApiKeyUsecase},
+    util::config::TomlConfig,
+};
+
+fn create(name: &str, scopes: &[&str], expires_in_secs: Option<u64>) -> dto::ApiKeyCreate {
+    dto::ApiKeyCreate {
+        name: name.to_owned(),
+        scopes: scopes.iter().map(|s| (*s).to_owned()).collect(),
+        expires_in_secs,
+    }
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn create_authenticate_revoke() {
+    let pool = super::memory_pool().await;
+    let usecase = BasicApiKeyUsecase::new(ApiKeyRepositoryDB::new(pool));
+
+    for invalid in [
+        create(" ", &["sample:read"], None),
+        create("batch", &[], None),
+        create("batch", &["sample:delete"], None),
+    ] {
+        assert!(matches!(
+            usecase.create(invalid).await,
+            Err(diagnostics::Error::Message(_))
+        ));
+    }
+
+    let created = usecase
+        .create(create(
+            "batch",
+            &["sample:write", "sample:read", "sample:read"],
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(created.key.starts_with("ak_"));
+    assert!(created.key.starts_with(&created.api_key.prefix));
+    assert_ne!(created.api_key.key_hash, created.key);
+    assert_eq!(created.api_key.scopes, vec!["sample:read", "sample:write"]);
+    let json = serde_json::to_value(&created).unwrap();
+    assert!(json.get("key_hash").is_none());
+    assert_eq!(json["key"], created.key.as_str());
+
+    let listed = usecase.list().await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].name, "batch");
+
+    let api_key = usecase.authenticate(&created.key).await.unwrap();
+    assert_eq!(api_key.id, created.api_key.id);
+    let principal = Principal::api_key(&api_key);
+    assert_eq!(principal.subject, Subject::ApiKey(api_key.id));
+    assert_eq!(principal.user_id(), None);
+    assert!(principal.has("sample:write"));
+    assert!(matches!(
+        usecase.authenticate("ak_unknown").await,
+        Err(diagnostics::Error::Unauthorized)
+    ));
+
+    let expired = usecase
+        .create(create("old", &["sample:read"], Some(0)))
+        .await
+        .unwrap();
+    assert!(matches!(
+        usecase.authenticate(&expired.key).await,
+        Err(diagnostics::Error::Unauthorized)
+    ));
+
+    usecase.revoke(created.api_key.id).await.unwrap();
+    assert!(matches!(
+        usecase.authenticate(&created.key).await,
+        Err(diagnostics::Error::Unauthorized)
+    ));
+    assert!(matches!(
+        usecase.revoke(created.api_key.id).await,
+        Err(diagnostics::Error::RowNotFound)
+    ));
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn api_key_endpoints() {
+    let (app, db_pool, _db) = super::test_app(|config| {
+        config.http.admin_port = None;
+        config.http.admin_token = Some("s3cret".to_owned());
+    })
+    .await;
+    let config = TomlConfig::load("app_config.toml", None).unwrap();
+    let authenticator = Authenticator::new(
+        JwtKeys::new(&config.jwt).unwrap(),
+        ApiKeyUsecase::new(ApiKeyRepositoryDB::new(db_pool.clone())),
+    );
+
+    let admin = |method: &str, uri: &str, body: Body| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, "Bearer s3cret")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap()
+    };
+    let res = app
+        .clone()
+        .oneshot(admin(
+            "POST",
+            "/admin/api-keys",
+            Body::from(r#"{"name": "batch", "scopes": ["sample:read", "voting:vote"]}"#),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let key = created["key"].as_str().unwrap().to_owned();
+    let id = created["id"].as_i64().unwrap();
+
+    let res = app
+        .clone()
+        .oneshot(admin("GET", "/admin/api-keys", Body::empty()))
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let listed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(listed[0]["id"], id);
+    assert!(listed[0].get("key").is_none());
+
+    let sample = |method: &str, key: Option<&str>| {
+        let mut req = Request::builder()
+            .method(method)
+            .uri("/api/v1/sample")
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(key) = key {
+            req = req.header(define::API_KEY_HEADER, key);
+        }
+        let app = app.clone();
+        let req = req.body(Body::from(r#"{"name": "x"}"#)).unwrap();
+        async move { app.oneshot(req).await.unwrap().status() }
+    };
+    assert_eq!(sample("GET", Some(&key)).await, StatusCode::OK);
+    assert_eq!(sample("POST", Some(&key)).await, StatusCode::FORBIDDEN);
+    assert_eq!(
+        sample("GET", Some("ak_nope")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(sample("GET", None).await, StatusCode::UNAUTHORIZED);
+
+    // the same key in grpc metadata
+    let inner = tower::service_fn(|req: Request<Body>| async move {
+        let principal = req.extensions().get::<Principal>().unwrap();
+        let mut res = Response::new(tonic::body::empty_body());
+        res.headers_mut().insert(
+            "x-subject",
+            format!("{:?}", principal.subject).parse().unwrap(),
+        );
+        Ok::<_, Infallible>(res)
+    });
+    let grpc = GrpcPermission::<VotingVote>::new(authenticator).layer(inner);
+    let req = Request::builder()
+        .uri("/voting.Voting/Vote")
+        .header(header::CONTENT_TYPE, "application/grpc")
+        .header(define::API_KEY_HEADER, &key)
+        .body(Body::empty())
+        .unwrap();
+    let res = grpc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["x-subject"], format!("ApiKey({id})"));
+
+    let res = app
+        .clone()
+        .oneshot(admin(
+            "DELETE",
+            &format!("/admin/api-keys/{id}"),
+            Body::empty(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(sample("GET", Some(&key)).await, StatusCode::UNAUTHORIZED);
+    let res = app
+        .oneshot(admin(
+            "DELETE",
+            &format!("/admin/api-keys/{id}"),
+            Body::empty(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    db_pool.close().await;
+}
@@ -0,0 +1,231 @@
+use std::convert::Infallible;
+
+use async_session::Session;
+use axum::{
+    body::Body, extract::FromRef, http::Response, response::IntoResponse, routing::get, Router,
+};
+use hyper::{header, Request, StatusCode};
+use tower::{Layer, ServiceExt};
+
+use crate::{
+    authz::{
+        Authenticator, Grants, GrpcPermission, Principal, SampleRead, SampleWrite, VotingVote,
+    },
+    depends::permission::RequirePermission,
+    repository::ApiKeyRepositoryDB,
+    session_impl::{
+        self, ClientInfo, MemoryStore, SessionCookies, SessionService, SessionStoreImpl,
+    },
+    token_impl::{JwtKeys, TokenKind},
+    usecase::ApiKeyUsecase,
+    util::config::{JwtConfig, SessionConfig, SessionCookieConfig},
+};
+
+#[derive(Clone)]
+struct TestState {
+    sessions: SessionService,
+    authenticator: Authenticator,
+}
+
+impl FromRef<TestState> for SessionService {
+    fn from_ref(input: &TestState) -> Self {
+        input.sessions.clone()
+    }
+}
+
+impl FromRef<TestState> for Authenticator {
+    fn from_ref(input: &TestState) -> Self {
+        input.authenticator.clone()
+    }
+}
+
+fn keys() -> JwtKeys {
+    JwtKeys::new(&JwtConfig {
+        secret: "0123456789abcdef0123456789abcdef".to_owned(),
+        ..JwtConfig::default()
+    })
+    .unwrap()
+}
+
+// api keys are not used here, the database only has to exist
+fn authenticator(keys: JwtKeys) -> Authenticator {
+    let pool = crate::app_state::DataBasePoolOptions::new()
+        .connect_lazy("sqlite::memory:")
+        .unwrap();
+    Authenticator::new(keys, ApiKeyUsecase::new(ApiKeyRepositoryDB::new(pool)))
+}
+
+fn grants(role: &str, permissions: &[&str]) -> Grants {
+    Grants {
+        roles: vec![role.to_owned()],
+        permissions: permissions.iter().map(|p| (*p).to_owned()).collect(),
+    }
+}
+
+fn access_token(keys: &JwtKeys, grants: Grants) -> String {
+    let mut claims = keys.claims(7, TokenKind::Access, None);
+    claims.grants = grants;
+    keys.encode(&claims).unwrap()
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn require_permission() {
+    let state = TestState {
+        sessions: SessionService::new(
+            SessionStoreImpl::new(MemoryStore::new(), &SessionConfig::default()),
+            SessionCookies::new(&SessionCookieConfig::default()),
+        ),
+        authenticator: authenticator(keys()),
+    };
+    let keys = keys();
+    let app =
+        Router::new()
+            .route(
+                "/read",
+                get(|p: RequirePermission<SampleRead>| async move {
+                    format!("{:?}", p.principal.subject)
+                }),
+            )
+            .route(
+                "/write",
+                get(|p: RequirePermission<SampleWrite>| async move {
+                    format!("{:?}", p.principal.subject)
+                }),
+            )
+            .with_state(state.clone());
+
+    let session_cookie = |grants: Option<Grants>| {
+        let sessions = state.sessions.clone();
+        async move {
+            let mut session = Session::new();
+            session_impl::bind_user(&mut session, 7, &ClientInfo::default()).unwrap();
+            if let Some(grants) = grants {
+                session_impl::bind_grants(&mut session, &grants).unwrap();
+            }
+            let res = sessions.create(session).await.unwrap().into_response();
+            res.headers()
+                .get_all(header::SET_COOKIE)
+                .iter()
+                .map(|v| v.to_str().unwrap())
+                .find(|v| v.starts_with(crate::define::SESSION_COOKIE))
+                .map(|v| v.split(';').next().unwrap().to_owned())
+                .unwrap()
+        }
+    };
+    let status = |uri: &str, header: Option<(header::HeaderName, String)>| {
+        let mut req = Request::builder().uri(uri);
+        if let Some((name, value)) = header {
+            req = req.header(name, value);
+        }
+        let app = app.clone();
+        let req = req.body(Body::empty()).unwrap();
+        async move { app.oneshot(req).await.unwrap().status() }
+    };
+
+    assert_eq!(status("/read", None).await, StatusCode::UNAUTHORIZED);
+
+    // session of a plain user
+    let cookie = session_cookie(Some(grants("user", &["sample:read"]))).await;
+    let with_cookie = || Some((header::COOKIE, cookie.clone()));
+    assert_eq!(status("/read", with_cookie()).await, StatusCode::OK);
+    assert_eq!(status("/write", with_cookie()).await, StatusCode::FORBIDDEN);
+
+    // a session from before grants were recorded is allowed nothing
+    let cookie = session_cookie(None).await;
+    assert_eq!(
+        status("/read", Some((header::COOKIE, cookie))).await,
+        StatusCode::FORBIDDEN
+    );
+
+    // bearer token of an admin
+    let token = access_token(&keys, grants("admin", &["sample:read", "sample:write"]));
+    let bearer = Some((header::AUTHORIZATION, format!("Bearer {token}")));
+    assert_eq!(status("/write", bearer).await, StatusCode::OK);
+    let bearer = Some((header::AUTHORIZATION, "Bearer nonsense".to_owned()));
+    assert_eq!(status("/read", bearer).await, StatusCode::UNAUTHORIZED);
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn grpc_permission() {
+    let keys = keys();
+    // answers with the user id the layer put in the extensions
+    let inner = tower::service_fn(|req: Request<Body>| async move {
+        let principal = req.extensions().get::<Principal>().unwrap();
+        let mut res = Response::new(tonic::body::empty_body());
+        res.headers_mut().insert(
+            "x-user-id",
+            principal.user_id().unwrap().to_string().parse().unwrap(),
+        );
+        Ok::<_, Infallible>(res)
+    });
+    let service = GrpcPermission::<VotingVote>::new(authenticator(keys.clone())).layer(inner);
+    let call = |token: Option<String>| {
+        let mut req = Request::builder().uri("/voting.Voting/Vote");
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let service = service.clone();
+        let req = req.body(Body::empty()).unwrap();
+        async move { service.oneshot(req).await.unwrap() }
+    };
+    let grpc_status = |res: &Response<_>| res.headers()["grpc-status"].to_str().unwrap().to_owned();
+
+    let res = call(None).await;
+    assert_eq!(
+        grpc_status(&res),
+        (tonic::Code::Unauthenticated as i32).to_string()
+    );
+
+    let token = access_token(&keys, grants("user", &["sample:read"]));
+    let res = call(Some(token)).await;
+    assert_eq!(
+        grpc_status(&res),
+        (tonic::Code::PermissionDenied as i32).to_string()
+    );
+
+    let token = access_token(&keys, grants("user", &["voting:vote"]));
+    let res = call(Some(token)).await;
+    assert_eq!(res.headers()["x-user-id"], "7");
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn role_grants() {
+    use crate::{
+        dto,
+        repository::{UserRepository, UserRepositoryDB},
+        usecase::BasicUserUsecase,
+    };
+
+    let pool = super::memory_pool().await;
+    let users = UserRepositoryDB::new(pool);
+    let user = BasicUserUsecase::new(users.clone())
+        .register(dto::UserRegister {
+            name: "alice".to_owned(),
+            email: "alice@example.com".to_owned(),
+            password: "correct horse".to_owned(),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        users.grants(user.id).await.unwrap(),
+        grants("user", &["sample:read", "voting:vote"])
+    );
+
+    users.add_role(user.id, "admin").await.unwrap();
+    users.add_role(user.id, "admin").await.unwrap();
+    let granted = users.grants(user.id).await.unwrap();
+    assert_eq!(granted.roles, vec!["admin", "user"]);
+    assert_eq!(
+        granted.permissions,
+        vec!["sample:read", "sample:write", "voting:vote"]
+    );
+
+    assert!(users.remove_role(user.id, "admin").await.unwrap());
+    assert!(!users.remove_role(user.id, "admin").await.unwrap());
+    assert!(!users.grants(user.id).await.unwrap().has("sample:write"));
+    assert_eq!(users.grants(user.id + 1).await.unwrap(), Grants::default());
+}
@@ -0,0 +1,107 @@
+use toml::{Table, Value};
+
+use crate::util::config::{self, TomlConfig};
+
+fn table(s: &str) -> Table {
+    toml::from_str::<Table>(s).unwrap()
+}
+
+#[test]
+fn merge_overlay() {
+    let mut base = table(
+        r#"
+        [database]
+        url = "sqlite://sqlite.db"
+        max_connection = 8
+        [redis]
+        url = "redis://localhost:6379"
+        "#,
+    );
+    config::merge(
+        &mut base,
+        table(
+            r#"
+            [database]
+            max_connection = 32
+            "#,
+        ),
+    );
+    assert_eq!(base["database"]["max_connection"].as_integer(), Some(32));
+    assert_eq!(base["database"]["url"].as_str(), Some("sqlite://sqlite.db"));
+    assert_eq!(
+        base["redis"]["url"].as_str(),
+        Some("redis://localhost:6379")
+    );
+}
+
+#[test]
+fn env_overrides() {
+    let mut base = table(
+        r#"
+        [database]
+        url = "sqlite://sqlite.db"
+        max_connection = 8
+        with_migrations = true
+        "#,
+    );
+    let vars = [
+        ("APP__DATABASE__URL", "postgres://sa:sa@db/app"),
+        ("APP__DATABASE__MAX_CONNECTION", "16"),
+        ("APP__DATABASE__WITH_MIGRATIONS", "false"),
+        ("APP__REDIS__URL", "redis://redis:6379"),
+        ("PATH", "/usr/bin"),
+    ]
+    .map(|(k, v)| (k.to_owned(), v.to_owned()));
+    config::apply_env_overrides(&mut base, vars).unwrap();
+
+    assert_eq!(
+        base["database"]["url"].as_str(),
+        Some("postgres://sa:sa@db/app")
+    );
+    assert_eq!(base["database"]["max_connection"].as_integer(), Some(16));
+    assert_eq!(base["database"]["with_migrations"].as_bool(), Some(false));
+    assert_eq!(base["redis"]["url"].as_str(), Some("redis://redis:6379"));
+
+    let vars = [(
+        "APP__DATABASE__MAX_CONNECTION".to_owned(),
+        "many".to_owned(),
+    )];
+    assert!(config::apply_env_overrides(&mut base, vars).is_err());
+}
+
+#[test]
+fn profile_overlay_path() {
+    assert_eq!(
+        config::profile_path("conf/app_config.toml", "prod"),
+        std::path::Path::new("conf/app_config_prod.toml")
+    );
+}
+
+#[cfg(feature = "use_sqlite")]
+#[test]
+fn sqlite_url_names_a_file() {
+    let mut config = TomlConfig::load("app_config.toml", None).unwrap();
+    for url in ["sqlite::memory:", "sqlite:app.db"] {
+        config.database.url = url.to_owned();
+        assert!(config.validate().is_err(), "{url}");
+    }
+    config.database.url = "sqlite://app.db".to_owned();
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn redacted_dump() {
+    let mut config = TomlConfig::load("app_config.toml", None).unwrap();
+    config.database.url = "postgres://sa:secret@db:5432/app".to_owned();
+    config.jwt.secret = "jwt-signing-secret".to_owned();
+    let dump = config.to_redacted_string().unwrap();
+    assert!(dump.contains("postgres://sa:***@db:5432/app"));
+    // key names stay, values are masked
+    assert!(!dump.contains("sa:secret"));
+    assert!(!dump.contains("jwt-signing-secret"));
+    assert!(toml::from_str::<Value>(&dump).is_ok());
+    assert_eq!(
+        config::redact_url("redis://localhost:6379"),
+        "redis://localhost:6379"
+    );
+}
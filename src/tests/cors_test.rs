@@ -0,0 +1,85 @@
+use axum::{body::Body, routing::get, Router};
+use hyper::{header, Method, Request, StatusCode};
+use tower::ServiceExt;
+
+use crate::{router, util::config::CorsConfig};
+
+fn credentialed(origins: &[&str]) -> CorsConfig {
+    CorsConfig {
+        allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+        allow_credentials: true,
+        ..CorsConfig::default()
+    }
+}
+
+#[test]
+fn wildcard_subdomain() {
+    let pattern = "https://*.example.com";
+    assert!(router::origin_matches(pattern, "https://app.example.com"));
+    assert!(router::origin_matches(pattern, "https://a.b.example.com"));
+    assert!(!router::origin_matches(pattern, "https://example.com"));
+    assert!(!router::origin_matches(pattern, "http://app.example.com"));
+    assert!(!router::origin_matches(
+        pattern,
+        "https://app.example.com.evil.io"
+    ));
+    assert!(router::origin_matches(
+        "https://app.example.com",
+        "https://app.example.com"
+    ));
+}
+
+#[test]
+fn validation() {
+    assert!(CorsConfig::default().validate().is_ok());
+    assert!(credentialed(&["https://*.example.com"]).validate().is_ok());
+    assert!(credentialed(&["*"]).validate().is_err());
+    assert!(credentialed(&["https://*.example.com", "*"])
+        .validate()
+        .is_err());
+    assert!(credentialed(&["https://example.com/path"])
+        .validate()
+        .is_err());
+    assert!(credentialed(&["https://ex*ample.com"]).validate().is_err());
+
+    let mut config = credentialed(&["https://example.com"]);
+    config.allowed_headers = vec!["*".to_owned()];
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn preflight() {
+    let app = Router::new()
+        .route("/", get(|| async { "ok" }))
+        .layer(router::cors(&credentialed(&["https://*.example.com"])));
+
+    let preflight = |origin: &'static str| {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = app
+        .clone()
+        .oneshot(preflight("https://app.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example.com"
+    );
+    assert_eq!(
+        res.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+        "true"
+    );
+
+    let res = app.oneshot(preflight("https://evil.io")).await.unwrap();
+    assert!(!res
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashSet, sync::Arc};
+
+use async_session::Session;
+use axum::{
+    body::Body,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use hyper::{header, Method, Request, StatusCode};
+use tower::ServiceExt;
+
+use crate::{
+    define,
+    router::{
+        self,
+        routing::{self, RouteTable},
+    },
+    session_impl::{MemoryStore, SessionCookies, SessionService, SessionStoreImpl},
+    util::{
+        config::{SessionConfig, SessionCookieConfig},
+        middleware::{self, Csrf},
+    },
+};
+
+fn app(sessions: SessionService) -> Router {
+    Router::new()
+        .route("/", get(|| async { "ok" }).post(|| async { "ok" }))
+        .route("/hook", post(|| async { "ok" }))
+        .layer(axum::middleware::from_fn_with_state(
+            Csrf {
+                sessions,
+                exempt: Arc::new(HashSet::from(["/hook".to_owned()])),
+            },
+            middleware::csrf_check,
+        ))
+}
+
+fn req(method: &str, uri: &str, cookie: Option<&str>, token: Option<&str>) -> Request<Body> {
+    let mut req = Request::builder().method(method).uri(uri);
+    if let Some(cookie) = cookie {
+        req = req.header(header::COOKIE, cookie);
+    }
+    if let Some(token) = token {
+        req = req.header(define::CSRF_HEADER, token);
+    }
+    req.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn csrf_check() {
+    let sessions = SessionService::new(
+        SessionStoreImpl::new(MemoryStore::new(), &SessionConfig::default()),
+        SessionCookies::new(&SessionCookieConfig::default()),
+    );
+    let res = sessions
+        .create(Session::new())
+        .await
+        .unwrap()
+        .into_response();
+    let token = res.headers()[define::CSRF_HEADER]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let sealed = res
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .find(|v| v.starts_with(define::SESSION_COOKIE))
+        .map(|v| v.split(';').next().unwrap().to_owned())
+        .unwrap();
+    let app = app(sessions);
+
+    // safe methods pass and hand out the token
+    let res = app
+        .clone()
+        .oneshot(req("GET", "/", Some(&sealed), None))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[define::CSRF_HEADER], token.as_str());
+
+    for sent in [None, Some("forged")] {
+        let res = app
+            .clone()
+            .oneshot(req("POST", "/", Some(&sealed), sent))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["code"], "csrf_token_invalid");
+    }
+
+    // a grpc content type on a rest route is no way around the token
+    let mut grpc = req("POST", "/", Some(&sealed), None);
+    grpc.headers_mut()
+        .insert(header::CONTENT_TYPE, "application/grpc".parse().unwrap());
+    let res = app.clone().oneshot(grpc).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app
+        .clone()
+        .oneshot(req("POST", "/", Some(&sealed), Some(&token)))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // exempt routes and requests without a session cookie are not checked
+    let res = app
+        .clone()
+        .oneshot(req("POST", "/hook", Some(&sealed), None))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app.oneshot(req("POST", "/", None, None)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[test]
+fn csrf_exempt_is_listed() {
+    let table = RouteTable::<()>::new()
+        .route("/form", routing::post(|| async {}))
+        .route("/hook", routing::post(|| async {}).csrf_exempt());
+    let exempt = table
+        .routes()
+        .iter()
+        .map(|route| (route.path.as_str(), route.csrf_exempt))
+        .collect::<Vec<_>>();
+    assert_eq!(exempt, [("/form", false), ("/hook", true)]);
+}
+
+#[test]
+fn session_remove_is_unsafe() {
+    // safe methods skip the check, a cross-site <img> must not be able to log users out
+    let routes = router::routes();
+    let remove = routes
+        .iter()
+        .find(|route| route.path == "/api/basic/session/remove")
+        .unwrap();
+    assert_eq!(remove.methods, vec![Method::POST]);
+}
@@ -0,0 +1,164 @@
+use axum::{body::Body, routing::get, Router};
+use hyper::{header, Request, StatusCode};
+use tonic_types::StatusExt;
+use tower::ServiceExt;
+
+use crate::{
+    define,
+    diagnostics::{Error, Problem},
+    util::middleware,
+};
+
+async fn problem_of(res: axum::response::Response) -> Problem {
+    let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[test]
+fn status_and_code() {
+    let cases = [
+        (Error::RowNotFound, StatusCode::NOT_FOUND, "not_found"),
+        (
+            Error::Unauthorized,
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+        ),
+        (Error::MfaRequired, StatusCode::UNAUTHORIZED, "mfa_required"),
+        (Error::Forbidden, StatusCode::FORBIDDEN, "forbidden"),
+        (
+            Error::NotImplemented,
+            StatusCode::NOT_IMPLEMENTED,
+            "not_implemented",
+        ),
+        (
+            Error::SqlXError(sqlx::Error::PoolTimedOut),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "database_unavailable",
+        ),
+        (
+            Error::SqlXError(sqlx::Error::ColumnNotFound("id".into())),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database_error",
+        ),
+        (
+            Error::BB8Error("refused".into()),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "redis_unavailable",
+        ),
+        (
+            Error::RedisError(bb8::RunError::TimedOut),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "redis_unavailable",
+        ),
+        (
+            Error::Message("bad".into()),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            Error::Conflict("taken".into()),
+            StatusCode::CONFLICT,
+            "conflict",
+        ),
+        (
+            Error::CsrfError("missing"),
+            StatusCode::FORBIDDEN,
+            "csrf_token_invalid",
+        ),
+    ];
+    for (error, status, code) in cases {
+        assert_eq!(error.status(), status, "{error:?}");
+        assert_eq!(error.code(), code, "{error:?}");
+    }
+}
+
+#[tokio::test]
+async fn server_errors_hide_detail() {
+    let res = axum::response::IntoResponse::into_response(Error::SqlXError(sqlx::Error::Protocol(
+        "secret internals".into(),
+    )));
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        define::PROBLEM_JSON_CONTENT_TYPE
+    );
+    let problem = problem_of(res).await;
+    assert_eq!(problem.code, "database_error");
+    assert!(problem.detail.is_none());
+    assert!(problem.debug.is_none());
+}
+
+#[tokio::test]
+async fn request_id_and_debug() {
+    let app = |debug_errors| {
+        Router::new()
+            .route(
+                "/",
+                get(|| async { Err::<(), _>(Error::SqlXError(sqlx::Error::PoolClosed)) }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                debug_errors,
+                middleware::problem_details,
+            ))
+    };
+    let request = || {
+        Request::builder()
+            .uri("/")
+            .header(define::REQUEST_ID_HEADER, "req-1")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let problem = problem_of(app(false).oneshot(request()).await.unwrap()).await;
+    assert_eq!(problem.status, 503);
+    assert_eq!(problem.request_id.as_deref(), Some("req-1"));
+    assert!(problem.debug.is_none());
+
+    let problem = problem_of(app(true).oneshot(request()).await.unwrap()).await;
+    assert!(problem.debug.unwrap().contains("PoolClosed"));
+}
+
+#[test]
+fn into_grpc_status() {
+    let cases = [
+        (Error::RowNotFound, tonic::Code::NotFound),
+        (Error::Unauthorized, tonic::Code::Unauthenticated),
+        (Error::Forbidden, tonic::Code::PermissionDenied),
+        (Error::Message("bad".into()), tonic::Code::InvalidArgument),
+        (Error::BB8Error("refused".into()), tonic::Code::Unavailable),
+        (
+            Error::SqlXError(sqlx::Error::PoolTimedOut),
+            tonic::Code::Unavailable,
+        ),
+        (
+            Error::SqlXError(sqlx::Error::Protocol("secret internals".into())),
+            tonic::Code::Internal,
+        ),
+    ];
+    for (error, code) in cases {
+        let expected_reason = error.code();
+        let status = tonic::Status::from(error);
+        assert_eq!(status.code(), code);
+        let info = status.get_details_error_info().unwrap();
+        assert_eq!(info.reason, expected_reason);
+        assert!(!status.message().contains("secret"));
+    }
+}
+
+#[test]
+fn from_grpc_status() {
+    assert!(matches!(
+        Error::from(tonic::Status::not_found("gone")),
+        Error::NotFound
+    ));
+    assert!(matches!(
+        Error::from(tonic::Status::invalid_argument("bad")),
+        Error::Message(m) if m == "bad"
+    ));
+
+    let error = Error::from(tonic::Status::unavailable("down"));
+    assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(error.code(), "upstream_error");
+    // upstream statuses go back out untouched
+    assert_eq!(tonic::Status::from(error).code(), tonic::Code::Unavailable);
+}
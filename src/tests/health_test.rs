@@ -0,0 +1,27 @@
+use crate::{app_state::AppState, health, util::config::TomlConfig};
+
+#[tokio::test]
+async fn readiness_reports_each_dependency() {
+    let config = TomlConfig::load("app_config.toml", None).unwrap();
+    let app_state = AppState::new(&config).await.unwrap();
+    app_state.migrate_database().await.unwrap();
+
+    let readiness = health::readiness(&app_state).await;
+    assert_eq!(readiness.checks["database"].status, health::CheckStatus::Ok);
+    assert_eq!(
+        readiness.checks["migrations"].status,
+        health::CheckStatus::Ok
+    );
+    // redis may or may not be running where the tests are
+    assert_eq!(
+        readiness.is_ready(),
+        readiness.checks["redis"].status == health::CheckStatus::Ok
+    );
+
+    let json = serde_json::to_value(&readiness).unwrap();
+    assert!(json["checks"]["database"]["latency_ms"].is_number());
+    // a failed check says how, never why
+    let redis = json["checks"]["redis"].as_object().unwrap();
+    assert!(["ok", "error", "timeout"].contains(&redis["status"].as_str().unwrap()));
+    assert_eq!(redis.len(), 2);
+}
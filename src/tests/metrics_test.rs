@@ -0,0 +1,126 @@
+use axum::{body::Body, routing::get, Router};
+use hyper::{body::HttpBody, header, Request, StatusCode};
+use prost::Message;
+use tower::{Layer, ServiceExt};
+
+use crate::{
+    app_state::AppState,
+    metrics::{self, GrpcMetricsLayer, Metrics},
+    proto::voting::{voting_server::VotingServer, VotingRequest, VotingService},
+    router,
+    util::config::TomlConfig,
+};
+
+#[tokio::test]
+async fn http_by_matched_route() {
+    let metrics = Metrics::new();
+    let app = Router::new()
+        .route("/items/:id", get(|| async { "item" }))
+        .layer(axum::middleware::from_fn_with_state(
+            metrics.clone(),
+            metrics::track_http,
+        ));
+    for id in ["1", "2"] {
+        let req = Request::builder()
+            .uri(format!("/items/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap();
+    }
+
+    let text = metrics.encode();
+    assert!(
+        text.contains(r#"http_requests_total{method="GET",route="/items/:id",status="200"} 2"#),
+        "{text}"
+    );
+    assert!(text.contains("http_request_duration_seconds_bucket"));
+    assert!(text.contains(&format!(
+        r#"build_info{{name="app",version="{}"}} 1"#,
+        env!("CARGO_PKG_VERSION")
+    )));
+    assert!(text.contains("process_uptime_seconds"));
+}
+
+async fn vote(metrics: &Metrics, vote: i32) {
+    let message = VotingRequest {
+        url: "https://example.com".to_owned(),
+        vote,
+    }
+    .encode_to_vec();
+    let mut frame = vec![0u8];
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+
+    let service =
+        GrpcMetricsLayer::new(metrics.clone()).layer(VotingServer::new(VotingService::default()));
+    let req = Request::builder()
+        .method("POST")
+        .uri("/voting.Voting/Vote")
+        .header("content-type", "application/grpc")
+        .body(Body::from(frame))
+        .unwrap();
+    let mut body = service.oneshot(req).await.unwrap().into_body();
+    while body.data().await.is_some() {}
+    let _ = body.trailers().await;
+}
+
+#[tokio::test]
+async fn grpc_by_method_and_code() {
+    let metrics = Metrics::new();
+    vote(&metrics, 0).await;
+    vote(&metrics, 7).await;
+
+    let text = metrics.encode();
+    assert!(
+        text.contains(r#"grpc_requests_total{code="Ok",method="/voting.Voting/Vote"} 1"#),
+        "{text}"
+    );
+    assert!(
+        text.contains(
+            r#"grpc_requests_total{code="InvalidArgument",method="/voting.Voting/Vote"} 1"#
+        ),
+        "{text}"
+    );
+}
+
+#[tokio::test]
+async fn admin_token_on_the_api_port() {
+    let mut config = TomlConfig::load("app_config.toml", None).unwrap();
+    config.http.admin_port = None;
+    config.http.admin_token = Some("s3cret".to_owned());
+    let app = router::init_router(AppState::new(&config).await.unwrap(), &config.http);
+
+    let scrape = |authorization: Option<&str>| {
+        let mut req = Request::builder().uri("/metrics");
+        if let Some(authorization) = authorization {
+            req = req.header(header::AUTHORIZATION, authorization);
+        }
+        app.clone().oneshot(req.body(Body::empty()).unwrap())
+    };
+    assert_eq!(
+        scrape(None).await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+    let res = scrape(Some("Bearer s3cret")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert!(text.contains("db_pool_acquire_wait_seconds"), "{text}");
+}
+
+#[tokio::test]
+async fn scrape_does_not_wait_out_a_busy_pool() {
+    let mut config = TomlConfig::load("app_config.toml", None).unwrap();
+    config.database.max_connection = 1;
+    let app_state = AppState::new(&config).await.unwrap();
+    let held = app_state.db_pool.acquire().await.unwrap();
+
+    let text = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        app_state.metrics.render(&app_state),
+    )
+    .await
+    .unwrap();
+    assert!(text.contains("db_pool_acquire_misses_total 1"), "{text}");
+    drop(held);
+}
@@ -0,0 +1,387 @@
+use axum::body::Body;
+use data_encoding::BASE32_NOPAD;
+use hyper::{header, Request, StatusCode};
+use tower::ServiceExt;
+
+use crate::{
+    authz::totp, define, diagnostics, dto, repository::UserRepositoryDB, usecase::BasicUserUsecase,
+};
+
+// rfc 6238 appendix b, sha1 with the codes cut to six digits
+const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+fn code(secret: &str, step: u64) -> String {
+    totp::hotp(&BASE32_NOPAD.decode(secret.as_bytes()).unwrap(), step)
+}
+
+fn unauthorized<T>(result: diagnostics::Result<T>) -> bool {
+    matches!(result, Err(diagnostics::Error::Unauthorized))
+}
+
+// of the usecase test
+const MAX_ATTEMPTS: u32 = 5;
+
+fn current_step() -> u64 {
+    jsonwebtoken::get_current_timestamp() / 30
+}
+
+#[test]
+fn totp_codes() {
+    for (time, expected) in [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+    ] {
+        assert_eq!(code(RFC_SECRET, time / 30), expected);
+    }
+    assert_eq!(totp::verify(RFC_SECRET, "287082", 59), Some(1));
+    // one step of clock drift either way
+    assert_eq!(totp::verify(RFC_SECRET, " 287082 ", 89), Some(1));
+    assert_eq!(totp::verify(RFC_SECRET, "287082", 0), Some(1));
+    assert_eq!(totp::verify(RFC_SECRET, "287082", 120), None);
+    for invalid in ["", "28708", "2870820", "28708a"] {
+        assert_eq!(totp::verify(RFC_SECRET, invalid, 59), None);
+    }
+    assert_eq!(totp::verify("not base32!", "287082", 59), None);
+
+    let secret = totp::generate_secret();
+    assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), 20);
+    let uri = totp::provisioning_uri("My App", "alice@example.com", &secret);
+    assert!(uri.starts_with("otpauth://totp/My%20App%3Aalice%40example.com?"));
+    assert!(uri.contains(&format!("secret={secret}&issuer=My%20App")));
+
+    let codes = totp::generate_recovery_codes();
+    assert_eq!(codes.len(), totp::RECOVERY_CODES);
+    assert_eq!(
+        totp::hash_recovery_code(&codes[0]),
+        totp::hash_recovery_code(&codes[0].to_uppercase().replace('-', ""))
+    );
+    assert_ne!(
+        totp::hash_recovery_code(&codes[0]),
+        totp::hash_recovery_code(&codes[1])
+    );
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn enroll_verify_reset() {
+    let pool = super::memory_pool().await;
+    let usecase = BasicUserUsecase::new(UserRepositoryDB::new(pool));
+    let user = usecase
+        .register(dto::UserRegister {
+            name: "alice".to_owned(),
+            email: "alice@example.com".to_owned(),
+            password: "correct horse".to_owned(),
+        })
+        .await
+        .unwrap();
+
+    // nothing to check before the secret is confirmed
+    usecase
+        .second_factor(&user, None, MAX_ATTEMPTS)
+        .await
+        .unwrap();
+    assert!(matches!(
+        usecase.confirm_totp(user.id, "000000").await,
+        Err(diagnostics::Error::Message(_))
+    ));
+    let enrollment = usecase.enroll_totp(&user, "app").await.unwrap();
+    assert!(enrollment.provisioning_uri.contains(&enrollment.secret));
+    usecase
+        .second_factor(&user, None, MAX_ATTEMPTS)
+        .await
+        .unwrap();
+    assert!(unauthorized(
+        usecase.verify_mfa(user.id, "000000", MAX_ATTEMPTS).await
+    ));
+
+    let step = current_step();
+    let wrong = code(&enrollment.secret, step + 5);
+    assert!(unauthorized(usecase.confirm_totp(user.id, &wrong).await));
+    let confirmed = code(&enrollment.secret, step);
+    let recovery = usecase
+        .confirm_totp(user.id, &confirmed)
+        .await
+        .unwrap()
+        .recovery_codes;
+    assert_eq!(recovery.len(), totp::RECOVERY_CODES);
+    assert!(matches!(
+        usecase.enroll_totp(&user, "app").await,
+        Err(diagnostics::Error::Conflict(_))
+    ));
+
+    assert!(matches!(
+        usecase.second_factor(&user, None, MAX_ATTEMPTS).await,
+        Err(diagnostics::Error::MfaRequired)
+    ));
+    assert!(usecase.has_second_factor(&user).await.unwrap());
+    // the confirming code is used up, the next one works once
+    assert!(unauthorized(
+        usecase.verify_mfa(user.id, &confirmed, MAX_ATTEMPTS).await
+    ));
+    let next = code(&enrollment.secret, step + 1);
+    assert_eq!(
+        usecase
+            .verify_mfa(user.id, &next, MAX_ATTEMPTS)
+            .await
+            .unwrap()
+            .id,
+        user.id
+    );
+    assert!(unauthorized(
+        usecase
+            .second_factor(&user, Some(&next), MAX_ATTEMPTS)
+            .await
+    ));
+
+    usecase
+        .second_factor(&user, Some(&recovery[0].to_uppercase()), MAX_ATTEMPTS)
+        .await
+        .unwrap();
+    assert!(unauthorized(
+        usecase
+            .second_factor(&user, Some(&recovery[0]), MAX_ATTEMPTS)
+            .await
+    ));
+    usecase
+        .verify_mfa(user.id, &recovery[1], MAX_ATTEMPTS)
+        .await
+        .unwrap();
+
+    // a right code starts the count over, wrong ones in a row lock the second factor
+    for _ in 0..MAX_ATTEMPTS {
+        assert!(unauthorized(
+            usecase.verify_mfa(user.id, "000000", MAX_ATTEMPTS).await
+        ));
+    }
+    assert!(unauthorized(
+        usecase
+            .verify_mfa(user.id, &recovery[2], MAX_ATTEMPTS)
+            .await
+    ));
+
+    usecase.reset_mfa(user.id).await.unwrap();
+    usecase
+        .second_factor(&user, None, MAX_ATTEMPTS)
+        .await
+        .unwrap();
+    assert!(unauthorized(
+        usecase
+            .verify_mfa(user.id, &recovery[2], MAX_ATTEMPTS)
+            .await
+    ));
+    assert!(matches!(
+        usecase.reset_mfa(user.id).await,
+        Err(diagnostics::Error::RowNotFound)
+    ));
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn mfa_endpoints() {
+    let (app, db_pool, _db) = super::test_app(|config| {
+        config.http.admin_port = None;
+        config.http.admin_token = Some("s3cret".to_owned());
+        config.mfa.max_attempts = 2;
+    })
+    .await;
+
+    // method, uri, session cookie and csrf token, json body
+    let call =
+        |method: &str, uri: &str, session: Option<&(String, String)>, body: serde_json::Value| {
+            let mut req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json");
+            if let Some((cookie, token)) = session {
+                req = req
+                    .header(header::COOKIE, cookie)
+                    .header(define::CSRF_HEADER, token);
+            }
+            let req = req.body(Body::from(body.to_string())).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+    let session_of = |res: &hyper::Response<axum::body::BoxBody>| {
+        let cookie = res
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .find(|v| v.starts_with(define::SESSION_COOKIE))
+            .map(|v| v.split(';').next().unwrap().to_owned())
+            .unwrap();
+        let token = res.headers()[define::CSRF_HEADER]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        (cookie, token)
+    };
+    let json = |res: hyper::Response<axum::body::BoxBody>| async move {
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    let email = format!("{}@example.com", uuid::Uuid::new_v4().simple());
+    let credentials = serde_json::json!({ "email": email, "password": "correct horse" });
+    let register =
+        serde_json::json!({ "name": "alice", "email": email, "password": "correct horse" });
+    let res = call("POST", "/api/v1/auth/register", None, register).await;
+    let user_id = json(res).await["id"].as_i64().unwrap();
+    let res = call("POST", "/api/v1/auth/login", None, credentials.clone()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let session = session_of(&res);
+
+    let res = call(
+        "POST",
+        "/api/v1/auth/mfa/totp",
+        Some(&session),
+        serde_json::json!({}),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let secret = json(res).await["secret"].as_str().unwrap().to_owned();
+    let step = current_step();
+    let res = call(
+        "POST",
+        "/api/v1/auth/mfa/totp/confirm",
+        Some(&session),
+        serde_json::json!({ "code": code(&secret, step) }),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let recovery = json(res).await["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+
+    // the password alone leaves the session waiting for the code
+    let res = call("POST", "/api/v1/auth/login", None, credentials.clone()).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let pending = session_of(&res);
+    assert_eq!(json(res).await["mfa_required"], true);
+    let me = |session: &(String, String)| {
+        call(
+            "GET",
+            "/api/v1/auth/me",
+            Some(session),
+            serde_json::Value::Null,
+        )
+    };
+    assert_eq!(me(&pending).await.status(), StatusCode::UNAUTHORIZED);
+    let res = call(
+        "GET",
+        "/api/v1/sample",
+        Some(&pending),
+        serde_json::Value::Null,
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let verify = |session: &(String, String), code: &str| {
+        call(
+            "POST",
+            "/api/v1/auth/mfa/verify",
+            Some(session),
+            serde_json::json!({ "code": code }),
+        )
+    };
+    assert_eq!(
+        verify(&pending, "000000").await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    let res = verify(&pending, &code(&secret, step + 1)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let verified = session_of(&res);
+    assert_ne!(verified.0, pending.0);
+    assert_eq!(me(&verified).await.status(), StatusCode::OK);
+    let res = call(
+        "GET",
+        "/api/v1/sample",
+        Some(&verified),
+        serde_json::Value::Null,
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    // a logged in session has nothing to verify
+    assert_eq!(
+        verify(&verified, &recovery[0]).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    // the code can come with the password, bearer tokens need it there
+    let res = call("POST", "/api/v1/auth/token", None, credentials.clone()).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(json(res).await["code"], "mfa_required");
+    let with_code = |code: &str| {
+        let mut with_code = credentials.clone();
+        with_code["mfa_code"] = code.into();
+        with_code
+    };
+    let res = call("POST", "/api/v1/auth/token", None, with_code(&recovery[0])).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = call("POST", "/api/v1/auth/login", None, with_code(&recovery[0])).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = call("POST", "/api/v1/auth/login", None, with_code(&recovery[1])).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // wrong codes count on every route, after max_attempts in a row the right ones are
+    // refused as well
+    let res = call("POST", "/api/v1/auth/login", None, credentials.clone()).await;
+    let pending = session_of(&res);
+    assert_eq!(
+        verify(&pending, "000000").await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    let res = call("POST", "/api/v1/auth/token", None, with_code("000000")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        verify(&pending, &recovery[2]).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    let res = call("POST", "/api/v1/auth/token", None, with_code(&recovery[3])).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = call("POST", "/api/v1/auth/login", None, with_code(&recovery[4])).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // another user without a second factor logging in on the same browser is not kept
+    // waiting for the code of the first
+    let res = call("POST", "/api/v1/auth/login", None, credentials.clone()).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let pending = session_of(&res);
+    let other = format!("{}@example.com", uuid::Uuid::new_v4().simple());
+    let register =
+        serde_json::json!({ "name": "bob", "email": other, "password": "correct horse" });
+    call("POST", "/api/v1/auth/register", None, register).await;
+    let res = call(
+        "POST",
+        "/api/v1/auth/login",
+        Some(&pending),
+        serde_json::json!({ "email": other, "password": "correct horse" }),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = me(&session_of(&res)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(json(res).await["email"], other.as_str());
+
+    let reset = |user_id: i64| {
+        let req = Request::builder()
+            .method("DELETE")
+            .uri(format!("/admin/users/{user_id}/mfa"))
+            .header(header::AUTHORIZATION, "Bearer s3cret")
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move { app.oneshot(req).await.unwrap().status() }
+    };
+    assert_eq!(reset(user_id).await, StatusCode::NO_CONTENT);
+    assert_eq!(reset(user_id).await, StatusCode::NOT_FOUND);
+    let res = call("POST", "/api/v1/auth/login", None, credentials).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    db_pool.close().await;
+}
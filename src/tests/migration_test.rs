@@ -0,0 +1,32 @@
+use crate::migration;
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn revert_and_reapply() {
+    let pool = super::memory_pool().await;
+    let status = migration::status(&pool).await.unwrap();
+    assert!(status.iter().all(|m| m.applied && m.reversible));
+    let latest = status.last().unwrap().version;
+
+    assert_eq!(migration::revert(&pool, None).await.unwrap(), vec![latest]);
+    let status = migration::status(&pool).await.unwrap();
+    assert!(!status.last().unwrap().applied);
+    assert!(status.iter().rev().skip(1).all(|m| m.applied));
+
+    // down to nothing, every table of the migrations is gone
+    let reverted = migration::revert(&pool, Some(0)).await.unwrap();
+    assert_eq!(reverted.len(), status.len() - 1);
+    let (tables,): (i64,) = sqlx::query_as(
+        r#" select count(*) from sqlite_master where type = 'table'
+            and name not like '\_sqlx%' escape '\' and name != 'sqlite_sequence' "#,
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(tables, 0);
+    assert!(migration::revert(&pool, None).await.unwrap().is_empty());
+
+    migration::up(&pool).await.unwrap();
+    let status = migration::status(&pool).await.unwrap();
+    assert!(status.iter().all(|m| m.applied && !m.checksum_mismatch));
+}
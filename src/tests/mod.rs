@@ -1 +1,84 @@
-pub(crate) mod sample_usecase_test;
\ No newline at end of file
+pub(crate) mod admin_test;
+pub(crate) mod api_key_test;
+pub(crate) mod authz_test;
+pub(crate) mod config_test;
+pub(crate) mod cors_test;
+pub(crate) mod csrf_test;
+pub(crate) mod diagnostics_test;
+pub(crate) mod health_test;
+pub(crate) mod metrics_test;
+pub(crate) mod mfa_test;
+pub(crate) mod migration_test;
+pub(crate) mod oidc_test;
+pub(crate) mod page_test;
+pub(crate) mod request_id_test;
+pub(crate) mod routes_test;
+pub(crate) mod sample_usecase_test;
+pub(crate) mod session_store_test;
+pub(crate) mod session_test;
+pub(crate) mod shutdown_test;
+pub(crate) mod token_test;
+pub(crate) mod tracing_test;
+pub(crate) mod user_test;
+
+#[cfg(feature = "use_sqlite")]
+pub(crate) use self::fixture::{memory_pool, test_app};
+
+#[cfg(feature = "use_sqlite")]
+mod fixture {
+    use std::path::PathBuf;
+
+    use axum::Router;
+    use sqlx::{sqlite::SqlitePoolOptions, Pool};
+
+    use crate::{
+        app_state::{AppState, DataBase},
+        router,
+        session_impl::{MemoryStore, SessionStoreImpl},
+        util::config::TomlConfig,
+    };
+
+    /// a migrated in-memory database, one connection so every query sees the same one
+    pub(crate) async fn memory_pool() -> Pool<DataBase> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::migration::up(&pool).await.unwrap();
+        pool
+    }
+
+    /// sqlite file of a `test_app`, removed with its `-shm` and `-wal` files on drop
+    pub(crate) struct TempDb {
+        path: PathBuf,
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            for suffix in ["", "-shm", "-wal"] {
+                let _ = std::fs::remove_file(format!("{}{suffix}", self.path.display()));
+            }
+        }
+    }
+
+    /// the full router on `app_config.toml` after `configure`, with its own migrated
+    /// database file (the shared one is migrated by other tests at the same time) and
+    /// memory sessions. the pool is returned for seeding and checks, close it before
+    /// the `TempDb` goes out of scope
+    pub(crate) async fn test_app(
+        configure: impl FnOnce(&mut TomlConfig),
+    ) -> (Router, Pool<DataBase>, TempDb) {
+        let mut config = TomlConfig::load("app_config.toml", None).unwrap();
+        let db = TempDb {
+            path: std::env::temp_dir().join(format!("app_test_{}.db", uuid::Uuid::new_v4())),
+        };
+        config.database.url = format!("sqlite://{}", db.path.display());
+        configure(&mut config);
+        let mut app_state = AppState::new(&config).await.unwrap();
+        app_state.migrate_database().await.unwrap();
+        app_state.session_store = SessionStoreImpl::new(MemoryStore::new(), &config.session);
+        let db_pool = app_state.db_pool.clone();
+        (router::init_router(app_state, &config.http), db_pool, db)
+    }
+}
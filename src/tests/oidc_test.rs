@@ -0,0 +1,367 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    response::{IntoResponse, Redirect},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hyper::{header, Request, StatusCode};
+use jsonwebtoken::{EncodingKey, Header};
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+
+use crate::{
+    app_state::AppState,
+    define, diagnostics,
+    oidc_impl::{Identity, OidcProviders},
+    repository::UserRepositoryDB,
+    usecase::BasicUserUsecase,
+    util::config::{OidcConfig, OidcProviderConfig, TomlConfig},
+};
+
+const CLIENT_ID: &str = "app";
+const CODE: &str = "code-1";
+
+// what the mock provider remembers from the authorization request
+#[derive(Clone, Default)]
+struct MockProvider {
+    issuer: String,
+    email: String,
+    challenge: Arc<Mutex<Option<(String, String)>>>,
+}
+
+async fn discovery(State(mock): State<MockProvider>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "issuer": mock.issuer,
+        "authorization_endpoint": format!("{}/authorize", mock.issuer),
+        "token_endpoint": format!("{}/token", mock.issuer),
+        "jwks_uri": format!("{}/jwks", mock.issuer),
+    }))
+}
+
+async fn authorize(
+    State(mock): State<MockProvider>,
+    Query(query): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    assert_eq!(query["client_id"], CLIENT_ID);
+    assert_eq!(query["code_challenge_method"], "S256");
+    *mock.challenge.lock().unwrap() =
+        Some((query["code_challenge"].clone(), query["nonce"].clone()));
+    Redirect::to(&format!(
+        "{}?code={CODE}&state={}",
+        query["redirect_uri"], query["state"]
+    ))
+}
+
+async fn token(
+    State(mock): State<MockProvider>,
+    Form(form): Form<HashMap<String, String>>,
+) -> axum::response::Response {
+    let (challenge, nonce) = mock.challenge.lock().unwrap().clone().unwrap();
+    let verified = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+    if form["code"] != CODE || verified != challenge {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "invalid_grant" })),
+        )
+            .into_response();
+    }
+    let now = jsonwebtoken::get_current_timestamp();
+    let claims = serde_json::json!({
+        "iss": mock.issuer,
+        "aud": CLIENT_ID,
+        "sub": "mock-1",
+        "email": mock.email,
+        "email_verified": true,
+        "name": "Alice",
+        "nonce": nonce,
+        "iat": now,
+        "exp": now + 60,
+    });
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = Some("test".to_owned());
+    let key = EncodingKey::from_rsa_pem(include_bytes!("keys/rsa_private.pem")).unwrap();
+    let id_token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+    Json(serde_json::json!({ "access_token": "at", "token_type": "Bearer", "id_token": id_token }))
+        .into_response()
+}
+
+async fn jwks() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        include_str!("keys/rsa_jwks.json"),
+    )
+}
+
+// serves the mock provider on a free local port and returns its issuer url
+fn start_provider(email: &str) -> MockProvider {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mock = MockProvider {
+        issuer: format!("http://{}", listener.local_addr().unwrap()),
+        email: email.to_owned(),
+        ..MockProvider::default()
+    };
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/authorize", get(authorize))
+        .route("/token", post(token))
+        .route("/jwks", get(jwks))
+        .with_state(mock.clone());
+    let server = axum::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+    mock
+}
+
+fn session_cookie(res: &hyper::Response<axum::body::BoxBody>) -> String {
+    res.headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .find(|v| v.starts_with(define::SESSION_COOKIE))
+        .map(|v| v.split(';').next().unwrap().to_owned())
+        .unwrap()
+}
+
+fn location(res: &hyper::Response<axum::body::BoxBody>) -> String {
+    res.headers()[header::LOCATION].to_str().unwrap().to_owned()
+}
+
+fn identity(sub: &str, email: &str, email_verified: bool) -> Identity {
+    Identity {
+        sub: sub.to_owned(),
+        email: Some(email.to_owned()),
+        email_verified: Some(email_verified),
+        name: None,
+    }
+}
+
+// starts a login and takes the browser through the provider, returns the session cookie
+// with the pending login and the callback the provider sent back
+async fn begin_login(app: &Router, mock: &MockProvider) -> (String, String) {
+    let req = Request::builder()
+        .uri("/api/v1/auth/oidc/mock/login")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    let pre_login = session_cookie(&res);
+    let authorize_url = location(&res);
+    assert!(authorize_url.starts_with(&format!("{}/authorize?", mock.issuer)));
+
+    let http = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let res = http.get(&authorize_url).send().await.unwrap();
+    let callback = res.headers()[header::LOCATION].to_str().unwrap();
+    let callback = callback
+        .strip_prefix("http://localhost")
+        .unwrap()
+        .to_owned();
+    (pre_login, callback)
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn login_external() {
+    let pool = super::memory_pool().await;
+    let usecase = BasicUserUsecase::new(UserRepositoryDB::new(pool));
+
+    let created = usecase
+        .login_external("mock", identity("1", "Bob@Example.com", false))
+        .await
+        .unwrap();
+    assert_eq!(created.email, "bob@example.com");
+    assert_eq!(created.name, "bob");
+    assert!(usecase.grants(created.id).await.unwrap().has("sample:read"));
+    // no password to log in with
+    assert!(matches!(
+        usecase
+            .login(crate::dto::UserLogin {
+                email: "bob@example.com".to_owned(),
+                password: String::new(),
+                mfa_code: None,
+            })
+            .await,
+        Err(diagnostics::Error::Unauthorized)
+    ));
+
+    // the same account again, whatever its email says now
+    let again = usecase
+        .login_external("mock", identity("1", "other@example.com", false))
+        .await
+        .unwrap();
+    assert_eq!(again.id, created.id);
+
+    // another account with the email links only when the provider verified it
+    assert!(matches!(
+        usecase
+            .login_external("other", identity("2", "bob@example.com", false))
+            .await,
+        Err(diagnostics::Error::Conflict(_))
+    ));
+    let linked = usecase
+        .login_external("other", identity("2", "bob@example.com", true))
+        .await
+        .unwrap();
+    assert_eq!(linked.id, created.id);
+
+    let mut no_email = identity("3", "", true);
+    no_email.email = None;
+    assert!(matches!(
+        usecase.login_external("mock", no_email).await,
+        Err(diagnostics::Error::Message(_))
+    ));
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn authorization_code_flow() {
+    let email = format!("{}@example.com", uuid::Uuid::new_v4().simple());
+    let mock = start_provider(&email);
+
+    let (app, db_pool, _db) = super::test_app(|config| {
+        config.oidc = OidcConfig {
+            post_login_redirect: "/welcome".to_owned(),
+            ..OidcConfig::default()
+        };
+        config.oidc.providers.insert(
+            "mock".to_owned(),
+            OidcProviderConfig {
+                discovery_url: format!("{}/.well-known/openid-configuration", mock.issuer),
+                client_id: CLIENT_ID.to_owned(),
+                client_secret: "secret".to_owned(),
+                redirect_url: "http://localhost/api/v1/auth/oidc/mock/callback".to_owned(),
+                scopes: vec!["openid".to_owned(), "email".to_owned()],
+            },
+        );
+    })
+    .await;
+
+    let get = |uri: &str, cookie: Option<&str>| {
+        let mut req = Request::builder().uri(uri);
+        if let Some(cookie) = cookie {
+            req = req.header(header::COOKIE, cookie);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        let app = app.clone();
+        async move { app.oneshot(req).await.unwrap() }
+    };
+
+    assert_eq!(
+        get("/api/v1/auth/oidc/nope/login", None).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // a forged state uses up the pending login, the real callback can not follow it
+    let (pre_login, callback) = begin_login(&app, &mock).await;
+    let forged = format!("/api/v1/auth/oidc/mock/callback?code={CODE}&state=forged");
+    assert_eq!(
+        get(&forged, Some(&pre_login)).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        get(&callback, Some(&pre_login)).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        get(&callback, None).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    let (pre_login, callback) = begin_login(&app, &mock).await;
+    let res = get(&callback, Some(&pre_login)).await;
+    assert_eq!(res.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&res), "/welcome");
+    let cookie = session_cookie(&res);
+    assert_ne!(cookie, pre_login);
+
+    let res = get("/api/v1/auth/me", Some(&cookie)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let user: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(user["email"], email.as_str());
+    assert_eq!(user["name"], "Alice");
+
+    // the code and the pending login are used up
+    assert_eq!(
+        get(&callback, Some(&cookie)).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        get(&callback, Some(&pre_login)).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    db_pool.close().await;
+}
+
+#[test]
+fn validate_config() {
+    let provider = || OidcProviderConfig {
+        discovery_url: "https://accounts.example.com/.well-known/openid-configuration".to_owned(),
+        client_id: CLIENT_ID.to_owned(),
+        client_secret: String::new(),
+        redirect_url: "https://app.example.com/api/v1/auth/oidc/example/callback".to_owned(),
+        scopes: vec!["openid".to_owned()],
+    };
+    let config = |provider: OidcProviderConfig| {
+        let mut config = OidcConfig::default();
+        config.providers.insert("example".to_owned(), provider);
+        config
+    };
+    assert!(config(provider()).validate().is_ok());
+
+    let mut invalid = vec![
+        config(OidcProviderConfig {
+            discovery_url: "accounts.example.com".to_owned(),
+            ..provider()
+        }),
+        config(OidcProviderConfig {
+            client_id: String::new(),
+            ..provider()
+        }),
+        config(OidcProviderConfig {
+            scopes: vec!["email".to_owned()],
+            ..provider()
+        }),
+    ];
+    for redirect in ["https://evil.example.com", "//evil.example.com"] {
+        invalid.push(OidcConfig {
+            post_login_redirect: redirect.to_owned(),
+            ..OidcConfig::default()
+        });
+    }
+    for config in invalid {
+        assert!(config.validate().is_err());
+        assert!(OidcProviders::new(&config).is_err());
+    }
+}
+
+#[tokio::test]
+async fn invalid_providers_fail_the_app_state() {
+    let mut config = TomlConfig::load("app_config.toml", None).unwrap();
+    config.oidc.providers.insert(
+        "broken".to_owned(),
+        OidcProviderConfig {
+            discovery_url: "accounts.example.com".to_owned(),
+            client_id: CLIENT_ID.to_owned(),
+            client_secret: String::new(),
+            redirect_url: "https://app.example.com/api/v1/auth/oidc/broken/callback".to_owned(),
+            scopes: vec!["openid".to_owned()],
+        },
+    );
+    let error = AppState::new(&config).await.err().unwrap();
+    assert!(
+        error.to_string().contains("oidc.providers.broken"),
+        "{error}"
+    );
+}
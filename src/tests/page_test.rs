@@ -0,0 +1,224 @@
+use axum::{body::Body, extract::Query};
+use hyper::{header, Request, StatusCode, Uri};
+use tower::ServiceExt;
+
+use crate::{
+    define, diagnostics,
+    entity::Sample,
+    repository::{
+        page::{Filter, FilterOp, Sort, Value},
+        BasicRepository, PageRequest, SampleRepositoryDB,
+    },
+};
+
+fn query(s: &str) -> Vec<(String, String)> {
+    let uri: Uri = format!("/?{s}").parse().unwrap();
+    Query::<Vec<(String, String)>>::try_from_uri(&uri)
+        .unwrap()
+        .0
+}
+
+fn parse(s: &str) -> diagnostics::Result<PageRequest> {
+    PageRequest::from_query::<Sample>(&query(s))
+}
+
+#[test]
+fn from_query() {
+    assert_eq!(parse("").unwrap(), PageRequest::default());
+
+    let request =
+        parse("limit=5&offset=10&sort=-name&name[like]=a%25&id[in]=1,2&id[gte]=3&id[lte]=9&name=x")
+            .unwrap();
+    assert_eq!(request.limit, 5);
+    assert_eq!(request.offset, 10);
+    assert_eq!(
+        request.sort,
+        Sort {
+            field: "name",
+            descending: true
+        }
+    );
+    assert_eq!(
+        request.filters,
+        vec![
+            Filter {
+                field: "name",
+                op: FilterOp::Like("a%".to_owned())
+            },
+            Filter {
+                field: "id",
+                op: FilterOp::In(vec![Value::Integer(1), Value::Integer(2)])
+            },
+            Filter {
+                field: "id",
+                op: FilterOp::Range(Some(Value::Integer(3)), Some(Value::Integer(9)))
+            },
+            Filter {
+                field: "name",
+                op: FilterOp::Eq(Value::Text("x".to_owned()))
+            },
+        ]
+    );
+
+    for invalid in [
+        "limit=0",
+        "limit=101",
+        "limit=x",
+        "offset=-1",
+        "sort=password_hash",
+        "password_hash=x",
+        "name[regex]=x",
+        "name[eq=x",
+        "id[like]=1%",
+        "id=one",
+        "id[in]=1,x",
+        "cursor=nope",
+    ] {
+        assert!(
+            matches!(parse(invalid), Err(diagnostics::Error::Message(_))),
+            "{invalid}"
+        );
+    }
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn find_page() {
+    let pool = super::memory_pool().await;
+    let repo = SampleRepositoryDB::new(pool);
+    // names repeat so the id has to break ties
+    for i in 0..25 {
+        repo.create(Sample::with_name(format!("name{}", i % 7)))
+            .await
+            .unwrap();
+    }
+
+    // walk every page by cursor, names in descending order
+    let mut seen = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut q = "limit=10&sort=-name".to_owned();
+        if let Some(cursor) = &cursor {
+            q.push_str(&format!("&cursor={cursor}"));
+        }
+        let page = repo.find_page(parse(&q).unwrap()).await.unwrap();
+        assert_eq!(page.total, 25);
+        assert!(page.items.len() <= 10);
+        seen.extend(page.items.into_iter().map(|s| (s.name, s.id)));
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    let mut expected = seen.clone();
+    expected.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    assert_eq!(seen.len(), 25);
+    assert_eq!(seen, expected);
+
+    // a cursor only fits the sort it came from
+    let page = repo
+        .find_page(parse("limit=1&sort=name").unwrap())
+        .await
+        .unwrap();
+    let cursor = page.next_cursor.unwrap();
+    assert!(parse(&format!("cursor={cursor}")).is_err());
+    assert!(parse(&format!("sort=name&offset=1&cursor={cursor}")).is_err());
+    assert!(parse(&format!("sort=name&cursor={cursor}")).is_ok());
+
+    let page = repo
+        .find_page(parse("limit=3&offset=3").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        page.items.iter().map(|s| s.id).collect::<Vec<_>>(),
+        vec![4, 5, 6]
+    );
+    assert!(page.next_cursor.is_some());
+
+    let ids = |q: &str| {
+        let repo = &repo;
+        let request = parse(q).unwrap();
+        async move {
+            let page = repo.find_page(request).await.unwrap();
+            assert_eq!(page.total, page.items.len() as i64);
+            page.items.into_iter().map(|s| s.id).collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(ids("name=name3").await, vec![4, 11, 18, 25]);
+    assert_eq!(ids("name[like]=name3%25&id[gte]=5").await, vec![11, 18, 25]);
+    assert_eq!(ids("id[in]=2,4,99").await, vec![2, 4]);
+    assert_eq!(
+        ids("id[gte]=20&id[lte]=22&sort=-id").await,
+        vec![22, 21, 20]
+    );
+    assert_eq!(ids("id[lte]=2").await, vec![1, 2]);
+    assert_eq!(ids("name=nobody").await, Vec::<i64>::new());
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn sample_endpoint() {
+    let (app, db_pool, _db) = super::test_app(|config| {
+        config.http.admin_port = None;
+        config.http.admin_token = Some("s3cret".to_owned());
+    })
+    .await;
+    let repo = SampleRepositoryDB::new(db_pool.clone());
+    for name in ["a", "b", "c"] {
+        repo.create(Sample::with_name(name.to_owned()))
+            .await
+            .unwrap();
+    }
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/admin/api-keys")
+        .header(header::AUTHORIZATION, "Bearer s3cret")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"name": "reader", "scopes": ["sample:read"]}"#,
+        ))
+        .unwrap();
+    let res = app.clone().oneshot(req).await.unwrap();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let key = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["key"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    let get = |uri: &str| {
+        let req = Request::builder()
+            .uri(uri)
+            .header(define::API_KEY_HEADER, &key)
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move { app.oneshot(req).await.unwrap() }
+    };
+    let res = get("/api/v1/sample?limit=2&sort=-name").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page["total"], 3);
+    assert_eq!(page["items"][0]["name"], "c");
+    assert_eq!(page["items"][1]["name"], "b");
+    let cursor = page["next_cursor"].as_str().unwrap();
+
+    let res = get(&format!(
+        "/api/v1/sample?limit=2&sort=-name&cursor={cursor}"
+    ))
+    .await;
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page["items"][0]["name"], "a");
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    assert!(page["next_cursor"].is_null());
+
+    let res = get("/api/v1/sample?secret=1").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["detail"], "unknown field `secret`");
+
+    db_pool.close().await;
+}
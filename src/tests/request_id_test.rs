@@ -0,0 +1,105 @@
+use std::convert::Infallible;
+
+use axum::{body::Body, routing::get, Extension, Router};
+use hyper::{header, Request, Response};
+use tower::{Layer, ServiceExt};
+
+use crate::{
+    define,
+    diagnostics::{Error, Problem},
+    proto::MultiplexService,
+    util::{
+        middleware,
+        request_id::{RequestId, RequestIdLayer},
+    },
+};
+
+fn rest() -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(|Extension(id): Extension<RequestId>| async move { id.0 }),
+        )
+        .route("/error", get(|| async { Err::<(), _>(Error::NotFound) }))
+        .layer(axum::middleware::from_fn_with_state(
+            false,
+            middleware::problem_details,
+        ))
+}
+
+fn request(uri: &str, id: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder().uri(uri);
+    if let Some(id) = id {
+        builder = builder.header(define::REQUEST_ID_HEADER, id);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+async fn body_string(res: Response<axum::body::BoxBody>) -> String {
+    let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn incoming_id_is_kept() {
+    let res = RequestIdLayer
+        .layer(rest())
+        .oneshot(request("/", Some("abc-123")))
+        .await
+        .unwrap();
+    assert_eq!(res.headers()[define::REQUEST_ID_HEADER], "abc-123");
+    assert_eq!(body_string(res).await, "abc-123");
+}
+
+#[tokio::test]
+async fn missing_or_invalid_id_is_generated() {
+    for id in [None, Some("not valid"), Some(&*"x".repeat(200))] {
+        let res = RequestIdLayer
+            .layer(rest())
+            .oneshot(request("/", id))
+            .await
+            .unwrap();
+        let echoed = res.headers()[define::REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert!(uuid::Uuid::parse_str(&echoed).is_ok(), "{echoed}");
+        assert_eq!(body_string(res).await, echoed);
+    }
+}
+
+#[tokio::test]
+async fn error_body_carries_id() {
+    let res = RequestIdLayer
+        .layer(rest())
+        .oneshot(request("/error", Some("abc-123")))
+        .await
+        .unwrap();
+    let problem: Problem = serde_json::from_str(&body_string(res).await).unwrap();
+    assert_eq!(problem.request_id.as_deref(), Some("abc-123"));
+}
+
+#[tokio::test]
+async fn grpc_side_of_multiplex() {
+    let grpc = tower::service_fn(|req: Request<Body>| async move {
+        let id = req.extensions().get::<RequestId>().unwrap().0.clone();
+        Ok::<_, Infallible>(
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/grpc")
+                .header("grpc-status", "0")
+                .header("seen-id", id)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    });
+    let service = RequestIdLayer.layer(MultiplexService::new(rest(), grpc));
+    let req = Request::builder()
+        .uri("/voting.Voting/Vote")
+        .header(header::CONTENT_TYPE, "application/grpc")
+        .header(define::REQUEST_ID_HEADER, "grpc-1")
+        .body(Body::empty())
+        .unwrap();
+    let res = service.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["seen-id"], "grpc-1");
+    assert_eq!(res.headers()[define::REQUEST_ID_HEADER], "grpc-1");
+}
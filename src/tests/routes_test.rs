@@ -0,0 +1,19 @@
+use hyper::Method;
+
+use crate::{proto, router};
+
+#[test]
+fn rest_routes() {
+    let routes = router::routes();
+    let sample = routes
+        .iter()
+        .find(|r| r.path == "/api/v1/sample")
+        .expect("sample route registered");
+    assert_eq!(sample.methods, vec![Method::GET, Method::POST]);
+}
+
+#[test]
+fn grpc_routes() {
+    let routes = proto::grpc_routes().unwrap();
+    assert!(routes.contains(&"/voting.Voting/Vote".to_owned()));
+}
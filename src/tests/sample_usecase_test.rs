@@ -7,7 +7,7 @@ use crate::{
     app_state::AppState,
     diagnostics,
     entity::{Sample, Entity},
-    repository::{BasicRepository, SampleRepository, SampleRepositoryDB},
+    repository::{BasicRepository, Page, PageRequest, SampleRepository, SampleRepositoryDB},
     usecase::BasicSampleUsecase,
     util,
 };
@@ -29,7 +29,7 @@ impl BasicRepository<Sample> for SampleRepositoryMap {
     async fn create(&self, entity: Sample) -> diagnostics::Result<Sample> {
         let mut g = self.map.write().await;
         let map = g.borrow_mut();
-        let id = if map.len() == 0 {
+        let id = if map.is_empty() {
             0
         } else {
             map.keys().max().unwrap() + 1
@@ -46,10 +46,14 @@ impl BasicRepository<Sample> for SampleRepositoryMap {
             .collect::<Vec<Sample>>())
     }
 
+    async fn find_page(&self, _request: PageRequest) -> diagnostics::Result<Page<Sample>> {
+        Err(diagnostics::Error::NotImplemented)
+    }
+
     async fn find_by_id(&self, id: &'_ i64) -> diagnostics::Result<Sample> {
         let map = self.map.read().await;
         if let Some(name) = map.get(id) {
-            Ok(Sample::new(id.clone(), name.clone()))
+            Ok(Sample::new(*id, name.clone()))
         } else {
             Err(diagnostics::Error::RowNotFound)
         }
@@ -83,13 +87,7 @@ impl BasicRepository<Sample> for SampleRepositoryMap {
     {
         let map = self.map.read().await;
         let samples = ids
-            .filter_map(|id| {
-                if let Some(name) = map.get(&id) {
-                    Some(Sample::new(id.clone(), name.clone()))
-                } else {
-                    None
-                }
-            })
+            .filter_map(|id| map.get(id).map(|name| Sample::new(*id, name.clone())))
             .collect::<Vec<Sample>>();
         Ok(samples)
     }
@@ -144,9 +142,9 @@ impl FromRef<AppState> for SampleRepositoryMap {
 
 #[tokio::test]
 async fn sample_usecase() {
-    let config = util::config::TomlConfig::from_file("config.toml").unwrap();
+    let config = util::config::TomlConfig::load("app_config.toml", None).unwrap();
     let _guard = util::tracing::init(&config.tracing).unwrap();
-    let app_state = AppState::new(&config).await;
+    let app_state = AppState::new(&config).await.unwrap();
     if config.database.with_migrations {
         let _ = app_state.migrate_database().await;
     }
@@ -159,11 +157,11 @@ async fn sample_usecase() {
     let s1 = sample_usecase_impl
         .create(Sample::with_name("s1".into()))
         .await;
-    let v = vec![0, 1,2,3];
+    let v = [0, 1, 2, 3];
     let samples = sample_usecase_impl.sample_repository.find_all_by_id(v.iter()).await;
 
     println!("{:?} {:?} {:?}", s, s1, samples);
-    let _ = sample_usecase_impl.sample_repository.delete_all_by_id(vec![0].iter()).await;
+    let _ = sample_usecase_impl.sample_repository.delete_all_by_id([0].iter()).await;
     let samples = sample_usecase_impl.find_all().await;
     println!("samples {:?}", samples);
 }
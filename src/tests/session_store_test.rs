@@ -0,0 +1,192 @@
+use async_session::{
+    chrono::{Duration as ChronoDuration, Utc},
+    Session, SessionStore,
+};
+use axum::{
+    body::Body,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use hyper::{header, Request, StatusCode};
+use tower::ServiceExt;
+
+use crate::{
+    depends::Depends,
+    session_impl::{
+        self, ClientInfo, MemoryStore, RotateSession, SessionCookies, SessionService,
+        SessionStoreImpl,
+    },
+    util::{
+        config::{SessionConfig, SessionCookieConfig},
+        middleware,
+    },
+};
+
+// `name=value` of the Set-Cookie header, as the browser sends it back
+fn sealed_cookie(res: Response) -> String {
+    let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().to_owned()
+}
+
+// one suite for every backend, they only differ in how records are kept
+async fn conformance(store: SessionStoreImpl) {
+    store.clear_store().await.unwrap();
+
+    // round trip
+    let mut session = Session::new();
+    session.insert("answer", 42).unwrap();
+    let cookie = store.store_session(session).await.unwrap().unwrap();
+    let loaded = store.load_session(cookie.clone()).await.unwrap().unwrap();
+    assert_eq!(loaded.get::<i32>("answer"), Some(42));
+    assert!(loaded.expiry().is_some());
+
+    // unknown id
+    let stranger = Session::new().into_cookie_value().unwrap();
+    assert!(store.load_session(stranger).await.unwrap().is_none());
+
+    // destroyed sessions stay gone, loading a stale copy does not bring them back
+    store.destroy_session(loaded).await.unwrap();
+    assert!(store.load_session(cookie).await.unwrap().is_none());
+
+    // past the absolute lifetime nothing is stored
+    let mut session = Session::new();
+    let created_at = Utc::now() - ChronoDuration::days(2);
+    session
+        .insert("__created_at", created_at.timestamp())
+        .unwrap();
+    assert!(store.store_session(session).await.unwrap().is_none());
+
+    // per user index
+    let client = ClientInfo::default();
+    let mut cookies = Vec::new();
+    for _ in 0..3 {
+        let mut session = Session::new();
+        session_impl::bind_user(&mut session, 7, &client).unwrap();
+        cookies.push(store.store_session(session).await.unwrap().unwrap());
+    }
+    let mut other = Session::new();
+    session_impl::bind_user(&mut other, 8, &client).unwrap();
+    let other_id = other.id().to_owned();
+    store.store_session(other).await.unwrap();
+
+    let sessions = store.user_sessions(7).await.unwrap();
+    assert_eq!(sessions.len(), 3);
+    assert!(!store.revoke_session(7, &other_id).await.unwrap());
+    assert!(store.revoke_session(7, &sessions[0].id).await.unwrap());
+    assert!(!store.revoke_session(7, &sessions[0].id).await.unwrap());
+    assert_eq!(store.user_sessions(7).await.unwrap().len(), 2);
+    assert_eq!(store.revoke_user_sessions(7).await.unwrap(), 2);
+    assert!(store.user_sessions(7).await.unwrap().is_empty());
+    for cookie in cookies {
+        assert!(store.load_session(cookie).await.unwrap().is_none());
+    }
+
+    // rotation keeps the data under a new id, the old id is dead
+    let mut session = Session::new();
+    session_impl::bind_user(&mut session, 9, &client).unwrap();
+    session.insert("answer", 42).unwrap();
+    let cookie = store.store_session(session).await.unwrap().unwrap();
+    let loaded = store.load_session(cookie.clone()).await.unwrap().unwrap();
+    let (rotated, rotated_cookie) = store.rotate(loaded.clone()).await.unwrap().unwrap();
+    assert_ne!(rotated.id(), loaded.id());
+    assert!(store.load_session(cookie).await.unwrap().is_none());
+    let reloaded = store.load_session(rotated_cookie).await.unwrap().unwrap();
+    assert_eq!(reloaded.get::<i32>("answer"), Some(42));
+    let listed = store.user_sessions(9).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, rotated.id());
+    // a stale copy cannot be rotated back to life
+    assert!(store.rotate(loaded).await.unwrap().is_none());
+    store.revoke_user_sessions(9).await.unwrap();
+
+    // extractors and cookies on top of the store
+    let sessions = SessionService::new(
+        store.clone(),
+        SessionCookies::new(&SessionCookieConfig::default()),
+    );
+    let mut session = Session::new();
+    session.insert("answer", 42).unwrap();
+    let jar = sessions.create(session).await.unwrap();
+    let app = Router::new()
+        .route(
+            "/",
+            get(|Depends(session): Depends<Session>| async move {
+                session.get::<i32>("answer").unwrap_or_default().to_string()
+            }),
+        )
+        .route(
+            "/rotate",
+            get(|Depends(session): Depends<Session>| async move { RotateSession(session) }),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            sessions.clone(),
+            middleware::rotate_session,
+        ))
+        .with_state(sessions);
+    let req = |uri: &str, cookie: Option<&str>| {
+        let mut req = Request::builder().uri(uri);
+        if let Some(cookie) = cookie {
+            req = req.header(header::COOKIE, cookie);
+        }
+        req.body(Body::empty()).unwrap()
+    };
+    let sealed = sealed_cookie(jar.into_response());
+    let res = app.clone().oneshot(req("/", Some(&sealed))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app.clone().oneshot(req("/", None)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .clone()
+        .oneshot(req("/rotate", Some(&sealed)))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let rotated = sealed_cookie(res);
+    assert_ne!(rotated, sealed);
+    let res = app.clone().oneshot(req("/", Some(&rotated))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app.oneshot(req("/", Some(&sealed))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    store.clear_store().await.unwrap();
+    assert!(store.user_sessions(8).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn memory_backend() {
+    conformance(SessionStoreImpl::new(
+        MemoryStore::new(),
+        &SessionConfig::default(),
+    ))
+    .await;
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn sql_backend() {
+    let pool = super::memory_pool().await;
+    conformance(SessionStoreImpl::new(
+        session_impl::SqlStore::new(pool),
+        &SessionConfig::default(),
+    ))
+    .await;
+}
+
+// needs a running redis, `cargo test -- --ignored` with REDIS_URL set
+#[tokio::test]
+#[ignore]
+async fn redis_backend() {
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1".to_owned());
+    let pool = bb8::Pool::builder()
+        .build(bb8_redis::RedisConnectionManager::new(url).unwrap())
+        .await
+        .unwrap();
+    let config = SessionConfig::default();
+    conformance(SessionStoreImpl::new(
+        session_impl::RedisStore::new(pool, config.absolute_ttl()),
+        &config,
+    ))
+    .await;
+}
@@ -0,0 +1,235 @@
+use std::{net::SocketAddr, time::Duration};
+
+use async_session::{
+    chrono::{Duration as ChronoDuration, Utc},
+    Session,
+};
+use axum::{body::Body, extract::ConnectInfo, response::IntoResponse, routing::get, Json, Router};
+use hyper::{header, HeaderMap, Request};
+use tower::ServiceExt;
+use tower_http::add_extension::AddExtensionLayer;
+
+use crate::{
+    app_state::TrustedProxies,
+    depends::Depends,
+    session_impl::{self, session_expiry, ClientInfo, SessionCookies, SessionInfo},
+    util::config::{CookieMode, CookieSameSite, SessionConfig, SessionCookieConfig},
+};
+
+const IDLE: Duration = Duration::from_secs(30 * 60);
+const ABSOLUTE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[test]
+fn idle_expiry_slides() {
+    let created_at = Utc::now() - ChronoDuration::hours(1);
+    let now = Utc::now();
+    assert_eq!(
+        session_expiry(created_at, now, IDLE, ABSOLUTE),
+        Some(now + ChronoDuration::minutes(30))
+    );
+}
+
+#[test]
+fn absolute_lifetime_caps_expiry() {
+    let now = Utc::now();
+    let created_at = now - ChronoDuration::hours(24) + ChronoDuration::minutes(10);
+    assert_eq!(
+        session_expiry(created_at, now, IDLE, ABSOLUTE),
+        Some(created_at + ChronoDuration::hours(24))
+    );
+
+    let created_at = now - ChronoDuration::hours(25);
+    assert_eq!(session_expiry(created_at, now, IDLE, ABSOLUTE), None);
+}
+
+#[test]
+fn config_validation() {
+    assert!(SessionConfig::default().validate().is_ok());
+    let config = SessionConfig {
+        idle_ttl_secs: 3600,
+        absolute_ttl_secs: 60,
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn bound_session_info() {
+    let mut session = Session::new();
+    let client = ClientInfo {
+        user_agent: Some("curl/8".to_owned()),
+        ip: Some("10.0.0.1".to_owned()),
+    };
+    assert_eq!(session_impl::session_user_id(&session), None);
+    session_impl::bind_user(&mut session, 42, &client).unwrap();
+    assert_eq!(session_impl::session_user_id(&session), Some(42));
+
+    let info = SessionInfo::from_session(&session);
+    assert_eq!(info.id, session.id());
+    assert_eq!(info.user_agent.as_deref(), Some("curl/8"));
+    assert_eq!(info.ip.as_deref(), Some("10.0.0.1"));
+}
+
+// the ip the extractor sees behind a connection from `peer`
+async fn client_info(trusted: &[&str], peer: &str, headers: &[(&str, &str)]) -> Option<String> {
+    let trusted = trusted
+        .iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect::<Vec<_>>();
+    let app = Router::new()
+        .route(
+            "/",
+            get(|Depends(client): Depends<ClientInfo>| async move {
+                Json((client.user_agent, client.ip))
+            }),
+        )
+        .layer(AddExtensionLayer::new(ConnectInfo(
+            format!("{peer}:40000").parse::<SocketAddr>().unwrap(),
+        )))
+        .with_state(TrustedProxies(trusted.into()));
+    let mut req = Request::builder()
+        .uri("/")
+        .header("user-agent", "test-agent");
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let res = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+    let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let (user_agent, ip): (Option<String>, Option<String>) =
+        serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(user_agent.as_deref(), Some("test-agent"));
+    ip
+}
+
+#[tokio::test]
+async fn client_info_trusts_only_configured_proxies() {
+    let forwarded = [
+        ("x-forwarded-for", "198.51.100.9, 203.0.113.7, 10.0.0.1"),
+        ("x-real-ip", "10.0.0.2"),
+    ];
+    // anyone else can put anything into the headers
+    assert_eq!(
+        client_info(&[], "192.0.2.1", &forwarded).await.as_deref(),
+        Some("192.0.2.1")
+    );
+    // the last hop before the trusted proxies, earlier entries came from the client
+    assert_eq!(
+        client_info(&["192.0.2.1", "10.0.0.1"], "192.0.2.1", &forwarded)
+            .await
+            .as_deref(),
+        Some("203.0.113.7")
+    );
+    assert_eq!(
+        client_info(&["192.0.2.1"], "192.0.2.1", &[("x-real-ip", "10.0.0.2")])
+            .await
+            .as_deref(),
+        Some("10.0.0.2")
+    );
+    assert_eq!(
+        client_info(&["192.0.2.1"], "192.0.2.1", &[])
+            .await
+            .as_deref(),
+        Some("192.0.2.1")
+    );
+}
+
+const OLD_KEY: &str = "0123456789abcdef0123456789abcdef-old";
+const NEW_KEY: &str = "0123456789abcdef0123456789abcdef-new";
+
+fn cookie_config(mode: CookieMode, keys: &[&str]) -> SessionCookieConfig {
+    SessionCookieConfig {
+        mode,
+        keys: keys.iter().map(|key| key.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+// Set-Cookie of `issue`, and the Cookie header a browser would send back
+fn issued(cookies: &SessionCookies, value: &str) -> (String, HeaderMap) {
+    let res = cookies
+        .issue(value.to_owned(), Duration::from_secs(60))
+        .into_response();
+    let set_cookie = res.headers()[header::SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let mut headers = HeaderMap::new();
+    let pair = set_cookie.split(';').next().unwrap();
+    headers.insert(header::COOKIE, pair.parse().unwrap());
+    (set_cookie, headers)
+}
+
+#[test]
+fn cookie_keys_rotate() {
+    for mode in [CookieMode::Private, CookieMode::Signed] {
+        let old = SessionCookies::new(&cookie_config(mode, &[OLD_KEY]));
+        let rotated = SessionCookies::new(&cookie_config(mode, &[NEW_KEY, OLD_KEY]));
+        let retired = SessionCookies::new(&cookie_config(mode, &[NEW_KEY]));
+
+        let (_, headers) = issued(&old, "session-value");
+        assert_eq!(rotated.read(&headers).as_deref(), Some("session-value"));
+        assert_eq!(retired.read(&headers), None);
+
+        // issued with the newest key
+        let (_, headers) = issued(&rotated, "session-value");
+        assert_eq!(retired.read(&headers).as_deref(), Some("session-value"));
+    }
+}
+
+#[test]
+fn cookie_is_sealed() {
+    let private = SessionCookies::new(&cookie_config(CookieMode::Private, &[NEW_KEY]));
+    let (set_cookie, _) = issued(&private, "session-value");
+    assert!(!set_cookie.contains("session-value"));
+
+    let signed = SessionCookies::new(&cookie_config(CookieMode::Signed, &[NEW_KEY]));
+    let (set_cookie, _) = issued(&signed, "session-value");
+    let tampered = set_cookie
+        .split(';')
+        .next()
+        .unwrap()
+        .replace("session-value", "session-other");
+    let mut headers = HeaderMap::new();
+    headers.insert(header::COOKIE, tampered.parse().unwrap());
+    assert_eq!(signed.read(&headers), None);
+}
+
+#[test]
+fn cookie_attributes_from_config() {
+    let config = SessionCookieConfig {
+        same_site: CookieSameSite::Strict,
+        domain: Some("example.com".to_owned()),
+        path: "/api".to_owned(),
+        ..cookie_config(CookieMode::Private, &[NEW_KEY])
+    };
+    let (set_cookie, _) = issued(&SessionCookies::new(&config), "session-value");
+    for attribute in [
+        "HttpOnly",
+        "Secure",
+        "SameSite=Strict",
+        "Path=/api",
+        "Domain=example.com",
+        "Max-Age=60",
+    ] {
+        assert!(
+            set_cookie.contains(attribute),
+            "{attribute} in {set_cookie}"
+        );
+    }
+}
+
+#[test]
+fn cookie_config_validation() {
+    assert!(cookie_config(CookieMode::Private, &[NEW_KEY])
+        .validate()
+        .is_ok());
+    assert!(cookie_config(CookieMode::Private, &["short"])
+        .validate()
+        .is_err());
+    let config = SessionCookieConfig {
+        same_site: CookieSameSite::None,
+        secure: false,
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}
@@ -0,0 +1,63 @@
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{routing::get, Router};
+
+use crate::util::shutdown::{self, Shutdown};
+
+#[tokio::test]
+async fn listeners_hold_back_drain() {
+    let shutdown = Shutdown::new();
+    let mut listener = shutdown.subscribe();
+    let task = tokio::spawn(async move {
+        listener.recv().await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    });
+
+    shutdown.trigger();
+    assert!(shutdown.is_triggered());
+    tokio::time::timeout(Duration::from_secs(1), shutdown.drained())
+        .await
+        .expect("drained after the listener is dropped");
+    assert!(task.is_finished());
+}
+
+#[tokio::test]
+async fn in_flight_request_finishes() {
+    let shutdown = Shutdown::new();
+    let router = Router::new().route(
+        "/slow",
+        get(|| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "done"
+        }),
+    );
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let address = server.local_addr();
+    let server = server.with_graceful_shutdown(shutdown.triggered());
+    let serving = {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            shutdown::serve_until_shutdown(server, &shutdown, Duration::from_secs(5)).await
+        })
+    };
+
+    let request = tokio::spawn(async move {
+        hyper::Client::new()
+            .get(format!("http://{address}/slow").parse().unwrap())
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown.trigger();
+
+    let response = request
+        .await
+        .unwrap()
+        .expect("request completes during shutdown");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"done");
+    tokio::time::timeout(Duration::from_secs(1), serving)
+        .await
+        .expect("server stops after draining")
+        .unwrap();
+}
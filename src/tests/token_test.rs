@@ -0,0 +1,133 @@
+use crate::{
+    app_state::AppState,
+    authz::Grants,
+    diagnostics,
+    token_impl::{JwtKeys, TokenKind, TokenService},
+    util::config::{JwtAlgorithm, JwtConfig, TomlConfig},
+};
+
+fn config(algorithm: JwtAlgorithm) -> JwtConfig {
+    let key = |name: &str| Some(format!("src/tests/keys/{name}").into());
+    let (private_key_file, public_key_file) = match algorithm {
+        JwtAlgorithm::Hs256 => (None, None),
+        JwtAlgorithm::Rs256 => (key("rsa_private.pem"), key("rsa_public.pem")),
+        JwtAlgorithm::EdDsa => (key("ed25519_private.pem"), key("ed25519_public.pem")),
+    };
+    JwtConfig {
+        algorithm,
+        secret: "0123456789abcdef0123456789abcdef".to_owned(),
+        private_key_file,
+        public_key_file,
+        leeway_secs: 0,
+        ..JwtConfig::default()
+    }
+}
+
+fn unauthorized<T>(result: diagnostics::Result<T>) -> bool {
+    matches!(result, Err(diagnostics::Error::Unauthorized))
+}
+
+#[test]
+fn sign_and_verify() {
+    for algorithm in [
+        JwtAlgorithm::Hs256,
+        JwtAlgorithm::Rs256,
+        JwtAlgorithm::EdDsa,
+    ] {
+        let keys = JwtKeys::new(&config(algorithm)).unwrap();
+        let token = keys
+            .encode(&keys.claims(7, TokenKind::Access, None))
+            .unwrap();
+        let claims = keys.decode(&token, TokenKind::Access).unwrap();
+        assert_eq!(claims.user_id(), Some(7), "{algorithm:?}");
+
+        // a refresh token is not an access token and the other way round
+        assert!(unauthorized(keys.decode(&token, TokenKind::Refresh)));
+
+        let mut tampered = token.clone();
+        tampered.insert(token.find('.').unwrap() + 2, 'x');
+        assert!(unauthorized(keys.decode(&tampered, TokenKind::Access)));
+    }
+
+    // signed by someone else
+    let keys = JwtKeys::new(&config(JwtAlgorithm::Hs256)).unwrap();
+    let other = JwtKeys::new(&JwtConfig {
+        secret: "fedcba9876543210fedcba9876543210".to_owned(),
+        ..config(JwtAlgorithm::Hs256)
+    })
+    .unwrap();
+    let token = other
+        .encode(&other.claims(7, TokenKind::Access, None))
+        .unwrap();
+    assert!(unauthorized(keys.decode(&token, TokenKind::Access)));
+
+    // wrong issuer, expired
+    let mut claims = keys.claims(7, TokenKind::Access, None);
+    claims.iss = "someone-else".to_owned();
+    let token = keys.encode(&claims).unwrap();
+    assert!(unauthorized(keys.decode(&token, TokenKind::Access)));
+    let mut claims = keys.claims(7, TokenKind::Access, None);
+    claims.exp = claims.iat - 1;
+    let token = keys.encode(&claims).unwrap();
+    assert!(unauthorized(keys.decode(&token, TokenKind::Access)));
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn refresh_rotation_detects_reuse() {
+    let pool = super::memory_pool().await;
+    let tokens = TokenService::new(JwtKeys::new(&config(JwtAlgorithm::Hs256)).unwrap(), pool);
+
+    let first = tokens.issue(7, Grants::default()).await.unwrap();
+    let access = tokens
+        .keys()
+        .decode(&first.access_token, TokenKind::Access)
+        .unwrap();
+    assert_eq!(access.user_id(), Some(7));
+    // access tokens are not refresh tokens
+    assert!(unauthorized(tokens.refresh(&first.access_token).await));
+
+    let second = tokens.refresh(&first.refresh_token).await.unwrap();
+    assert_ne!(second.refresh_token, first.refresh_token);
+
+    // the first token again: reuse, the family is revoked
+    assert!(unauthorized(tokens.refresh(&first.refresh_token).await));
+    assert!(unauthorized(tokens.refresh(&second.refresh_token).await));
+
+    // other logins are not affected, revoke ends them
+    let other = tokens.issue(7, Grants::default()).await.unwrap();
+    let other = tokens.refresh(&other.refresh_token).await.unwrap();
+    tokens.revoke(&other.refresh_token).await.unwrap();
+    assert!(unauthorized(tokens.refresh(&other.refresh_token).await));
+}
+
+#[test]
+fn config_validation() {
+    assert!(JwtConfig::default().validate().is_ok());
+    let short = JwtConfig {
+        secret: "short".to_owned(),
+        ..JwtConfig::default()
+    };
+    assert!(short.validate().is_err());
+    let no_keys = JwtConfig {
+        algorithm: JwtAlgorithm::Rs256,
+        ..JwtConfig::default()
+    };
+    assert!(no_keys.validate().is_err());
+    assert!(config(JwtAlgorithm::EdDsa).validate().is_ok());
+    let ttl = JwtConfig {
+        refresh_ttl_secs: 60,
+        ..JwtConfig::default()
+    };
+    assert!(ttl.validate().is_err());
+}
+
+#[tokio::test]
+async fn unreadable_keys_fail_the_app_state() {
+    let mut config = TomlConfig::load("app_config.toml", None).unwrap();
+    config.jwt.algorithm = JwtAlgorithm::Rs256;
+    config.jwt.private_key_file = Some("src/tests/keys/missing.pem".into());
+    config.jwt.public_key_file = Some("src/tests/keys/rsa_public.pem".into());
+    let error = AppState::new(&config).await.err().unwrap();
+    assert!(error.to_string().contains("private_key_file"), "{error}");
+}
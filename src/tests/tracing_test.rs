@@ -0,0 +1,204 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{body::Body, routing::get, Router};
+use hyper::{header, Request, StatusCode};
+use tower::{Layer, ServiceExt};
+use tracing_subscriber::{fmt::MakeWriter, prelude::__tracing_subscriber_SubscriberExt};
+
+use crate::{
+    app_state::AppState,
+    authz::Principal,
+    depends::Depends,
+    token_impl::TokenKind,
+    util::{
+        config::{LogFormat, LogOutputConfig, TomlConfig},
+        request_id::RequestIdLayer,
+        tracing::{output_layer, LogLevels},
+    },
+};
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Buffer {
+    type Writer = Buffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Buffer {
+    fn lines(&self) -> Vec<serde_json::Value> {
+        let bytes = self.0.lock().unwrap();
+        std::str::from_utf8(&bytes)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+fn output(format: LogFormat, filter: Option<&str>) -> LogOutputConfig {
+    LogOutputConfig {
+        format,
+        filter: filter.map(str::to_owned),
+        app_only: false,
+        with_max_level: "INFO".to_owned(),
+        with_file: false,
+        with_line_number: false,
+        with_target: true,
+    }
+}
+
+#[test]
+fn directives() {
+    let mut config = output(LogFormat::Full, None);
+    assert_eq!(config.directives(), "info");
+    config.app_only = true;
+    config.with_max_level = "DEBUG".to_owned();
+    assert_eq!(config.directives(), "app=debug");
+    config.filter = Some("app=trace,sqlx=warn".to_owned());
+    assert_eq!(config.directives(), "app=trace,sqlx=warn");
+}
+
+#[test]
+fn json_flattens_span_fields() {
+    let buffer = Buffer::default();
+    let (layer, _) = output_layer(
+        &output(LogFormat::Json, Some("app=debug,other=warn")),
+        buffer.clone(),
+        false,
+    )
+    .unwrap();
+    let subscriber = tracing_subscriber::registry().with(vec![layer]);
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span =
+            tracing::info_span!("request", request_id = "abc", route = tracing::field::Empty);
+        let _enter = span.enter();
+        span.record("route", "/items/:id");
+        tracing::debug!(attempt = 2, "handled");
+        tracing::info!(target: "other", "filtered out");
+        tracing::warn!(target: "other", "kept");
+    });
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 2, "{lines:?}");
+    let line = &lines[0];
+    assert_eq!(line["level"], "DEBUG");
+    assert_eq!(line["message"], "handled");
+    assert_eq!(line["request_id"], "abc");
+    assert_eq!(line["route"], "/items/:id");
+    assert_eq!(line["attempt"], 2);
+    assert_eq!(line["spans"], serde_json::json!(["request"]));
+    assert_eq!(lines[1]["target"], "other");
+}
+
+#[tokio::test]
+async fn request_span_records_the_user() {
+    let buffer = Buffer::default();
+    let (layer, _) = output_layer(
+        &output(LogFormat::Json, Some("app=info")),
+        buffer.clone(),
+        false,
+    )
+    .unwrap();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(vec![layer]));
+
+    let config = TomlConfig::load("app_config.toml", None).unwrap();
+    let app_state = AppState::new(&config).await.unwrap();
+    let keys = app_state.jwt_keys.clone();
+    let app = Router::new()
+        .route("/whoami", get(|_: Depends<Principal>| async { "ok" }))
+        .with_state(app_state);
+    let token = keys
+        .encode(&keys.claims(7, TokenKind::Access, None))
+        .unwrap();
+    let req = Request::builder()
+        .uri("/whoami")
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    let res = RequestIdLayer.layer(app).oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let lines = buffer.lines();
+    let finished = lines
+        .iter()
+        .find(|line| line["message"] == "request finished")
+        .unwrap_or_else(|| panic!("{lines:?}"));
+    assert_eq!(finished["user_id"], 7);
+}
+
+#[tokio::test]
+async fn runtime_level_change_reverts() {
+    let buffer = Buffer::default();
+    let config = output(LogFormat::Json, Some("app=info"));
+    let (layer, handle) = output_layer(&config, buffer.clone(), false).unwrap();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(vec![layer]));
+    let levels = LogLevels::new(vec![("console", handle, config.directives())]);
+    let messages = || {
+        buffer
+            .lines()
+            .iter()
+            .map(|line| line["message"].as_str().unwrap_or_default().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    tracing::debug!("before");
+    assert!(levels.set(Some("console"), "app=[", None).is_err());
+    assert!(levels.set(Some("file"), "app=debug", None).is_err());
+    levels
+        .set(None, "app=debug", Some(Duration::from_millis(50)))
+        .unwrap();
+    assert_eq!(levels.current()["console"].directives, "app=debug");
+    tracing::debug!("during");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(levels.current()["console"].directives, "app=info");
+    assert!(levels.current()["console"].revert_in_secs.is_none());
+    tracing::debug!("after");
+
+    let messages = messages();
+    assert!(messages.contains(&"during".to_owned()), "{messages:?}");
+    assert!(!messages.contains(&"before".to_owned()));
+    assert!(!messages.contains(&"after".to_owned()));
+}
+
+#[test]
+fn log_records_are_bridged() {
+    // global, a second init in the same process is fine to ignore
+    let _ = tracing_log::LogTracer::init();
+    let buffer = Buffer::default();
+    let mut config = output(LogFormat::Json, Some("bridge=debug"));
+    config.with_line_number = true;
+    let (layer, _) = output_layer(&config, buffer.clone(), false).unwrap();
+
+    tracing::subscriber::with_default(tracing_subscriber::registry().with(vec![layer]), || {
+        log::debug!(target: "bridge", "from log");
+        log::trace!(target: "bridge", "filtered out");
+    });
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1, "{lines:?}");
+    assert_eq!(lines[0]["message"], "from log");
+    assert_eq!(lines[0]["target"], "bridge");
+    assert_eq!(lines[0]["level"], "DEBUG");
+    assert!(lines[0]["line"].is_u64());
+    assert!(lines[0].get("log.target").is_none());
+}
@@ -0,0 +1,190 @@
+use axum::body::Body;
+use hyper::{header, Request, StatusCode};
+use tower::ServiceExt;
+
+use crate::{define, diagnostics, dto, repository::UserRepositoryDB, usecase::BasicUserUsecase};
+
+fn register(email: &str, password: &str) -> dto::UserRegister {
+    dto::UserRegister {
+        name: "alice".to_owned(),
+        email: email.to_owned(),
+        password: password.to_owned(),
+    }
+}
+
+fn login(email: &str, password: &str) -> dto::UserLogin {
+    dto::UserLogin {
+        email: email.to_owned(),
+        password: password.to_owned(),
+        mfa_code: None,
+    }
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn register_and_login() {
+    let pool = super::memory_pool().await;
+    let usecase = BasicUserUsecase::new(UserRepositoryDB::new(pool));
+
+    let user = usecase
+        .register(register(" Alice@Example.com ", "correct horse"))
+        .await
+        .unwrap();
+    assert_eq!(user.email, "alice@example.com");
+    assert!(user.password_hash.starts_with("$argon2id$"));
+    assert!(serde_json::to_value(&user)
+        .unwrap()
+        .get("password_hash")
+        .is_none());
+
+    assert!(matches!(
+        usecase
+            .register(register("alice@example.com", "another one"))
+            .await,
+        Err(diagnostics::Error::Conflict(_))
+    ));
+    assert!(matches!(
+        usecase.register(register("bob@example.com", "short")).await,
+        Err(diagnostics::Error::Message(_))
+    ));
+
+    let logged_in = usecase
+        .login(login("ALICE@example.com", "correct horse"))
+        .await
+        .unwrap();
+    assert_eq!(logged_in.id, user.id);
+    for (email, password) in [
+        ("alice@example.com", "wrong horse"),
+        ("nobody@example.com", "correct horse"),
+    ] {
+        assert!(matches!(
+            usecase.login(login(email, password)).await,
+            Err(diagnostics::Error::Unauthorized)
+        ));
+    }
+}
+
+#[cfg(feature = "use_sqlite")]
+#[tokio::test]
+async fn login_endpoints() {
+    let (app, db_pool, _db) = super::test_app(|_| {}).await;
+
+    let json = |method: &str, uri: &str, body: serde_json::Value| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let email = format!("{}@example.com", uuid::Uuid::new_v4().simple());
+    let credentials = serde_json::json!({ "email": email, "password": "correct horse" });
+
+    let res = app
+        .clone()
+        .oneshot(json(
+            "POST",
+            "/api/v1/auth/register",
+            serde_json::json!({ "name": "alice", "email": email, "password": "correct horse" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let res = app
+        .clone()
+        .oneshot(json("POST", "/api/v1/auth/login", credentials.clone()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let token = res.headers()[define::CSRF_HEADER]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let cookie = res
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|v| v.to_str().unwrap())
+        .find(|v| v.starts_with(define::SESSION_COOKIE))
+        .map(|v| v.split(';').next().unwrap().to_owned())
+        .unwrap();
+
+    let me = || {
+        Request::builder()
+            .uri("/api/v1/auth/me")
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let res = app.clone().oneshot(me()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let user: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(user["email"], email.as_str());
+
+    // new users may read samples but not write them
+    let res = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/api/v1/sample")
+                .header(header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let mut create = json("POST", "/api/v1/sample", serde_json::json!({ "name": "x" }));
+    create
+        .headers_mut()
+        .insert(header::COOKIE, cookie.parse().unwrap());
+    create
+        .headers_mut()
+        .insert(define::CSRF_HEADER, token.parse().unwrap());
+    let res = app.clone().oneshot(create).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // bearer tokens reach the same user without a cookie
+    let res = app
+        .clone()
+        .oneshot(json("POST", "/api/v1/auth/token", credentials.clone()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let tokens: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let bearer = |token: &str| {
+        Request::builder()
+            .uri("/api/v1/auth/me")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let res = app
+        .clone()
+        .oneshot(bearer(tokens["access_token"].as_str().unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app
+        .clone()
+        .oneshot(bearer(tokens["refresh_token"].as_str().unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let logout = Request::builder()
+        .method("POST")
+        .uri("/api/v1/auth/logout")
+        .header(header::COOKIE, &cookie)
+        .header(define::CSRF_HEADER, token)
+        .body(Body::empty())
+        .unwrap();
+    let res = app.clone().oneshot(logout).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = app.oneshot(me()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    db_pool.close().await;
+}
@@ -0,0 +1,201 @@
+mod service;
+
+use std::{fmt, fs, sync::Arc, time::Duration};
+
+use hyper::{header, HeaderMap};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    authz::Grants,
+    diagnostics,
+    util::config::{JwtAlgorithm, JwtConfig},
+};
+
+pub(crate) use self::service::{TokenPair, TokenService};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TokenKind {
+    Access,
+    Refresh,
+}
+
+/// payload of access and refresh tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Claims {
+    // user id
+    pub sub: String,
+    pub iss: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    pub iat: u64,
+    pub exp: u64,
+    pub jti: String,
+    pub typ: TokenKind,
+    // refresh tokens only, every rotation of one login stays in the same family
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fam: Option<String>,
+    // access tokens only
+    #[serde(flatten)]
+    pub grants: Grants,
+}
+
+impl Claims {
+    pub(crate) fn user_id(&self) -> Option<i64> {
+        self.sub.parse().ok()
+    }
+}
+
+/// the token of an `Authorization: Bearer <token>` value
+pub(crate) fn bearer_token(value: &str) -> Option<&str> {
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then_some(token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// signing and verification keys from `[jwt]`
+#[derive(Clone)]
+pub(crate) struct JwtKeys {
+    inner: Arc<Keys>,
+}
+
+struct Keys {
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    validation: Validation,
+    issuer: String,
+    audience: Option<String>,
+    access_ttl: Duration,
+    refresh_ttl: Duration,
+}
+
+impl fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtKeys")
+            .field("algorithm", &self.inner.algorithm)
+            .field("issuer", &self.inner.issuer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtKeys {
+    pub(crate) fn new(config: &JwtConfig) -> anyhow::Result<Self> {
+        let pem = |path: &Option<std::path::PathBuf>, name: &str| {
+            let path = path
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("jwt: {name} is not set"))?;
+            fs::read(path).map_err(|e| anyhow::anyhow!("jwt: {name} `{}`: {e}", path.display()))
+        };
+        let (algorithm, encoding, decoding) = match config.algorithm {
+            JwtAlgorithm::Hs256 => {
+                let secret = if config.secret.is_empty() {
+                    tracing::warn!("jwt.secret is empty, tokens will not survive a restart");
+                    format!(
+                        "{}{}",
+                        uuid::Uuid::new_v4().simple(),
+                        uuid::Uuid::new_v4().simple()
+                    )
+                } else {
+                    config.secret.clone()
+                };
+                (
+                    Algorithm::HS256,
+                    EncodingKey::from_secret(secret.as_bytes()),
+                    DecodingKey::from_secret(secret.as_bytes()),
+                )
+            }
+            JwtAlgorithm::Rs256 => (
+                Algorithm::RS256,
+                EncodingKey::from_rsa_pem(&pem(&config.private_key_file, "private_key_file")?)?,
+                DecodingKey::from_rsa_pem(&pem(&config.public_key_file, "public_key_file")?)?,
+            ),
+            JwtAlgorithm::EdDsa => (
+                Algorithm::EdDSA,
+                EncodingKey::from_ed_pem(&pem(&config.private_key_file, "private_key_file")?)?,
+                DecodingKey::from_ed_pem(&pem(&config.public_key_file, "public_key_file")?)?,
+            ),
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[config.issuer.as_str()]);
+        match &config.audience {
+            Some(audience) => validation.set_audience(&[audience.as_str()]),
+            None => validation.validate_aud = false,
+        }
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        validation.leeway = config.leeway_secs;
+
+        Ok(Self {
+            inner: Arc::new(Keys {
+                algorithm,
+                encoding,
+                decoding,
+                validation,
+                issuer: config.issuer.clone(),
+                audience: config.audience.clone(),
+                access_ttl: config.access_ttl(),
+                refresh_ttl: config.refresh_ttl(),
+            }),
+        })
+    }
+
+    pub(crate) fn ttl(&self, kind: TokenKind) -> Duration {
+        match kind {
+            TokenKind::Access => self.inner.access_ttl,
+            TokenKind::Refresh => self.inner.refresh_ttl,
+        }
+    }
+
+    /// claims for a new token of `user_id`, valid from now for the ttl of `kind`
+    pub(crate) fn claims(&self, user_id: i64, kind: TokenKind, family: Option<String>) -> Claims {
+        let now = jsonwebtoken::get_current_timestamp();
+        Claims {
+            sub: user_id.to_string(),
+            iss: self.inner.issuer.clone(),
+            aud: self.inner.audience.clone(),
+            iat: now,
+            exp: now + self.ttl(kind).as_secs(),
+            jti: uuid::Uuid::new_v4().simple().to_string(),
+            typ: kind,
+            fam: family,
+            grants: Grants::default(),
+        }
+    }
+
+    pub(crate) fn encode(&self, claims: &Claims) -> anyhow::Result<String> {
+        Ok(jsonwebtoken::encode(
+            &Header::new(self.inner.algorithm),
+            claims,
+            &self.inner.encoding,
+        )?)
+    }
+
+    /// `Unauthorized` unless the token is signed by our key, unexpired, from our issuer
+    /// and of the expected kind
+    pub(crate) fn decode(&self, token: &str, kind: TokenKind) -> diagnostics::Result<Claims> {
+        let claims =
+            jsonwebtoken::decode::<Claims>(token, &self.inner.decoding, &self.inner.validation)
+                .map_err(|e| {
+                    tracing::debug!("rejected {kind:?} token: {e}");
+                    diagnostics::Error::Unauthorized
+                })?
+                .claims;
+        if claims.typ != kind {
+            tracing::debug!("rejected {:?} token used as {kind:?} token", claims.typ);
+            return Err(diagnostics::Error::Unauthorized);
+        }
+        Ok(claims)
+    }
+
+    /// claims of the `Authorization: Bearer` access token, `None` when the headers carry
+    /// no bearer token so callers can fall back to other credentials
+    pub(crate) fn decode_bearer(&self, headers: &HeaderMap) -> Option<diagnostics::Result<Claims>> {
+        let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+        let token = bearer_token(value)?;
+        Some(self.decode(token, TokenKind::Access))
+    }
+}
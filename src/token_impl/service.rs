@@ -0,0 +1,147 @@
+use serde::Serialize;
+use sqlx::Pool;
+
+use super::{JwtKeys, TokenKind};
+use crate::{
+    app_state::DataBase,
+    authz::Grants,
+    diagnostics,
+    repository::{UserRepository, UserRepositoryDB},
+};
+
+/// what the token endpoints return
+#[derive(Debug, Serialize)]
+pub(crate) struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    // access token lifetime in seconds
+    pub expires_in: u64,
+}
+
+/// issues access and refresh tokens. refresh tokens are single use: every refresh marks
+/// the presented token used in `refresh_tokens` and hands out a new one of the same
+/// family, presenting a used token again revokes the whole family
+#[derive(Clone, Debug)]
+pub(crate) struct TokenService {
+    keys: JwtKeys,
+    pool: Pool<DataBase>,
+}
+
+impl TokenService {
+    pub(crate) fn new(keys: JwtKeys, pool: Pool<DataBase>) -> Self {
+        Self { keys, pool }
+    }
+
+    pub(crate) fn keys(&self) -> &JwtKeys {
+        &self.keys
+    }
+
+    /// a token pair of a new family, after the user authenticated
+    pub(crate) async fn issue(
+        &self,
+        user_id: i64,
+        grants: Grants,
+    ) -> diagnostics::Result<TokenPair> {
+        // expired rows are only kept for reuse detection while they could be presented
+        sqlx::query(r#"delete from refresh_tokens where expires_at <= $1"#)
+            .bind(jsonwebtoken::get_current_timestamp() as i64)
+            .execute(&self.pool)
+            .await?;
+        let family = uuid::Uuid::new_v4().simple().to_string();
+        self.issue_in(family, user_id, grants).await
+    }
+
+    /// exchanges a refresh token for a new pair. `Unauthorized` when the token is invalid,
+    /// revoked or was used before, the latter also revokes its family
+    pub(crate) async fn refresh(&self, refresh_token: &str) -> diagnostics::Result<TokenPair> {
+        let claims = self.keys.decode(refresh_token, TokenKind::Refresh)?;
+        let (Some(family), Some(user_id)) = (claims.fam.clone(), claims.user_id()) else {
+            return Err(diagnostics::Error::Unauthorized);
+        };
+
+        let used = sqlx::query(
+            r#"update refresh_tokens set used_at = $1 where id = $2 and used_at is null"#,
+        )
+        .bind(jsonwebtoken::get_current_timestamp() as i64)
+        .bind(&claims.jti)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if used == 0 {
+            // used before or the family is gone, whoever holds the newer token loses it too
+            let revoked = self.revoke_family(&family).await?;
+            if revoked > 0 {
+                tracing::warn!(
+                    "refresh token reuse for user {user_id}, revoked {revoked} tokens of family `{family}`"
+                );
+            }
+            return Err(diagnostics::Error::Unauthorized);
+        }
+        // role changes since the last refresh show up in the new access token
+        let grants = UserRepositoryDB::new(self.pool.clone())
+            .grants(user_id)
+            .await?;
+        self.issue_in(family, user_id, grants).await
+    }
+
+    /// logout for token clients, every refresh token of the family stops working.
+    /// access tokens stay valid until they expire
+    pub(crate) async fn revoke(&self, refresh_token: &str) -> diagnostics::Result<()> {
+        let claims = self.keys.decode(refresh_token, TokenKind::Refresh)?;
+        if let Some(family) = &claims.fam {
+            self.revoke_family(family).await?;
+        }
+        Ok(())
+    }
+
+    /// every refresh token of the user, e.g. after a role change
+    pub(crate) async fn revoke_user(&self, user_id: i64) -> diagnostics::Result<u64> {
+        Ok(
+            sqlx::query(r#"delete from refresh_tokens where user_id = $1"#)
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn revoke_family(&self, family: &str) -> diagnostics::Result<u64> {
+        Ok(
+            sqlx::query(r#"delete from refresh_tokens where family = $1"#)
+                .bind(family)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn issue_in(
+        &self,
+        family: String,
+        user_id: i64,
+        grants: Grants,
+    ) -> diagnostics::Result<TokenPair> {
+        let mut access = self.keys.claims(user_id, TokenKind::Access, None);
+        access.grants = grants;
+        let refresh = self
+            .keys
+            .claims(user_id, TokenKind::Refresh, Some(family.clone()));
+        sqlx::query(
+            r#"insert into refresh_tokens(id, family, user_id, expires_at) values ($1, $2, $3, $4)"#,
+        )
+        .bind(&refresh.jti)
+        .bind(&family)
+        .bind(user_id)
+        .bind(refresh.exp as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(TokenPair {
+            access_token: self.keys.encode(&access)?,
+            refresh_token: self.keys.encode(&refresh)?,
+            token_type: "Bearer",
+            expires_in: self.keys.ttl(TokenKind::Access).as_secs(),
+        })
+    }
+}
@@ -0,0 +1,100 @@
+use axum::extract::FromRef;
+
+use crate::{
+    app_state::AppState,
+    authz::{self, api_key},
+    diagnostics::{self, Error},
+    dto,
+    entity::ApiKey,
+    repository::ApiKeyRepository,
+};
+
+#[derive(Clone)]
+pub(crate) struct BasicApiKeyUsecase<ApiKeyRepositoryT> {
+    pub api_key_repository: ApiKeyRepositoryT,
+}
+
+impl<ApiKeyRepositoryT> BasicApiKeyUsecase<ApiKeyRepositoryT>
+where
+    ApiKeyRepositoryT: ApiKeyRepository,
+{
+    pub fn new(api_key_repository: ApiKeyRepositoryT) -> Self {
+        BasicApiKeyUsecase { api_key_repository }
+    }
+
+    pub async fn create(
+        &self,
+        create: dto::ApiKeyCreate,
+    ) -> diagnostics::Result<dto::ApiKeyCreated> {
+        let name = create.name.trim();
+        if name.is_empty() {
+            return Err(Error::Message("name must not be empty".to_owned()));
+        }
+        if create.scopes.is_empty() {
+            return Err(Error::Message("scopes must not be empty".to_owned()));
+        }
+        if let Some(unknown) = create
+            .scopes
+            .iter()
+            .find(|scope| !authz::ALL_PERMISSIONS.contains(&scope.as_str()))
+        {
+            return Err(Error::Message(format!("unknown scope `{unknown}`")));
+        }
+
+        let now = now();
+        let expires_at = create
+            .expires_in_secs
+            .map(|secs| now.saturating_add(i64::try_from(secs).unwrap_or(i64::MAX)));
+        let key = api_key::generate();
+        let mut scopes = create.scopes;
+        scopes.sort();
+        scopes.dedup();
+        let api_key = self
+            .api_key_repository
+            .create(ApiKey::new(
+                name.to_owned(),
+                api_key::display_prefix(&key),
+                api_key::hash(&key),
+                scopes,
+                now,
+                expires_at,
+            ))
+            .await?;
+        Ok(dto::ApiKeyCreated { api_key, key })
+    }
+
+    pub async fn list(&self) -> diagnostics::Result<Vec<ApiKey>> {
+        self.api_key_repository.find_all().await
+    }
+
+    /// `RowNotFound` for an unknown id
+    pub async fn revoke(&self, id: i64) -> diagnostics::Result<()> {
+        let api_key = self.api_key_repository.find_by_id(&id).await?;
+        self.api_key_repository.delete_by_id(&api_key.id).await
+    }
+
+    /// the stored key for `key`, `Unauthorized` when unknown, revoked or expired
+    pub async fn authenticate(&self, key: &str) -> diagnostics::Result<ApiKey> {
+        match self
+            .api_key_repository
+            .find_by_hash(&api_key::hash(key))
+            .await?
+        {
+            Some(api_key) if !api_key.is_expired(now()) => Ok(api_key),
+            _ => Err(Error::Unauthorized),
+        }
+    }
+}
+
+fn now() -> i64 {
+    jsonwebtoken::get_current_timestamp() as i64
+}
+
+impl<ApiKeyRepositoryT> FromRef<AppState> for BasicApiKeyUsecase<ApiKeyRepositoryT>
+where
+    ApiKeyRepositoryT: FromRef<AppState> + ApiKeyRepository,
+{
+    fn from_ref(state: &AppState) -> Self {
+        BasicApiKeyUsecase::new(ApiKeyRepositoryT::from_ref(state))
+    }
+}
@@ -1,4 +1,6 @@
+mod api_key_usecase;
 mod sample_usecase;
+mod user_usecase;
 
 use axum::{
     async_trait,
@@ -6,7 +8,10 @@ use axum::{
     http::request::Parts,
 };
 
-use crate::{diagnostics, repository::SampleRepositoryDB};
+use crate::{
+    diagnostics,
+    repository::{ApiKeyRepositoryDB, SampleRepositoryDB, UserRepositoryDB},
+};
 
 pub(crate) struct Usecase<T>(pub T);
 
@@ -19,10 +24,14 @@ where
     type Rejection = diagnostics::Error;
 
     async fn from_request_parts(_parts: &mut Parts, state: &S) -> diagnostics::Result<Self> {
-        Ok(Usecase::<T>(T::from_ref(state).into()))
+        Ok(Usecase::<T>(T::from_ref(state)))
     }
 }
 
+pub(crate) use self::api_key_usecase::BasicApiKeyUsecase;
 pub(crate) use self::sample_usecase::BasicSampleUsecase;
+pub(crate) use self::user_usecase::BasicUserUsecase;
 // user custom exports
+pub(crate) type ApiKeyUsecase = BasicApiKeyUsecase<ApiKeyRepositoryDB>;
 pub(crate) type SampleUsecase = BasicSampleUsecase<SampleRepositoryDB>;
+pub(crate) type UserUsecase = BasicUserUsecase<UserRepositoryDB>;
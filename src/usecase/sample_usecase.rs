@@ -1,6 +1,11 @@
 use axum::extract::FromRef;
 
-use crate::{app_state::AppState, diagnostics, entity::Sample, repository::SampleRepository};
+use crate::{
+    app_state::AppState,
+    diagnostics,
+    entity::Sample,
+    repository::{Page, PageRequest, SampleRepository},
+};
 
 pub(crate) struct BasicSampleUsecase<SampleRepositoryT> {
     pub sample_repository: SampleRepositoryT,
@@ -19,6 +24,10 @@ where
         Ok(samples)
     }
 
+    pub async fn find_page(&self, request: PageRequest) -> diagnostics::Result<Page<Sample>> {
+        self.sample_repository.find_page(request).await
+    }
+
     pub async fn create(&self, sample: Sample) -> diagnostics::Result<Sample> {
         let sample = self.sample_repository.create(sample).await?;
         Ok(sample)
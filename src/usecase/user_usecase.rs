@@ -0,0 +1,283 @@
+use axum::extract::FromRef;
+
+use crate::{
+    app_state::AppState,
+    authz::{self, totp, Grants},
+    diagnostics::{self, Error},
+    dto,
+    entity::User,
+    oidc_impl::Identity,
+    repository::UserRepository,
+    util::password,
+};
+
+pub(crate) const MIN_PASSWORD_LEN: usize = 8;
+
+pub(crate) struct BasicUserUsecase<UserRepositoryT> {
+    pub user_repository: UserRepositoryT,
+}
+
+impl<UserRepositoryT> BasicUserUsecase<UserRepositoryT>
+where
+    UserRepositoryT: UserRepository,
+{
+    pub fn new(user_repository: UserRepositoryT) -> Self {
+        BasicUserUsecase { user_repository }
+    }
+
+    pub async fn register(&self, register: dto::UserRegister) -> diagnostics::Result<User> {
+        let name = register.name.trim();
+        let email = normalize_email(&register.email);
+        if name.is_empty() {
+            return Err(Error::Message("name must not be empty".to_owned()));
+        }
+        if !email.contains('@') {
+            return Err(Error::Message("email is not valid".to_owned()));
+        }
+        if register.password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(Error::Message(format!(
+                "password must be at least {MIN_PASSWORD_LEN} characters"
+            )));
+        }
+
+        let password_hash = password::hash(register.password).await?;
+        let user = self
+            .user_repository
+            .create(User::new(name.to_owned(), email, password_hash))
+            .await?;
+        self.user_repository
+            .add_role(user.id, authz::DEFAULT_ROLE)
+            .await?;
+        Ok(user)
+    }
+
+    /// the user behind the credentials, `Unauthorized` for an unknown email and a wrong
+    /// password alike
+    pub async fn login(&self, login: dto::UserLogin) -> diagnostics::Result<User> {
+        let email = normalize_email(&login.email);
+        let Some(user) = self.user_repository.find_by_email(&email).await? else {
+            // same work as a wrong password, response times do not tell which emails exist
+            password::hash(login.password).await?;
+            return Err(Error::Unauthorized);
+        };
+        // users from an openid provider have no password
+        if user.password_hash.is_empty() {
+            password::hash(login.password).await?;
+            return Err(Error::Unauthorized);
+        }
+        if !password::verify(login.password, user.password_hash.clone()).await? {
+            return Err(Error::Unauthorized);
+        }
+        Ok(user)
+    }
+
+    /// the local user of an openid identity. an identity seen before maps to its user, a
+    /// verified email that is registered already gets linked to that user, otherwise a
+    /// new user without a password is created
+    pub async fn login_external(
+        &self,
+        provider: &str,
+        identity: Identity,
+    ) -> diagnostics::Result<User> {
+        if let Some(user) = self
+            .user_repository
+            .find_by_identity(provider, &identity.sub)
+            .await?
+        {
+            return Ok(user);
+        }
+        let Some(email) = identity
+            .email
+            .as_deref()
+            .map(normalize_email)
+            .filter(|email| email.contains('@'))
+        else {
+            return Err(Error::Message(
+                "the provider did not share an email address".to_owned(),
+            ));
+        };
+
+        let user = match self.user_repository.find_by_email(&email).await? {
+            Some(user) if identity.email_verified == Some(true) => user,
+            // an unverified email proves nothing about owning the account
+            Some(_) => return Err(Error::Conflict("email is already registered".to_owned())),
+            None => {
+                let name = identity
+                    .name
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or_else(|| email.split('@').next().unwrap_or_default())
+                    .to_owned();
+                let user = self
+                    .user_repository
+                    .create(User::new(name, email, String::new()))
+                    .await?;
+                self.user_repository
+                    .add_role(user.id, authz::DEFAULT_ROLE)
+                    .await?;
+                user
+            }
+        };
+        self.user_repository
+            .link_identity(user.id, provider, &identity.sub)
+            .await?;
+        tracing::info!("linked {provider} account to user {}", user.id);
+        Ok(user)
+    }
+
+    pub async fn grants(&self, user_id: i64) -> diagnostics::Result<Grants> {
+        self.user_repository.grants(user_id).await
+    }
+
+    /// whether the user turned on a second factor, a login waits for its code then
+    pub async fn has_second_factor(&self, user: &User) -> diagnostics::Result<bool> {
+        Ok(matches!(
+            self.user_repository.find_totp(user.id).await?,
+            Some(totp) if totp.is_confirmed()
+        ))
+    }
+
+    /// `MfaRequired` when the user has a second factor and `code` is missing,
+    /// `Unauthorized` when it is wrong
+    pub async fn second_factor(
+        &self,
+        user: &User,
+        code: Option<&str>,
+        max_attempts: u32,
+    ) -> diagnostics::Result<()> {
+        match self.user_repository.find_totp(user.id).await? {
+            Some(totp) if totp.is_confirmed() => {
+                let code = code.ok_or(Error::MfaRequired)?;
+                self.verify_mfa(user.id, code, max_attempts)
+                    .await
+                    .map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// the user once `code`, a totp or an unused recovery code, checks out. both work
+    /// only once. after `max_attempts` wrong codes in a row, on whatever route they came,
+    /// every code is refused until an admin resets the second factor
+    pub async fn verify_mfa(
+        &self,
+        user_id: i64,
+        code: &str,
+        max_attempts: u32,
+    ) -> diagnostics::Result<User> {
+        let totp = match self.user_repository.find_totp(user_id).await? {
+            Some(totp) if totp.is_confirmed() => totp,
+            _ => return Err(Error::Unauthorized),
+        };
+        if !self
+            .user_repository
+            .claim_mfa_attempt(user_id, max_attempts)
+            .await?
+        {
+            tracing::info!("second factor of user {user_id} is locked");
+            return Err(Error::Unauthorized);
+        }
+        let verified = match totp::verify(&totp.secret, code, now()) {
+            Some(step) => {
+                self.user_repository
+                    .use_totp_step(user_id, step as i64)
+                    .await?
+            }
+            None => {
+                self.user_repository
+                    .use_recovery_code(user_id, &totp::hash_recovery_code(code))
+                    .await?
+            }
+        };
+        if !verified {
+            return Err(Error::Unauthorized);
+        }
+        self.user_repository.reset_mfa_attempts(user_id).await?;
+        self.user_repository.find_by_id(&user_id).await
+    }
+
+    /// a new totp secret for the user, it takes effect once `confirm_totp` saw a code of
+    /// it. `Conflict` when the user has a second factor already
+    pub async fn enroll_totp(
+        &self,
+        user: &User,
+        issuer: &str,
+    ) -> diagnostics::Result<dto::TotpEnrollment> {
+        if let Some(totp) = self.user_repository.find_totp(user.id).await? {
+            if totp.is_confirmed() {
+                return Err(Error::Conflict(
+                    "two-factor authentication is on".to_owned(),
+                ));
+            }
+        }
+        let secret = totp::generate_secret();
+        self.user_repository.save_totp(user.id, &secret).await?;
+        Ok(dto::TotpEnrollment {
+            provisioning_uri: totp::provisioning_uri(issuer, &user.email, &secret),
+            secret,
+        })
+    }
+
+    /// turns the second factor on with a first code of the enrolled secret and hands out
+    /// the recovery codes
+    pub async fn confirm_totp(
+        &self,
+        user_id: i64,
+        code: &str,
+    ) -> diagnostics::Result<dto::RecoveryCodes> {
+        let totp = match self.user_repository.find_totp(user_id).await? {
+            Some(totp) if !totp.is_confirmed() => totp,
+            Some(_) => {
+                return Err(Error::Conflict(
+                    "two-factor authentication is on".to_owned(),
+                ))
+            }
+            None => return Err(Error::Message("no totp enrollment to confirm".to_owned())),
+        };
+        let step = totp::verify(&totp.secret, code, now()).ok_or(Error::Unauthorized)?;
+        let recovery_codes = totp::generate_recovery_codes();
+        let hashes = recovery_codes
+            .iter()
+            .map(|code| totp::hash_recovery_code(code))
+            .collect::<Vec<_>>();
+        if !self
+            .user_repository
+            .confirm_totp(user_id, step as i64, &hashes)
+            .await?
+        {
+            return Err(Error::Conflict(
+                "two-factor authentication is on".to_owned(),
+            ));
+        }
+        tracing::info!("user {user_id} turned on two-factor authentication");
+        Ok(dto::RecoveryCodes { recovery_codes })
+    }
+
+    /// for users who lost their authenticator and recovery codes. `RowNotFound` when the
+    /// user had no second factor
+    pub async fn reset_mfa(&self, user_id: i64) -> diagnostics::Result<()> {
+        if !self.user_repository.delete_totp(user_id).await? {
+            return Err(Error::RowNotFound);
+        }
+        tracing::info!("two-factor authentication of user {user_id} was reset");
+        Ok(())
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn now() -> u64 {
+    jsonwebtoken::get_current_timestamp()
+}
+
+impl<UserRepositoryT> FromRef<AppState> for BasicUserUsecase<UserRepositoryT>
+where
+    UserRepositoryT: FromRef<AppState> + UserRepository,
+{
+    fn from_ref(state: &AppState) -> Self {
+        BasicUserUsecase::new(UserRepositoryT::from_ref(state))
+    }
+}
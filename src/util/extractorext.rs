@@ -35,13 +35,13 @@ where
             Err(rejection) => {
                 let err = match rejection {
                     JsonRejection::JsonDataError(_) => {
-                        Error::Message(format!("{:?} {}", path, rejection.to_string()))
+                        Error::Message(format!("{:?} {}", path, rejection))
                     }
                     JsonRejection::JsonSyntaxError(_) => {
-                        Error::Message(format!("{:?} {}", path, rejection.to_string()))
+                        Error::Message(format!("{:?} {}", path, rejection))
                     }
                     JsonRejection::MissingJsonContentType(_) => {
-                        Error::Message(format!("{:?} {}", path, rejection.to_string()))
+                        Error::Message(format!("{:?} {}", path, rejection))
                     }
                     _ => Error::Message(rejection.to_string()),
                 };
@@ -69,10 +69,7 @@ where
                 let err = match rejection {
                     PathRejection::FailedToDeserializePathParams(inner) => {
                         let kind = inner.into_kind();
-                        let inner_err = match &kind {
-                            _ => Error::Message(format!("FailedToDeserializePathParams: {}", kind)),
-                        };
-                        inner_err
+                        Error::Message(format!("FailedToDeserializePathParams: {}", kind))
                     }
                     PathRejection::MissingPathParams(error) => Error::Message(error.to_string()),
                     _ => Error::Message(format!("Unhandled path rejection: {}", rejection)),
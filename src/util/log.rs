@@ -1,8 +0,0 @@
-use log4rs;
-
-pub(crate) fn init_file(yaml_file:&'static str) -> anyhow::Result<()>{
-    log4rs::init_file(
-        yaml_file, 
-        Default::default()
-    )
-}
\ No newline at end of file
@@ -1,50 +1,206 @@
+use std::{collections::HashSet, sync::Arc};
+
+use async_session::{Session, SessionStore};
 use axum::{
+    body::{self, Full},
+    extract::{MatchedPath, State},
+    http::{HeaderValue, Method, Request},
+    middleware::Next,
     response::{IntoResponse, Response},
-    Json,
 };
-use serde_json::json;
+use axum_extra::extract::CookieJar;
+use hyper::header;
 
-use crate::define;
+use crate::{
+    app_state::SessionService,
+    define,
+    depends::admin::constant_time_eq,
+    diagnostics::{self, Error, Problem, ProblemDebug},
+    session_impl::{self, RotateSession},
+    util::request_id::RequestId,
+};
 
 pub(crate) async fn response_map(res: Response) -> Response {
     if res.status().is_client_error() || res.status().is_server_error() {
-        if let Some(_) = res
+        if res
             .headers()
-            .get(define::CUSTOM_HEADER_IS_DIAGNOSTICS_ERROR)
+            .contains_key(define::CUSTOM_HEADER_IS_DIAGNOSTICS_ERROR)
         {
             return res;
         }
 
+        // handlers that already answer with a json body (e.g. /readyz)
+        if res.headers().get(header::CONTENT_TYPE).is_some_and(|v| {
+            v.as_bytes().starts_with(b"application/json")
+                || v.as_bytes()
+                    .starts_with(define::PROBLEM_JSON_CONTENT_TYPE.as_bytes())
+        }) {
+            return res;
+        }
+
         let status = res.status();
+        let problem = Problem::from_status(status);
         if let Ok(bytes) = hyper::body::to_bytes(res.into_body()).await {
             if let Ok(message) = std::str::from_utf8(&bytes) {
-                return (status, Json(json!({ "error": message }))).into_response();
+                if !message.is_empty() {
+                    return problem.with_detail(message).into_response();
+                }
             }
         }
-        status.into_response()
+        problem.into_response()
     } else {
         res
     }
 }
 
-// pub async fn csrf_check<B>(
-//     State(ctx): State<AppContext>,
-//     cookies: Cookies,
-//     request: Request<B>,
-//     next: Next<B>,
-// ) -> Response {
-//     let (mut parts, body) = request.into_parts();
-//     // ReadableSession life time 제어를 위해 from_request_parts 로 얻어와 drop 시킨다
-//     let s = ReadableSession::from_request_parts(&mut parts, &ctx).await.unwrap();
-//     let token = s.get::<String>(defines::CSRF_TOKEN_NAME);
-//     let cookie = cookies.get(defines::CSRF_TOKEN_NAME);
-//     if let (Some(token), Some(cookie)) = (token, cookie) {
-//         debug!("{token} {}", cookie.value());
-//     }
-//     drop(s);
-//     let response = next.run(Request::from_parts(parts, body)).await;
-
-//     // do something with `response`...
-
-//     response
-// }
+/// completes problem+json bodies with the request id and, when `http.debug_errors`
+/// is set, the internal error detail
+pub(crate) async fn problem_details<B>(
+    State(debug_errors): State<bool>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .or_else(|| {
+            request
+                .headers()
+                .get(define::REQUEST_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned())
+        });
+
+    let res = next.run(request).await;
+    let Some(mut problem) = res.extensions().get::<Problem>().cloned() else {
+        return res;
+    };
+    problem.request_id = request_id;
+    if debug_errors {
+        problem.debug = res.extensions().get::<ProblemDebug>().map(|d| d.0.clone());
+    }
+    if problem.request_id.is_none() && problem.debug.is_none() {
+        return res;
+    }
+
+    let (mut parts, _) = res.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = serde_json::to_vec(&problem).unwrap_or_default();
+    parts.extensions.insert(problem);
+    Response::from_parts(parts, body::boxed(Full::from(body)))
+}
+
+/// finishes the `RotateSession` a handler returned: the session moves to a new id and the
+/// response gets the new cookies. a failed rotation replaces the response with the error
+pub(crate) async fn rotate_session<B>(
+    State(sessions): State<SessionService>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut res = next.run(request).await;
+    let Some(RotateSession(session)) = res.extensions_mut().remove::<RotateSession>() else {
+        return res;
+    };
+    match sessions.rotate(session).await {
+        Ok((_, jar)) => (jar, res).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// state of `csrf_check`
+#[derive(Clone, Debug)]
+pub(crate) struct Csrf {
+    pub sessions: SessionService,
+    // matched paths of the routes marked `csrf_exempt`
+    pub exempt: Arc<HashSet<String>>,
+}
+
+/// synchronizer token check for requests authenticated by the session cookie. the token
+/// lives in the session, scripts read it from the `XSRF-TOKEN` cookie or the
+/// `x-csrf-token` response header and send it back in `x-csrf-token` on unsafe methods.
+/// requests without a session (bearer tokens, webhooks) carry no ambient credential and
+/// pass, as do routes marked `csrf_exempt`. grpc calls go to tonic and never get here
+pub(crate) async fn csrf_check<B>(
+    State(csrf): State<Csrf>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let exempt = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|path| csrf.exempt.contains(path.as_str()));
+    if exempt {
+        return next.run(request).await;
+    }
+
+    let unsafe_method = !matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    let session = match csrf.sessions.load(request.headers()).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return next.run(request).await,
+        // the handler reports it if it needs the session
+        Err(e) if !unsafe_method => {
+            tracing::warn!("csrf check could not load the session: {e:?}");
+            return next.run(request).await;
+        }
+        Err(e) => return e.into_response(),
+    };
+
+    let token = match session_impl::csrf_token(&session) {
+        Some(token) => token,
+        // sessions from before csrf was enabled
+        None if !unsafe_method => match issue_token(&csrf.sessions, session).await {
+            Ok(token) => token,
+            Err(e) => return e.into_response(),
+        },
+        None => {
+            return Error::CsrfError("session has no csrf token, reload it first").into_response()
+        }
+    };
+    if unsafe_method {
+        let sent = request
+            .headers()
+            .get(define::CSRF_HEADER)
+            .map(|v| v.as_bytes())
+            .unwrap_or_default();
+        if !constant_time_eq(sent, token.as_bytes()) {
+            return Error::CsrfError("missing or invalid csrf token").into_response();
+        }
+    }
+
+    let cookie_sent = CookieJar::from_headers(request.headers())
+        .get(define::CSRF_COOKIE)
+        .is_some_and(|cookie| cookie.value() == token);
+    let mut res = next.run(request).await;
+    // a handler that rotated or ended the session already set the new state
+    let handled = res.headers().contains_key(define::CSRF_HEADER)
+        || res.headers().get_all(header::SET_COOKIE).iter().any(|v| {
+            v.as_bytes()
+                .starts_with(format!("{}=", define::CSRF_COOKIE).as_bytes())
+        });
+    if handled {
+        return res;
+    }
+    if let Ok(value) = HeaderValue::from_str(&token) {
+        res.headers_mut().insert(define::CSRF_HEADER, value);
+    }
+    if !cookie_sent {
+        let cookie = csrf.sessions.cookies().csrf_cookie(&token);
+        if let Ok(value) = HeaderValue::from_str(&cookie.encoded().to_string()) {
+            res.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    res
+}
+
+async fn issue_token(
+    sessions: &SessionService,
+    mut session: Session,
+) -> diagnostics::Result<String> {
+    let token = session_impl::reset_csrf_token(&mut session).map_err(anyhow::Error::from)?;
+    sessions.store().store_session(session).await?;
+    Ok(token)
+}
@@ -1,6 +1,8 @@
 pub(crate) mod tracing;
-pub(crate) mod log;
 pub(crate) mod config;
 pub(crate) mod extractorext;
 pub(crate) mod middleware;
+pub(crate) mod password;
+pub(crate) mod request_id;
+pub(crate) mod shutdown;
 
@@ -0,0 +1,124 @@
+use std::{
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::future::BoxFuture;
+use hyper::{header::HeaderValue, Request, Response};
+use tower::{Layer, Service};
+use tracing::{field, Instrument};
+use uuid::Uuid;
+
+use crate::define;
+
+// incoming ids longer than this are replaced instead of trusted
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// id of the current request, in the request extensions of rest and grpc handlers
+#[derive(Debug, Clone)]
+pub(crate) struct RequestId(pub String);
+
+/// takes `x-request-id` from the client or makes a uuid v4, puts it into the request
+/// (extension and header), runs the request inside a `request` span and echoes the id
+/// in the response. wraps the whole `MultiplexService` so grpc gets the same treatment
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestIdService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: std::fmt::Debug,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let (id, header) = incoming(&req).unwrap_or_else(|| {
+            let id = Uuid::new_v4().to_string();
+            let header = HeaderValue::from_str(&id).expect("uuid is a valid header value");
+            (id, header)
+        });
+        req.headers_mut()
+            .insert(define::REQUEST_ID_HEADER, header.clone());
+        req.extensions_mut().insert(RequestId(id.clone()));
+
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id,
+            method = %req.method(),
+            path = %req.uri().path(),
+            // filled in further down the stack once known
+            route = field::Empty,
+            user_id = field::Empty,
+            status = field::Empty,
+            grpc_status = field::Empty,
+            latency_ms = field::Empty,
+        );
+        let start = Instant::now();
+        let future = span.in_scope(|| self.inner.call(req));
+
+        Box::pin(
+            async move {
+                let span = tracing::Span::current();
+                let result = future.await;
+                span.record("latency_ms", start.elapsed().as_millis() as u64);
+                match result {
+                    Ok(mut res) => {
+                        span.record("status", res.status().as_u16());
+                        // trailers-only grpc errors carry the status in the headers
+                        if let Some(grpc_status) = res
+                            .headers()
+                            .get("grpc-status")
+                            .and_then(|v| v.to_str().ok())
+                        {
+                            span.record("grpc_status", grpc_status);
+                        }
+                        tracing::info!("request finished");
+                        res.headers_mut().insert(define::REQUEST_ID_HEADER, header);
+                        Ok(res)
+                    }
+                    Err(e) => {
+                        tracing::error!("request failed: {e:?}");
+                        Err(e)
+                    }
+                }
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// fills in `user_id` of the `request` span once an extractor knows the caller
+pub(crate) fn record_user_id(user_id: i64) {
+    tracing::Span::current().record("user_id", user_id);
+}
+
+fn incoming<B>(req: &Request<B>) -> Option<(String, HeaderValue)> {
+    let header = req.headers().get(define::REQUEST_ID_HEADER)?;
+    let id = header.to_str().ok()?;
+    let valid = !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b));
+    valid.then(|| (id.to_owned(), header.clone()))
+}
@@ -0,0 +1,117 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+/// shared shutdown trigger. every `ShutdownListener` alive counts as in-flight work,
+/// `drained` resolves once all of them are dropped
+#[derive(Clone, Debug)]
+pub(crate) struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ShutdownListener {
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub(crate) fn new() -> Self {
+        let (tx, _) = watch::channel(false);
+        Self { tx: Arc::new(tx) }
+    }
+
+    pub(crate) fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub(crate) fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    pub(crate) fn subscribe(&self) -> ShutdownListener {
+        ShutdownListener {
+            rx: self.tx.subscribe(),
+        }
+    }
+
+    /// resolves when triggered, does not hold back `drained`
+    pub(crate) fn triggered(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut listener = self.subscribe();
+        async move { listener.recv().await }
+    }
+
+    pub(crate) async fn drained(&self) {
+        self.tx.closed().await
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownListener {
+    pub(crate) async fn recv(&mut self) {
+        // the sender lives in `Shutdown`, an error means it is gone which is a shutdown too
+        let _ = self.rx.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// SIGINT or SIGTERM
+pub(crate) async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("SIGINT received"),
+        _ = terminate => tracing::info!("SIGTERM received"),
+    }
+}
+
+/// runs `server` (already wired with `with_graceful_shutdown(shutdown.triggered())`)
+/// and once shutdown is triggered gives it and every listener `timeout` to finish
+pub(crate) async fn serve_until_shutdown<F>(server: F, shutdown: &Shutdown, timeout: Duration)
+where
+    F: Future<Output = hyper::Result<()>>,
+{
+    let server = async {
+        if let Err(e) = server.await {
+            tracing::error!("server error: {e:?}");
+        }
+    };
+    tokio::pin!(server);
+    let server_finished = tokio::select! {
+        _ = &mut server => true,
+        _ = shutdown.triggered() => false,
+    };
+    if !shutdown.is_triggered() {
+        return;
+    }
+
+    tracing::info!("shutting down, draining connections for up to {timeout:?}");
+    let drain = async {
+        if !server_finished {
+            server.await;
+        }
+        shutdown.drained().await;
+    };
+    match tokio::time::timeout(timeout, drain).await {
+        Ok(_) => tracing::info!("all connections drained"),
+        Err(_) => tracing::warn!("shutdown timeout elapsed, dropping remaining connections"),
+    }
+}
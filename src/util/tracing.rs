@@ -1,12 +1,42 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::{field::Field, Event, Subscriber};
 use tracing_appender::{
     non_blocking::WorkerGuard,
     rolling::{RollingFileAppender, Rotation},
 };
-use tracing_subscriber::{fmt::writer::MakeWriterExt, prelude::__tracing_subscriber_SubscriberExt};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{
+    fmt::{
+        format::{JsonFields, Writer},
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter,
+    },
+    prelude::__tracing_subscriber_SubscriberExt,
+    registry::LookupSpan,
+    reload, EnvFilter, Layer, Registry,
+};
+
+use crate::{
+    diagnostics,
+    util::config::{LogFormat, LogOutputConfig, TracingConfig},
+};
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+pub(crate) type FilterHandle = reload::Handle<EnvFilter, Registry>;
 
+static LOG_LEVELS: OnceLock<LogLevels> = OnceLock::new();
+
+#[allow(clippy::wrong_self_convention)]
 trait ConfigStringToValue {
     fn into_rotation(&self) -> Rotation;
-    fn into_level(&self) -> tracing::Level;
 }
 
 impl ConfigStringToValue for String {
@@ -18,21 +48,9 @@ impl ConfigStringToValue for String {
             _ => Rotation::DAILY,
         }
     }
-
-    fn into_level(&self) -> tracing::Level {
-        match self.as_str() {
-            "TRACE" => tracing::Level::TRACE,
-            "DEBUG" => tracing::Level::DEBUG,
-            "INFO" => tracing::Level::INFO,
-            "WARN" => tracing::Level::WARN,
-            _ => tracing::Level::ERROR,
-        }
-    }
 }
 
-pub(crate) fn init(config: &crate::util::config::TracingConfig) -> anyhow::Result<WorkerGuard> {
-    let app_name = module_path!().split("::").next().unwrap().to_owned();
-
+pub(crate) fn init(config: &TracingConfig) -> anyhow::Result<WorkerGuard> {
     // create non blocking file writer
     let (file_writer, guard) = tracing_appender::non_blocking(RollingFileAppender::new(
         config.rolling_file.rotation.into_rotation(),
@@ -40,53 +58,304 @@ pub(crate) fn init(config: &crate::util::config::TracingConfig) -> anyhow::Resul
         config.rolling_file.file_name_prefix.as_str(),
     ));
 
-    let file_layer = {
-        let app_only = config.rolling_file.app_only;
-        let app_name = app_name.clone();
-        tracing_subscriber::fmt::layer()
-            .with_file(config.rolling_file.with_file)
-            .with_line_number(config.rolling_file.with_line_number)
-            .with_target(config.rolling_file.with_target)
-            .with_ansi(false)
-            .with_writer(
-                file_writer
-                    .with_max_level(config.rolling_file.with_max_level.into_level())
-                    .with_filter(move |meta| {
-                        if app_only {
-                            meta.target().starts_with(app_name.as_str())
-                        } else {
-                            true
-                        }
-                    }),
-            )
-    };
-
-    let console_layer = {
-        let app_only = config.console.app_only;
-        tracing_subscriber::fmt::layer()
-            .with_file(config.console.with_file)
-            .with_line_number(config.console.with_line_number)
-            .with_target(config.console.with_target)
-            .with_ansi(true)
-            .with_writer(
-                std::io::stdout
-                    .with_max_level(config.console.with_max_level.into_level())
-                    .with_filter(move |meta| {
-                        if app_only {
-                            meta.target().starts_with(app_name.as_str())
-                        } else {
-                            true
-                        }
-                    }),
-            )
-    };
+    let (file_layer, file_filter) = output_layer(&config.rolling_file.output, file_writer, false)?;
+    let (console_layer, console_filter) =
+        output_layer(&config.console.output, std::io::stdout, true)?;
 
     tracing::subscriber::set_global_default(
-        tracing_subscriber::registry()
-            .with(file_layer)
-            .with(console_layer),
+        tracing_subscriber::registry().with(vec![file_layer, console_layer]),
     )?;
+    // `log` records (sqlx, redis, ..) go through the same layers and filters. the log side
+    // lets everything through so runtime changes to the directives apply to them as well
+    tracing_log::LogTracer::init()?;
+
+    let _ = LOG_LEVELS.set(LogLevels::new(vec![
+        (
+            "rolling_file",
+            file_filter,
+            config.rolling_file.output.directives(),
+        ),
+        (
+            "console",
+            console_filter,
+            config.console.output.directives(),
+        ),
+    ]));
 
     // hold required for nonblocking
     Ok(guard)
 }
+
+/// the filters installed by `init`, `None` until then
+pub(crate) fn log_levels() -> Option<&'static LogLevels> {
+    LOG_LEVELS.get()
+}
+
+/// fmt layer in the configured format, filtered by the configured directives.
+/// the filter can be swapped at runtime through the returned handle
+pub(crate) fn output_layer<W>(
+    config: &LogOutputConfig,
+    writer: W,
+    ansi: bool,
+) -> anyhow::Result<(BoxedLayer, FilterHandle)>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let (filter, handle) = reload::Layer::new(EnvFilter::try_new(config.directives())?);
+    let layer = tracing_subscriber::fmt::layer()
+        .with_file(config.with_file)
+        .with_line_number(config.with_line_number)
+        .with_target(config.with_target)
+        .with_writer(writer);
+
+    let layer: BoxedLayer = match config.format {
+        LogFormat::Full => layer.with_ansi(ansi).with_filter(filter).boxed(),
+        LogFormat::Compact => layer.compact().with_ansi(ansi).with_filter(filter).boxed(),
+        LogFormat::Pretty => layer.pretty().with_ansi(ansi).with_filter(filter).boxed(),
+        LogFormat::Json => layer
+            .with_ansi(false)
+            .fmt_fields(JsonFields::new())
+            .event_format(FlatJson {
+                with_file: config.with_file,
+                with_line_number: config.with_line_number,
+                with_target: config.with_target,
+            })
+            .with_filter(filter)
+            .boxed(),
+    };
+    Ok((layer, handle))
+}
+
+/// runtime view of the output filters, backs `/admin/log-level`
+#[derive(Clone)]
+pub(crate) struct LogLevels {
+    outputs: Arc<BTreeMap<&'static str, Output>>,
+}
+
+struct Output {
+    handle: FilterHandle,
+    default: String,
+    state: Mutex<OutputState>,
+}
+
+struct OutputState {
+    directives: String,
+    revert_at: Option<Instant>,
+    // bumped on every change so a stale revert does not undo a newer one
+    generation: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct OutputLevel {
+    pub directives: String,
+    pub default: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_in_secs: Option<u64>,
+}
+
+impl LogLevels {
+    /// `(name, handle, directives the output started with)`
+    pub(crate) fn new(outputs: Vec<(&'static str, FilterHandle, String)>) -> Self {
+        let outputs = outputs
+            .into_iter()
+            .map(|(name, handle, directives)| {
+                let output = Output {
+                    handle,
+                    default: directives.clone(),
+                    state: Mutex::new(OutputState {
+                        directives,
+                        revert_at: None,
+                        generation: 0,
+                    }),
+                };
+                (name, output)
+            })
+            .collect();
+        Self {
+            outputs: Arc::new(outputs),
+        }
+    }
+
+    pub(crate) fn current(&self) -> BTreeMap<&'static str, OutputLevel> {
+        self.outputs
+            .iter()
+            .map(|(name, output)| {
+                let state = output.state.lock().unwrap();
+                let level = OutputLevel {
+                    directives: state.directives.clone(),
+                    default: output.default.clone(),
+                    revert_in_secs: state
+                        .revert_at
+                        .map(|at| at.saturating_duration_since(Instant::now()).as_secs()),
+                };
+                (*name, level)
+            })
+            .collect()
+    }
+
+    /// replaces the directives of `output` (every output when `None`). with `revert_after`
+    /// the configured directives come back on their own, needs a tokio runtime
+    pub(crate) fn set(
+        &self,
+        output: Option<&str>,
+        directives: &str,
+        revert_after: Option<Duration>,
+    ) -> diagnostics::Result<()> {
+        EnvFilter::try_new(directives)
+            .map_err(|e| diagnostics::Error::Message(format!("invalid directives: {e}")))?;
+        let names = match output {
+            Some(name) => {
+                let (name, _) = self.outputs.get_key_value(name).ok_or_else(|| {
+                    diagnostics::Error::Message(format!("unknown log output `{name}`"))
+                })?;
+                vec![*name]
+            }
+            None => self.outputs.keys().copied().collect(),
+        };
+
+        for name in names {
+            let generation = self.apply(name, directives, revert_after)?;
+            if let Some(after) = revert_after {
+                let levels = self.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(after).await;
+                    levels.revert(name, generation);
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(
+        &self,
+        name: &str,
+        directives: &str,
+        revert_after: Option<Duration>,
+    ) -> diagnostics::Result<u64> {
+        let output = &self.outputs[name];
+        let mut state = output.state.lock().unwrap();
+        // validated by the caller
+        let filter = EnvFilter::try_new(directives).unwrap_or_default();
+        output
+            .handle
+            .reload(filter)
+            .map_err(|e| diagnostics::Error::Message(format!("unable to reload filter: {e}")))?;
+        state.directives = directives.to_owned();
+        state.revert_at = revert_after.map(|after| Instant::now() + after);
+        state.generation += 1;
+        tracing::info!(output = name, directives, "log level changed");
+        Ok(state.generation)
+    }
+
+    fn revert(&self, name: &str, generation: u64) {
+        let output = &self.outputs[name];
+        if output.state.lock().unwrap().generation != generation {
+            return;
+        }
+        if let Err(e) = self.apply(name, &output.default, None) {
+            tracing::error!("unable to revert log level of {name}: {e:?}");
+        }
+    }
+}
+
+/// one json object per event. fields of every enclosing span (request_id, route, ..)
+/// sit next to the event fields, inner spans win over outer ones and the event over both
+struct FlatJson {
+    with_file: bool,
+    with_line_number: bool,
+    with_target: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        // bridged `log` records carry their file and line as fields
+        let meta = event.normalized_metadata();
+        let meta = meta.as_ref().unwrap_or_else(|| event.metadata());
+        let mut object = Map::new();
+
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        object.insert("timestamp".to_owned(), timestamp.into());
+        object.insert("level".to_owned(), meta.level().as_str().into());
+        if self.with_target {
+            object.insert("target".to_owned(), meta.target().into());
+        }
+        if self.with_file {
+            if let Some(file) = meta.file() {
+                object.insert("file".to_owned(), file.into());
+            }
+        }
+        if self.with_line_number {
+            if let Some(line) = meta.line() {
+                object.insert("line".to_owned(), line.into());
+            }
+        }
+
+        if let Some(scope) = ctx.event_scope() {
+            let mut spans = Vec::new();
+            for span in scope.from_root() {
+                spans.push(Value::from(span.name()));
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<N>>() else {
+                    continue;
+                };
+                // JsonFields keeps span fields as a json object
+                if let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(fields) {
+                    object.extend(fields);
+                }
+            }
+            object.insert("spans".to_owned(), spans.into());
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+
+        let line = serde_json::to_string(&object).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{line}")
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl JsonVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: Value) {
+        // already part of the normalized metadata
+        if !field.name().starts_with("log.") {
+            self.0.insert(field.name().to_owned(), value);
+        }
+    }
+}
+
+impl tracing::field::Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}").into());
+    }
+}
@@ -1,13 +1,18 @@
-use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
         State,
     },
     response::IntoResponse,
-    routing::get,
-    Router,
 };
 use futures::{stream::SplitSink, SinkExt, StreamExt};
 use serde::Deserialize;
@@ -15,20 +20,35 @@ use serde_json::{json, value::RawValue};
 use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
-use crate::{app_state::AppState, diagnostics};
+use crate::{
+    app_state::AppState,
+    diagnostics,
+    router::routing::{get, RouteTable},
+    util::shutdown::ShutdownListener,
+};
 
 type Tx = Arc<Mutex<SplitSink<WebSocket, Message>>>;
 
 #[derive(Clone, Debug)]
 pub(crate) struct PubSubState {
     topics: Arc<RwLock<HashMap<String, Topic>>>,
+    connections: Arc<AtomicUsize>,
 }
 impl PubSubState {
     pub(crate) fn new() -> Self {
         Self {
             topics: Arc::new(RwLock::new(HashMap::default())),
+            connections: Arc::new(AtomicUsize::new(0)),
         }
     }
+
+    pub(crate) fn connection_count(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub(crate) async fn topic_count(&self) -> usize {
+        self.topics.read().await.len()
+    }
 }
 
 #[derive(Debug)]
@@ -91,6 +111,17 @@ where
         .await;
 }
 
+async fn on_shutdown(tx: &'_ Tx) {
+    let _ = tx
+        .lock()
+        .await
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::AWAY,
+            reason: "server shutting down".into(),
+        })))
+        .await;
+}
+
 // async fn pubsub_handler(mut websocket: WebSocket, state: PubSubState) {
 //     let (tx, mut rx) = websocket.split();
 //     while let Some(Ok(message)) = rx.next().await {
@@ -101,11 +132,22 @@ where
 //     tracing::debug!("???");
 // }
 
-async fn pubsub_handler(websocket: WebSocket, state: PubSubState) {
+async fn pubsub_handler(websocket: WebSocket, state: PubSubState, mut shutdown: ShutdownListener) {
     let (tx, mut rx) = websocket.split();
     let tx = Arc::new(Mutex::new(tx));
     let uuid = Uuid::new_v4();
-    while let Some(Ok(message)) = rx.next().await {
+    state.connections.fetch_add(1, Ordering::Relaxed);
+    loop {
+        let message = tokio::select! {
+            message = rx.next() => match message {
+                Some(Ok(message)) => message,
+                _ => break,
+            },
+            _ = shutdown.recv() => {
+                on_shutdown(&tx).await;
+                break;
+            }
+        };
         match message {
             Message::Text(text) => {
                 tracing::debug!(text);
@@ -132,8 +174,8 @@ async fn pubsub_handler(websocket: WebSocket, state: PubSubState) {
                                 .or_insert_with(|| Topic::new(subscribe.topic.to_owned()));
                             let _ = topic
                                 .subscribers
-                                .entry(uuid.clone())
-                                .or_insert_with(|| Subscriber::new(uuid.clone(), tx.clone()));
+                                .entry(uuid)
+                                .or_insert_with(|| Subscriber::new(uuid, tx.clone()));
                         }
                     }
                     "publish" => {
@@ -145,21 +187,18 @@ async fn pubsub_handler(websocket: WebSocket, state: PubSubState) {
                             }
                         };
                         let topics = state.topics.read().await;
-                        match topics.get(publish.topic) {
-                            Some(topic) => {
-                                for (_k, v) in topic.subscribers.iter() {
-                                    v.tx.lock()
-                                        .await
-                                        .send(Message::Text(
-                                            json!({ "topic": publish.topic.to_owned(),
-                                                    "message": publish.message.to_owned() })
-                                            .to_string(),
-                                        ))
-                                        .await
-                                        .unwrap();
-                                }
+                        if let Some(topic) = topics.get(publish.topic) {
+                            for (_k, v) in topic.subscribers.iter() {
+                                v.tx.lock()
+                                    .await
+                                    .send(Message::Text(
+                                        json!({ "topic": publish.topic.to_owned(),
+                                                "message": publish.message.to_owned() })
+                                        .to_string(),
+                                    ))
+                                    .await
+                                    .unwrap();
                             }
-                            _ => {}
                         }
                     }
                     "cancel" => {
@@ -171,14 +210,11 @@ async fn pubsub_handler(websocket: WebSocket, state: PubSubState) {
                             }
                         };
                         let mut topics = state.topics.write().await;
-                        match topics.get_mut(cancel.topic) {
-                            Some(topic) => {
-                                topic.subscribers.remove(&uuid);
-                                if topic.subscribers.is_empty() {
-                                    topics.remove(cancel.topic);
-                                }
+                        if let Some(topic) = topics.get_mut(cancel.topic) {
+                            topic.subscribers.remove(&uuid);
+                            if topic.subscribers.is_empty() {
+                                topics.remove(cancel.topic);
                             }
-                            _ => {}
                         }
                     }
                     _ => {
@@ -197,36 +233,32 @@ async fn pubsub_handler(websocket: WebSocket, state: PubSubState) {
             }
             Message::Ping(_ping) => {
                 let _ = tx.lock().await.send(Message::Pong("Pong".into())).await;
-                ()
             }
             Message::Pong(_pong) => {
                 let _ = tx.lock().await.send(Message::Pong("ping".into())).await;
-                ()
             }
         }
     }
     {
         let mut topics = state.topics.write().await;
         for (_, topic) in topics.iter_mut() {
-            match topic.subscribers.get(&uuid) {
-                Some(_) => {
-                    topic.subscribers.remove(&uuid);
-                }
-                _ => {}
-            }
+            topic.subscribers.remove(&uuid);
         }
         topics.retain(|_, v| !v.subscribers.is_empty())
     }
+    state.connections.fetch_sub(1, Ordering::Relaxed);
 }
 
 async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
     let s = state.pubsub.clone();
-    ws.on_upgrade(move |socket| pubsub_handler(socket, s))
+    // held for the lifetime of the socket so shutdown waits for the close frame
+    let shutdown = state.shutdown.subscribe();
+    ws.on_upgrade(move |socket| pubsub_handler(socket, s, shutdown))
 }
 
-pub(crate) fn router() -> Router<AppState> {
+pub(crate) fn router() -> RouteTable<AppState> {
     //let prefix: String = prefix.into();
     //Router::new().route((prefix + "/").as_str(), get(ws_handler))
     //Router::new().route([path, "/"].join("").as_str(), get(ws_handler))
-    Router::new().route("/ws", get(ws_handler))
+    RouteTable::new().route("/ws", get(ws_handler))
 }